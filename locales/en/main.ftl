@@ -0,0 +1,231 @@
+## Shared
+
+back = &lt;- Back
+language-en = English
+language-sw = Swahili
+
+## Home page
+
+home-title = Home
+home-welcome = Welcome to our Newsletter!
+
+## Signup
+
+confirmation-email-subject = Welcome!
+confirmation-email-text =
+    Welcome to our newsletter!
+    Visit { $confirmation_link } to confirm your subscription.
+confirmation-email-html = Welcome to our newsletter!<br />Click <a href="{ $confirmation_link }">here</a> to confirm your subscription.
+
+signup-name-empty = The name cannot be empty.
+signup-name-too-long = The name cannot be longer than { $max_length } characters.
+signup-name-forbidden-character = The name cannot contain the character '{ $character }'.
+signup-email-invalid = { $email } is not a valid subscriber email.
+signup-email-likely-typo = { $email } looks like a typo, did you mean { $suggestion }?
+signup-email-disposable = Disposable email addresses are not accepted.
+signup-email-no-mail-servers = { $domain } does not accept email.
+signup-challenge-missing = The challenge is missing.
+signup-challenge-invalid = The challenge is malformed or was not issued by us.
+signup-challenge-expired = The challenge has expired.
+signup-challenge-wrong-solution = The challenge solution is incorrect.
+signup-challenge-already-used = The challenge has already been used.
+
+## Subscriber data requests
+
+data-request-sent = If we hold data about this address, we have emailed it a link to access it.
+data-request-email-subject = Your data
+data-request-email-text =
+    You asked for the data we hold about you.
+    Visit { $export_link } to download it.
+    Visit { $erasure_link } to have it erased.
+data-request-email-html = You asked for the data we hold about you.<br />Click <a href="{ $export_link }">here</a> to download it.<br />Click <a href="{ $erasure_link }">here</a> to have it erased.
+erasure-title = Erase your data
+erasure-explanation = This will unsubscribe you and delete all the data we hold about you.
+erasure-submit = Erase my data
+erasure-done = Your data has been erased.
+
+## Login
+
+login-title = Login
+login-username = Username
+login-username-placeholder = Enter Username
+login-password = Password
+login-password-placeholder = Enter Password
+login-submit = Login
+login-failed = Authentication failed
+login-unexpected-error = Something went wrong
+logout-done = You have successfully logged out.
+
+## Admin dashboard
+
+dashboard-title = Admin dashboard
+dashboard-welcome = Welcome { $username }!
+dashboard-actions = Available actions:
+dashboard-subscribers = Subscribers
+dashboard-suppressions = Suppressed addresses
+dashboard-change-password = Change password
+dashboard-api-tokens = Manage API tokens
+dashboard-audit-log = Audit log
+dashboard-logout = Logout
+
+## Change password
+
+password-title = Change Password
+password-current = Current password
+password-current-placeholder = Enter current password
+password-new = New password
+password-new-placeholder = Enter new password
+password-check = Confirm new password
+password-check-placeholder = Type the new password again
+password-submit = Change password
+password-mismatch = You entered two different new passwords - the field values must match.
+password-too-weak = Password must be between 12 and 128 characters long.
+password-incorrect = The current password is incorrect.
+password-changed = Your password has been changed.
+
+## Publish a newsletter issue
+
+newsletter-title = Publish Newsletter Issue
+newsletter-issue-title = Title:
+newsletter-issue-title-placeholder = Enter the issue title
+newsletter-text-content = Plain text content:
+newsletter-text-content-placeholder = Enter the content in plain text
+newsletter-html-content = HTML content:
+newsletter-html-content-placeholder = Enter the content in HTML format
+newsletter-translation = { $language } translation (optional)
+newsletter-submit = Publish
+newsletter-accepted = The newsletter issue has been accepted - emails will go out shortly.
+newsletter-attachments = Attachments (optional):
+newsletter-inline-images = Images to embed, referenced as cid:file-name in the HTML content (optional):
+newsletter-sender = From:
+newsletter-sender-default = Default sender
+newsletter-reply-to = Reply-To (optional):
+newsletter-headers = Extra email headers, as Name: value lines (optional):
+newsletter-tag = Postmark tag (optional):
+newsletter-metadata = Postmark metadata, as key: value lines (optional):
+newsletter-message-stream = Postmark message stream:
+newsletter-subject-lines = Alternative subject lines to A/B test, one per line (optional):
+newsletter-sample-percent = Share of the subscribers to test them on (%):
+newsletter-wait-minutes = Minutes to wait before sending the winner to everybody else:
+newsletter-metric = Pick the winner by:
+newsletter-metric-opens = open rate
+newsletter-metric-clicks = click rate
+newsletter-issues = Published issues:
+subject-test-too-few-subject-lines = An A/B test needs at least two different subject lines.
+subject-test-invalid-sample-percent = The test sample must be between 1% and 100% of the subscribers.
+subject-test-invalid-wait = The wait before sending the winner must be a positive number of minutes.
+subject-test-unknown-metric = Unknown A/B test metric.
+subject-test-translated = Subject lines cannot be A/B tested on translated issues yet.
+attachment-missing-file-name = Attachments need a file name.
+attachment-file-too-large = { $file_name } is larger than the size limit for a single file.
+attachment-too-large-in-total = The attachments of the issue are larger than the size limit.
+attachment-content-type-not-allowed = { $file_name } cannot be attached: { $content_type } files are not allowed.
+attachment-not-an-image = { $file_name } cannot be embedded: it is not an image.
+attachment-duplicate-file-name = Several attachments are named { $file_name }.
+email-options-unknown-sender = { $value } is not a known sender identity.
+email-options-invalid-reply-to = { $value } is not a valid reply-to address.
+email-options-invalid-header = { $value } is not a valid email header.
+email-options-reserved-header = The { $value } header cannot be set on an issue.
+email-options-tag-too-long = The tag of an issue can be at most 1000 characters long.
+email-options-invalid-metadata = { $value } is not valid metadata: keys are at most 20 characters long and values 80.
+email-options-too-much-metadata = An issue can carry at most 10 metadata fields.
+
+## Newsletter issue
+
+issue-title = Issue: { $title }
+issue-published-at = Published at: { $published_at }
+issue-sender = From: { $value }
+issue-reply-to = Reply-To: { $value }
+issue-tag = Postmark tag: { $value }
+issue-message-stream = Postmark message stream: { $value }
+issue-deliveries = Deliveries:
+issue-locale = Language
+issue-pending = Pending
+issue-sent = Sent
+issue-failed = Failed
+issue-skipped = Skipped
+issue-attachments = Attachments:
+issue-attachment-inline = embedded
+issue-subject-test = Subject line A/B test:
+issue-subject-test-pending = The winner will be picked by { $metric } on { $decide_at }, after testing on { $sample_percent }% of the subscribers.
+issue-subject-test-decided = The winner was picked by { $metric } on { $decided_at }.
+issue-subject = Subject line
+issue-opened = Opened
+issue-open-rate = Open rate
+issue-clicked = Clicked
+issue-click-rate = Click rate
+issue-winner = winner
+issue-not-found = There is no such newsletter issue.
+
+## API tokens
+
+api-tokens-title = API tokens
+api-tokens-name = Name
+api-tokens-created-at = Created at
+api-tokens-last-used-at = Last used at
+api-tokens-never-used = never
+api-tokens-revoked-on = revoked on { $revoked_at }
+api-tokens-revoke = Revoke
+api-tokens-new-name = Token name
+api-tokens-new-name-placeholder = What is this token for?
+api-tokens-create = Create token
+api-tokens-empty-name = The token name cannot be empty.
+api-tokens-created = Your new API token is <code>{ $token }</code> - copy it now, it will not be shown again.
+api-tokens-revoked = The API token has been revoked.
+api-tokens-not-revoked = The API token does not exist or has already been revoked.
+
+## Subscribers
+
+subscribers-title = Subscribers
+subscribers-email = Email
+subscribers-name = Name
+subscribers-status = Status
+subscribers-locale = Language
+subscribers-subscribed-at = Subscribed at
+subscriber-title = Subscriber { $email }
+subscriber-consent-history = Consent history:
+subscriber-consent-event = Event
+subscriber-consent-occurred-at = Occurred at
+subscriber-consent-ip-address = IP address
+subscriber-consent-user-agent = User agent
+subscriber-consent-source = Source
+subscriber-consent-wording = Wording
+subscriber-consent-unknown = unknown
+subscriber-export = Export data
+subscriber-erase = Erase data
+subscriber-erased = The subscriber's data has been erased.
+subscriber-not-found = There is no such subscriber.
+
+## Suppressions
+
+suppressions-title = Suppressions
+suppressions-explanation = We never send emails to suppressed addresses. Only a hash of each address is kept.
+suppressions-email-hash = Address hash
+suppressions-reason = Reason
+suppressions-created-at = Created at
+suppressions-remove = Remove
+suppressions-email = Email address
+suppressions-email-placeholder = Enter an email address
+suppressions-add = Suppress
+suppressions-import-emails = Email addresses, one per line
+suppressions-import = Import
+suppressions-unknown-reason = Unknown suppression reason.
+suppressions-added = The email address has been suppressed.
+suppressions-already-added = The email address was already suppressed.
+suppressions-imported = { $n_added } new email address(es) suppressed, { $n_invalid } invalid line(s) skipped.
+suppressions-removed = The suppression has been removed.
+suppressions-not-found = There is no such suppression.
+
+## Audit log
+
+audit-log-title = Audit log
+audit-log-occurred-at = Occurred at
+audit-log-user = User
+audit-log-ip-address = IP address
+audit-log-action = Action
+audit-log-target = Target
+audit-log-any-action = Any action
+audit-log-filter = Filter
+audit-log-older = Older events
+audit-log-unknown = unknown
+audit-log-command-line = { $operator } (command line)
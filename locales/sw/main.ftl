@@ -0,0 +1,231 @@
+## Shared
+
+back = &lt;- Rudi
+language-en = Kiingereza
+language-sw = Kiswahili
+
+## Home page
+
+home-title = Mwanzo
+home-welcome = Karibu kwenye jarida letu!
+
+## Signup
+
+confirmation-email-subject = Karibu!
+confirmation-email-text =
+    Karibu kwenye jarida letu!
+    Tembelea { $confirmation_link } ili kuthibitisha usajili wako.
+confirmation-email-html = Karibu kwenye jarida letu!<br />Bofya <a href="{ $confirmation_link }">hapa</a> ili kuthibitisha usajili wako.
+
+signup-name-empty = Jina haliwezi kuwa tupu.
+signup-name-too-long = Jina haliwezi kuzidi herufi { $max_length }.
+signup-name-forbidden-character = Jina haliwezi kuwa na herufi '{ $character }'.
+signup-email-invalid = { $email } si barua pepe halali.
+signup-email-likely-typo = { $email } inaonekana kuwa na kosa la uchapaji, ulimaanisha { $suggestion }?
+signup-email-disposable = Barua pepe za muda hazikubaliwi.
+signup-email-no-mail-servers = { $domain } haipokei barua pepe.
+signup-challenge-missing = Changamoto haipo.
+signup-challenge-invalid = Changamoto si sahihi au haikutolewa na sisi.
+signup-challenge-expired = Muda wa changamoto umekwisha.
+signup-challenge-wrong-solution = Jibu la changamoto si sahihi.
+signup-challenge-already-used = Changamoto imeshatumika.
+
+## Subscriber data requests
+
+data-request-sent = Ikiwa tuna taarifa kuhusu anwani hii, tumeitumia kiungo cha kuzifikia.
+data-request-email-subject = Taarifa zako
+data-request-email-text =
+    Uliomba taarifa tulizonazo kukuhusu.
+    Tembelea { $export_link } ili kuzipakua.
+    Tembelea { $erasure_link } ili zifutwe.
+data-request-email-html = Uliomba taarifa tulizonazo kukuhusu.<br />Bofya <a href="{ $export_link }">hapa</a> ili kuzipakua.<br />Bofya <a href="{ $erasure_link }">hapa</a> ili zifutwe.
+erasure-title = Futa taarifa zako
+erasure-explanation = Hatua hii itasitisha usajili wako na kufuta taarifa zote tulizonazo kukuhusu.
+erasure-submit = Futa taarifa zangu
+erasure-done = Taarifa zako zimefutwa.
+
+## Login
+
+login-title = Ingia
+login-username = Jina la mtumiaji
+login-username-placeholder = Weka jina la mtumiaji
+login-password = Nenosiri
+login-password-placeholder = Weka nenosiri
+login-submit = Ingia
+login-failed = Uthibitishaji umeshindikana
+login-unexpected-error = Hitilafu imetokea
+logout-done = Umetoka kwa mafanikio.
+
+## Admin dashboard
+
+dashboard-title = Dashibodi ya msimamizi
+dashboard-welcome = Karibu { $username }!
+dashboard-actions = Vitendo vinavyopatikana:
+dashboard-subscribers = Wanachama
+dashboard-suppressions = Anwani zilizozuiwa
+dashboard-change-password = Badilisha nenosiri
+dashboard-api-tokens = Simamia tokeni za API
+dashboard-audit-log = Kumbukumbu ya ukaguzi
+dashboard-logout = Toka
+
+## Change password
+
+password-title = Badilisha Nenosiri
+password-current = Nenosiri la sasa
+password-current-placeholder = Weka nenosiri la sasa
+password-new = Nenosiri jipya
+password-new-placeholder = Weka nenosiri jipya
+password-check = Thibitisha nenosiri jipya
+password-check-placeholder = Andika nenosiri jipya tena
+password-submit = Badilisha nenosiri
+password-mismatch = Umeweka manenosiri mawili tofauti - sehemu zote mbili lazima zilingane.
+password-too-weak = Nenosiri lazima liwe na herufi kati ya 12 na 128.
+password-incorrect = Nenosiri la sasa si sahihi.
+password-changed = Nenosiri lako limebadilishwa.
+
+## Publish a newsletter issue
+
+newsletter-title = Chapisha Toleo la Jarida
+newsletter-issue-title = Kichwa:
+newsletter-issue-title-placeholder = Weka kichwa cha toleo
+newsletter-text-content = Maudhui kwa maandishi matupu:
+newsletter-text-content-placeholder = Weka maudhui kwa maandishi matupu
+newsletter-html-content = Maudhui kwa HTML:
+newsletter-html-content-placeholder = Weka maudhui kwa muundo wa HTML
+newsletter-translation = Tafsiri ya { $language } (si lazima)
+newsletter-submit = Chapisha
+newsletter-accepted = Toleo la jarida limepokelewa - barua pepe zitatumwa hivi punde.
+newsletter-attachments = Viambatisho (si lazima):
+newsletter-inline-images = Picha za kupachika, zinazorejelewa kama cid:jina-la-faili katika maudhui ya HTML (si lazima):
+newsletter-sender = Kutoka:
+newsletter-sender-default = Mtumaji wa kawaida
+newsletter-reply-to = Jibu kwa (si lazima):
+newsletter-headers = Vichwa vya ziada vya barua pepe, kama mistari ya Jina: thamani (si lazima):
+newsletter-tag = Lebo ya Postmark (si lazima):
+newsletter-metadata = Metadata ya Postmark, kama mistari ya ufunguo: thamani (si lazima):
+newsletter-message-stream = Mkondo wa ujumbe wa Postmark:
+newsletter-subject-lines = Vichwa mbadala vya kujaribu (A/B), kimoja kwa kila mstari (si lazima):
+newsletter-sample-percent = Sehemu ya wanachama wa kujaribu (%):
+newsletter-wait-minutes = Dakika za kusubiri kabla ya kutuma kichwa bora kwa wengine wote:
+newsletter-metric = Chagua kichwa bora kwa:
+newsletter-metric-opens = kiwango cha kufunguliwa
+newsletter-metric-clicks = kiwango cha kubofya
+newsletter-issues = Matoleo yaliyochapishwa:
+subject-test-too-few-subject-lines = Jaribio la A/B linahitaji angalau vichwa viwili tofauti.
+subject-test-invalid-sample-percent = Sampuli ya jaribio lazima iwe kati ya 1% na 100% ya wanachama.
+subject-test-invalid-wait = Muda wa kusubiri kabla ya kutuma kichwa bora lazima uwe idadi chanya ya dakika.
+subject-test-unknown-metric = Kipimo cha jaribio la A/B hakijulikani.
+subject-test-translated = Vichwa vya matoleo yaliyotafsiriwa haviwezi kujaribiwa bado.
+attachment-missing-file-name = Viambatisho vinahitaji jina la faili.
+attachment-file-too-large = { $file_name } ni kubwa kuliko kikomo cha ukubwa wa faili moja.
+attachment-too-large-in-total = Viambatisho vya toleo ni vikubwa kuliko kikomo cha ukubwa.
+attachment-content-type-not-allowed = { $file_name } haiwezi kuambatishwa: faili za { $content_type } haziruhusiwi.
+attachment-not-an-image = { $file_name } haiwezi kupachikwa: si picha.
+attachment-duplicate-file-name = Viambatisho kadhaa vinaitwa { $file_name }.
+email-options-unknown-sender = { $value } si mtumaji anayejulikana.
+email-options-invalid-reply-to = { $value } si anwani halali ya kujibu.
+email-options-invalid-header = { $value } si kichwa halali cha barua pepe.
+email-options-reserved-header = Kichwa cha { $value } hakiwezi kuwekwa kwenye toleo.
+email-options-tag-too-long = Lebo ya toleo haiwezi kuzidi herufi 1000.
+email-options-invalid-metadata = { $value } si metadata halali: funguo zisizidi herufi 20 na thamani 80.
+email-options-too-much-metadata = Toleo haliwezi kubeba zaidi ya sehemu 10 za metadata.
+
+## Newsletter issue
+
+issue-title = Toleo: { $title }
+issue-published-at = Lilichapishwa: { $published_at }
+issue-sender = Kutoka: { $value }
+issue-reply-to = Jibu kwa: { $value }
+issue-tag = Lebo ya Postmark: { $value }
+issue-message-stream = Mkondo wa ujumbe wa Postmark: { $value }
+issue-deliveries = Uwasilishaji:
+issue-locale = Lugha
+issue-pending = Inasubiri
+issue-sent = Zimetumwa
+issue-failed = Zimeshindikana
+issue-skipped = Zimerukwa
+issue-attachments = Viambatisho:
+issue-attachment-inline = imepachikwa
+issue-subject-test = Jaribio la A/B la kichwa:
+issue-subject-test-pending = Kichwa bora kitachaguliwa kwa { $metric } tarehe { $decide_at }, baada ya kujaribu kwa { $sample_percent }% ya wanachama.
+issue-subject-test-decided = Kichwa bora kilichaguliwa kwa { $metric } tarehe { $decided_at }.
+issue-subject = Kichwa
+issue-opened = Yamefunguliwa
+issue-open-rate = Kiwango cha kufunguliwa
+issue-clicked = Yamebofywa
+issue-click-rate = Kiwango cha kubofya
+issue-winner = bora
+issue-not-found = Hakuna toleo kama hilo.
+
+## API tokens
+
+api-tokens-title = Tokeni za API
+api-tokens-name = Jina
+api-tokens-created-at = Iliundwa
+api-tokens-last-used-at = Ilitumika mwisho
+api-tokens-never-used = haijawahi
+api-tokens-revoked-on = ilibatilishwa { $revoked_at }
+api-tokens-revoke = Batilisha
+api-tokens-new-name = Jina la tokeni
+api-tokens-new-name-placeholder = Tokeni hii ni ya nini?
+api-tokens-create = Unda tokeni
+api-tokens-empty-name = Jina la tokeni haliwezi kuwa tupu.
+api-tokens-created = Tokeni yako mpya ya API ni <code>{ $token }</code> - inakili sasa, haitaonyeshwa tena.
+api-tokens-revoked = Tokeni ya API imebatilishwa.
+api-tokens-not-revoked = Tokeni ya API haipo au imeshabatilishwa.
+
+## Subscribers
+
+subscribers-title = Wanachama
+subscribers-email = Barua pepe
+subscribers-name = Jina
+subscribers-status = Hali
+subscribers-locale = Lugha
+subscribers-subscribed-at = Alijiunga
+subscriber-title = Mwanachama { $email }
+subscriber-consent-history = Historia ya ridhaa:
+subscriber-consent-event = Tukio
+subscriber-consent-occurred-at = Lilitokea
+subscriber-consent-ip-address = Anwani ya IP
+subscriber-consent-user-agent = Kivinjari
+subscriber-consent-source = Chanzo
+subscriber-consent-wording = Maneno
+subscriber-consent-unknown = haijulikani
+subscriber-export = Hamisha taarifa
+subscriber-erase = Futa taarifa
+subscriber-erased = Taarifa za mwanachama zimefutwa.
+subscriber-not-found = Hakuna mwanachama huyo.
+
+## Suppressions
+
+suppressions-title = Anwani zilizozuiwa
+suppressions-explanation = Hatutumi barua pepe kamwe kwa anwani zilizozuiwa. Tunahifadhi hashi ya kila anwani pekee.
+suppressions-email-hash = Hashi ya anwani
+suppressions-reason = Sababu
+suppressions-created-at = Iliongezwa
+suppressions-remove = Ondoa
+suppressions-email = Anwani ya barua pepe
+suppressions-email-placeholder = Weka anwani ya barua pepe
+suppressions-add = Zuia
+suppressions-import-emails = Anwani za barua pepe, moja kwa kila mstari
+suppressions-import = Ingiza
+suppressions-unknown-reason = Sababu ya kuzuia haijulikani.
+suppressions-added = Anwani ya barua pepe imezuiwa.
+suppressions-already-added = Anwani ya barua pepe ilikuwa imeshazuiwa.
+suppressions-imported = Anwani mpya { $n_added } zimezuiwa, mistari { $n_invalid } isiyo sahihi imerukwa.
+suppressions-removed = Uzuiaji umeondolewa.
+suppressions-not-found = Hakuna uzuiaji huo.
+
+## Audit log
+
+audit-log-title = Kumbukumbu ya ukaguzi
+audit-log-occurred-at = Ilitokea
+audit-log-user = Mtumiaji
+audit-log-ip-address = Anwani ya IP
+audit-log-action = Kitendo
+audit-log-target = Kilicholengwa
+audit-log-any-action = Kitendo chochote
+audit-log-filter = Chuja
+audit-log-older = Matukio ya zamani zaidi
+audit-log-unknown = haijulikani
+audit-log-command-line = { $operator } (mstari wa amri)
@@ -0,0 +1,9 @@
+CREATE TABLE api_tokens (
+    api_token_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users(user_id),
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL,
+    last_used_at timestamptz NULL,
+    revoked_at timestamptz NULL
+);
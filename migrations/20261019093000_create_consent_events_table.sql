@@ -0,0 +1,22 @@
+CREATE TABLE consent_events (
+    consent_event_id uuid PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    event_type TEXT NOT NULL,
+    occurred_at timestamptz NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    source TEXT NOT NULL,
+    consent_wording TEXT NOT NULL
+);
+CREATE INDEX consent_events_subscriber_id_idx ON consent_events (subscriber_id);
+
+-- Consent events are an audit record: they can be added, never rewritten.
+CREATE FUNCTION reject_consent_event_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'consent_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER consent_events_are_append_only
+    BEFORE UPDATE OR DELETE ON consent_events
+    FOR EACH ROW EXECUTE FUNCTION reject_consent_event_changes();
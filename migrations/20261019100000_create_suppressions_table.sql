@@ -0,0 +1,7 @@
+-- Addresses we must never email again. Only a hash of the normalized address
+-- is kept, so that erased subscribers do not linger here in clear text.
+CREATE TABLE suppressions (
+    email_hash TEXT PRIMARY KEY,
+    reason TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
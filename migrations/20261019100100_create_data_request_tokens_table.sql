@@ -0,0 +1,5 @@
+CREATE TABLE data_request_tokens (
+    data_request_token TEXT PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    created_at timestamptz NOT NULL
+);
@@ -0,0 +1,11 @@
+-- Consent events stay append-only, except when a subscriber exercises their
+-- right to erasure: the erasure transaction opts in with
+-- `SET LOCAL prod_craft.erasure = 'on'`.
+CREATE OR REPLACE FUNCTION reject_consent_event_changes() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' AND current_setting('prod_craft.erasure', true) = 'on' THEN
+        RETURN OLD;
+    END IF;
+    RAISE EXCEPTION 'consent_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
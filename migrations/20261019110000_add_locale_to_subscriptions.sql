@@ -0,0 +1,3 @@
+-- The language subscribers are written to in, as negotiated when they
+-- signed up. Existing subscribers all signed up in English.
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
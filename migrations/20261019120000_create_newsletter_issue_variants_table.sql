@@ -0,0 +1,31 @@
+-- Each issue holds one variant per locale it was written in, and names the
+-- one to send to subscribers whose locale it was not translated to.
+CREATE TABLE newsletter_issue_variants (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    locale TEXT NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    n_sent INTEGER NOT NULL DEFAULT 0,
+    n_failed INTEGER NOT NULL DEFAULT 0,
+    n_skipped INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (newsletter_issue_id, locale)
+);
+
+ALTER TABLE newsletter_issues ADD COLUMN default_locale TEXT NOT NULL DEFAULT 'en';
+ALTER TABLE newsletter_issues ALTER COLUMN default_locale DROP DEFAULT;
+
+INSERT INTO newsletter_issue_variants (
+    newsletter_issue_id, locale, title, text_content, html_content
+)
+SELECT newsletter_issue_id, default_locale, title, text_content, html_content
+FROM newsletter_issues;
+
+ALTER TABLE newsletter_issues
+    DROP COLUMN title,
+    DROP COLUMN text_content,
+    DROP COLUMN html_content;
+
+-- The variant each subscriber gets is picked when the issue is published.
+ALTER TABLE issue_delivery_queue ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
+ALTER TABLE issue_delivery_queue ALTER COLUMN locale DROP DEFAULT;
@@ -0,0 +1,38 @@
+-- A/B tests of the subject line of a newsletter issue.
+CREATE TABLE subject_tests (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    -- What the winner is picked by: 'opens' or 'clicks'.
+    metric TEXT NOT NULL,
+    sample_percent INTEGER NOT NULL,
+    decide_at timestamptz NOT NULL,
+    winner INTEGER,
+    decided_at timestamptz,
+    PRIMARY KEY (newsletter_issue_id)
+);
+
+-- Subject line 0 is the title of the issue.
+CREATE TABLE subject_test_variants (
+    newsletter_issue_id uuid NOT NULL REFERENCES subject_tests (newsletter_issue_id),
+    variant_index INTEGER NOT NULL,
+    subject TEXT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, variant_index)
+);
+
+-- One row per email sent to the sample. It is not linked to the subscriber
+-- on purpose: we only need the rates.
+CREATE TABLE subject_test_deliveries (
+    tracking_id uuid NOT NULL,
+    newsletter_issue_id uuid NOT NULL,
+    variant_index INTEGER NOT NULL,
+    sent_at timestamptz NOT NULL,
+    opened_at timestamptz,
+    clicked_at timestamptz,
+    PRIMARY KEY (tracking_id),
+    FOREIGN KEY (newsletter_issue_id, variant_index)
+        REFERENCES subject_test_variants (newsletter_issue_id, variant_index)
+);
+
+-- Deliveries to the sample carry the subject line they were assigned, the
+-- rest of the list is held back until the winner is known.
+ALTER TABLE issue_delivery_queue ADD COLUMN subject_variant INTEGER;
+ALTER TABLE issue_delivery_queue ADD COLUMN held BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,13 @@
+-- Files sent along with a newsletter issue. Their content is kept in a blob
+-- store, under `<newsletter_issue_id>/<attachment_id>`.
+CREATE TABLE newsletter_issue_attachments (
+    attachment_id uuid NOT NULL,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    file_name TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    -- Embedded in the HTML content, as `cid:<file_name>`.
+    inline BOOLEAN NOT NULL,
+    PRIMARY KEY (attachment_id),
+    UNIQUE (newsletter_issue_id, file_name)
+);
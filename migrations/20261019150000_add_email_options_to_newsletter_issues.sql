@@ -0,0 +1,11 @@
+-- How the emails of an issue are sent. The sender identity is copied from the
+-- configuration when the issue is published.
+ALTER TABLE newsletter_issues
+    ADD COLUMN sender_name TEXT,
+    ADD COLUMN sender_email TEXT,
+    ADD COLUMN reply_to TEXT,
+    -- An array of `{"Name": ..., "Value": ...}` objects.
+    ADD COLUMN headers JSONB NOT NULL DEFAULT '[]',
+    ADD COLUMN tag TEXT,
+    ADD COLUMN metadata JSONB NOT NULL DEFAULT '{}',
+    ADD COLUMN message_stream TEXT;
@@ -0,0 +1,2 @@
+-- Lets expired keys be found without scanning the whole table
+CREATE INDEX idempotency_created_at_idx ON idempotency (created_at);
@@ -0,0 +1,3 @@
+-- A hash of the payload of the request, to reject a key being reused for a
+-- different request. Unset for the keys saved before it was added.
+ALTER TABLE idempotency ADD COLUMN request_fingerprint TEXT NULL;
@@ -0,0 +1,8 @@
+-- Keys are unique per scope: a user, or the client of anonymous requests.
+-- Anonymous keys have no user.
+ALTER TABLE idempotency ADD COLUMN scope TEXT NULL;
+UPDATE idempotency SET scope = 'user:' || user_id;
+ALTER TABLE idempotency ALTER COLUMN scope SET NOT NULL;
+ALTER TABLE idempotency DROP CONSTRAINT idempotency_pkey;
+ALTER TABLE idempotency ADD PRIMARY KEY (scope, idempotency_key);
+ALTER TABLE idempotency ALTER COLUMN user_id DROP NOT NULL;
@@ -0,0 +1,21 @@
+CREATE TABLE audit_log (
+    audit_event_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    occurred_at timestamptz NOT NULL,
+    ip_address TEXT NULL,
+    action TEXT NOT NULL,
+    -- What the action was applied to, e.g. `newsletter_issue:<id>`
+    target TEXT NULL
+);
+CREATE INDEX audit_log_occurred_at_idx ON audit_log (occurred_at);
+
+-- The audit log can be added to, never rewritten.
+CREATE FUNCTION reject_audit_log_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_is_append_only
+    BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_log_changes();
@@ -0,0 +1,61 @@
+-- Addresses that only differ in case reach the same mailbox. Before signups
+-- were normalized, the same person could subscribe more than once: keep one
+-- subscriber per address, a confirmed one if any, then the oldest, and hand
+-- it what belonged to the others.
+CREATE TEMPORARY TABLE duplicate_subscribers AS
+SELECT s.id, s.email, survivor.id AS survivor_id, survivor.email AS survivor_email
+FROM (
+    SELECT
+        id,
+        email,
+        first_value(id) OVER (
+            PARTITION BY lower(email)
+            ORDER BY status = 'confirmed' DESC, subscribed_at, id
+        ) AS survivor_id
+    FROM subscriptions
+) s
+JOIN subscriptions survivor ON survivor.id = s.survivor_id
+WHERE s.id <> s.survivor_id;
+
+-- The consent history of the merged subscribers is kept, not rewritten.
+ALTER TABLE consent_events DISABLE TRIGGER consent_events_are_append_only;
+UPDATE consent_events c
+SET subscriber_id = d.survivor_id
+FROM duplicate_subscribers d
+WHERE c.subscriber_id = d.id;
+ALTER TABLE consent_events ENABLE TRIGGER consent_events_are_append_only;
+
+UPDATE subscription_tokens t
+SET subscriber_id = d.survivor_id
+FROM duplicate_subscribers d
+WHERE t.subscriber_id = d.id;
+
+UPDATE data_request_tokens t
+SET subscriber_id = d.survivor_id
+FROM duplicate_subscribers d
+WHERE t.subscriber_id = d.id;
+
+-- An issue is delivered once per mailbox.
+DELETE FROM issue_delivery_queue q
+USING duplicate_subscribers d
+WHERE q.subscriber_email = d.email
+    AND EXISTS (
+        SELECT 1
+        FROM issue_delivery_queue other
+        WHERE other.newsletter_issue_id = q.newsletter_issue_id
+            AND lower(other.subscriber_email) = lower(d.email)
+            AND other.ctid <> q.ctid
+            AND (other.subscriber_email = d.survivor_email OR other.ctid < q.ctid)
+    );
+UPDATE issue_delivery_queue q
+SET subscriber_email = d.survivor_email
+FROM duplicate_subscribers d
+WHERE q.subscriber_email = d.email;
+
+DELETE FROM subscriptions s
+USING duplicate_subscribers d
+WHERE s.id = d.id;
+
+DROP TABLE duplicate_subscribers;
+
+CREATE UNIQUE INDEX subscriptions_lower_email_idx ON subscriptions (lower(email));
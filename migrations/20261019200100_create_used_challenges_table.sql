@@ -0,0 +1,7 @@
+-- Solved signup challenges, kept until they expire so that they cannot be
+-- replayed, whichever replica they are sent to.
+CREATE TABLE used_challenges (
+    challenge TEXT PRIMARY KEY,
+    expires_at timestamptz NOT NULL
+);
+CREATE INDEX used_challenges_expires_at_idx ON used_challenges (expires_at);
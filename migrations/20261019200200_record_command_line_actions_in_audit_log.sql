@@ -0,0 +1,6 @@
+-- Commands run from the command line are done by whoever runs them, not by a
+-- user of the application.
+ALTER TABLE audit_log ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE audit_log ADD COLUMN operator TEXT NULL;
+ALTER TABLE audit_log ADD CONSTRAINT audit_log_has_one_actor
+    CHECK ((user_id IS NULL) <> (operator IS NULL));
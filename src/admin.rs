@@ -0,0 +1,439 @@
+//! Operational tasks for on-call engineers, run from the command line
+//! against the configured database, see `prod_craft admin --help`.
+//!
+//! Every command that changes something is recorded in the audit log, along
+//! with the operator running it.
+use crate::audit::{
+    record_command_line_audit_event,
+    IDEMPOTENCY_PURGED,
+    NEWSLETTER_ISSUE_REQUEUED,
+    PASSWORD_RESET,
+    SUBSCRIBER_CONFIRMED,
+    USER_CREATED,
+};
+use crate::authentication::{
+    change_password,
+    create_user,
+};
+use crate::configuration::Settings;
+use crate::consent::ConsentContext;
+use crate::idempotency::delete_expired_keys;
+use crate::routes::{
+    confirm_subscriber_and_record_consent,
+    enqueue_delivery_tasks,
+};
+use crate::startup::get_connection_pool;
+use anyhow::Context;
+use secrecy::Secret;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::io::BufRead;
+use std::time::Duration;
+use tracing::Span;
+use uuid::Uuid;
+
+/// What consent events recorded from the command line are attributed to.
+const CONSENT_SOURCE: &str = "admin-cli";
+
+#[derive(clap::Args)]
+pub struct AdminArgs {
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+    #[command(subcommand)]
+    pub command: AdminCommand,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum AdminCommand {
+    /// Create an admin user. The password is read from standard input.
+    CreateUser {
+        #[arg(long)]
+        username: String,
+    },
+    /// Set the password of a user. The password is read from standard input.
+    ResetPassword {
+        #[arg(long)]
+        username: String,
+    },
+    /// Confirm a pending subscriber, e.g. one who lost their confirmation
+    /// email. They are recorded as agreeing to the wording they signed up
+    /// with.
+    ConfirmSubscriber {
+        #[arg(long)]
+        email: String,
+    },
+    /// Queue an issue again for every confirmed subscriber it is not already
+    /// queued for.
+    RequeueIssue {
+        #[arg(long)]
+        issue_id: Uuid,
+        /// Deliveries are not recorded per subscriber, so the issue goes
+        /// again to those who got it already. Required, to confirm that.
+        #[arg(long)]
+        all: bool,
+    },
+    /// Delete the saved responses of idempotent requests.
+    PurgeIdempotency {
+        /// Only the responses saved longer ago than this are deleted.
+        #[arg(long)]
+        older_than_hours: u32,
+        /// Count what would be deleted without deleting it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+impl AdminCommand {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::CreateUser { .. } => "create-user",
+            Self::ResetPassword { .. } => "reset-password",
+            Self::ConfirmSubscriber { .. } => "confirm-subscriber",
+            Self::RequeueIssue { .. } => "requeue-issue",
+            Self::PurgeIdempotency { .. } => "purge-idempotency",
+        }
+    }
+}
+
+/// The outcome of a command, as rows of a table.
+#[derive(Debug)]
+pub struct Report {
+    columns: Vec<&'static str>,
+    rows: Vec<Vec<Value>>,
+}
+
+impl Report {
+    fn single(fields: Vec<(&'static str, Value)>) -> Self {
+        let (columns, row) = fields.into_iter().unzip();
+        Self {
+            columns,
+            rows: vec![row],
+        }
+    }
+
+    /// The value in the first row, for tests.
+    pub fn get(&self, column: &str) -> Option<&Value> {
+        let index = self.columns.iter().position(|c| *c == column)?;
+        self.rows.first().map(|row| &row[index])
+    }
+
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Table => self.render_table(),
+            OutputFormat::Json => {
+                let rows: Vec<serde_json::Map<String, Value>> = self
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        self.columns
+                            .iter()
+                            .map(|c| c.to_string())
+                            .zip(row.iter().cloned())
+                            .collect()
+                    })
+                    .collect();
+                format!("{}\n", serde_json::to_string_pretty(&rows).unwrap())
+            }
+        }
+    }
+
+    fn render_table(&self) -> String {
+        let cells: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|value| match value {
+                        Value::String(s) => s.clone(),
+                        Value::Null => String::new(),
+                        value => value.to_string(),
+                    })
+                    .collect()
+            })
+            .collect();
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                cells
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain([column.len()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        let mut table = String::new();
+        let headers = self.columns.iter().map(|c| c.to_string()).collect();
+        for row in std::iter::once(&headers).chain(&cells) {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            table.push_str(line.trim_end());
+            table.push('\n');
+        }
+        table
+    }
+}
+
+/// Runs `command` against the configured database, reading the passwords it
+/// needs from `input`.
+#[tracing::instrument(
+    name = "Run an admin command",
+    skip_all,
+    fields(
+        command = command.name(),
+        operator = %operator,
+        audit_target = tracing::field::Empty
+    )
+)]
+pub async fn execute(
+    command: AdminCommand,
+    operator: &str,
+    configuration: &Settings,
+    input: &mut dyn BufRead,
+) -> Result<Report, anyhow::Error> {
+    let pool = &get_connection_pool(&configuration.database);
+    let report = match command {
+        AdminCommand::CreateUser { username } => {
+            let password = read_password(input)?;
+            let mut transaction = pool.begin().await?;
+            let user_id = create_user(&username, password, transaction.as_mut()).await?;
+            let target = format!("user:{user_id}");
+            record_command_line_audit_event(
+                transaction.as_mut(),
+                operator,
+                USER_CREATED,
+                Some(&target),
+            )
+            .await?;
+            transaction.commit().await?;
+            Span::current().record("audit_target", &target);
+            Report::single(vec![
+                ("user_id", user_id.to_string().into()),
+                ("username", username.into()),
+            ])
+        }
+        AdminCommand::ResetPassword { username } => {
+            let password = read_password(input)?;
+            let user_id = get_user_id(pool, &username)
+                .await?
+                .with_context(|| format!("There is no user named {username}."))?;
+            let mut transaction = pool.begin().await?;
+            change_password(user_id, password, transaction.as_mut()).await?;
+            let target = format!("user:{user_id}");
+            record_command_line_audit_event(
+                transaction.as_mut(),
+                operator,
+                PASSWORD_RESET,
+                Some(&target),
+            )
+            .await?;
+            transaction.commit().await?;
+            Span::current().record("audit_target", &target);
+            Report::single(vec![
+                ("user_id", user_id.to_string().into()),
+                ("username", username.into()),
+            ])
+        }
+        AdminCommand::ConfirmSubscriber { email } => {
+            let subscriber = get_subscriber(pool, &email)
+                .await?
+                .with_context(|| format!("There is no subscriber with the address {email}."))?;
+            let consent_context = ConsentContext {
+                ip_address: None,
+                user_agent: None,
+                source: CONSENT_SOURCE.into(),
+            };
+            let mut transaction = pool.begin().await?;
+            confirm_subscriber_and_record_consent(
+                &mut transaction,
+                subscriber.id,
+                &consent_context,
+                &configuration.signup.consent_wording,
+            )
+            .await
+            .context("Failed to confirm the subscriber.")?;
+            let target = format!("subscriber:{}", subscriber.id);
+            record_command_line_audit_event(
+                transaction.as_mut(),
+                operator,
+                SUBSCRIBER_CONFIRMED,
+                Some(&target),
+            )
+            .await?;
+            transaction.commit().await?;
+            Span::current().record("audit_target", &target);
+            Report::single(vec![
+                ("subscriber_id", subscriber.id.to_string().into()),
+                ("email", email.into()),
+                ("previous_status", subscriber.status.into()),
+                ("status", "confirmed".into()),
+            ])
+        }
+        AdminCommand::RequeueIssue { issue_id, all } => {
+            anyhow::ensure!(
+                all,
+                "Requeueing sends the issue again to every confirmed subscriber it is not queued \
+                 for, including those who got it already. Pass --all to go ahead."
+            );
+            let n_queued = requeue_issue(pool, operator, issue_id).await?;
+            Span::current().record("audit_target", format!("newsletter_issue:{issue_id}"));
+            Report::single(vec![
+                ("newsletter_issue_id", issue_id.to_string().into()),
+                ("n_queued", n_queued.into()),
+            ])
+        }
+        AdminCommand::PurgeIdempotency {
+            older_than_hours,
+            dry_run,
+        } => {
+            let n_deleted = purge_idempotency(
+                pool,
+                older_than_hours,
+                dry_run,
+                configuration.idempotency.cleanup_batch_size,
+            )
+            .await?;
+            if !dry_run {
+                record_command_line_audit_event(pool, operator, IDEMPOTENCY_PURGED, None).await?;
+            }
+            Report::single(vec![
+                ("older_than_hours", older_than_hours.into()),
+                ("dry_run", dry_run.into()),
+                ("n_deleted", n_deleted.into()),
+            ])
+        }
+    };
+    // The report is not logged: it can hold usernames and email addresses.
+    tracing::info!("Ran an admin command.");
+    Ok(report)
+}
+
+/// The first line of `input`.
+fn read_password(input: &mut dyn BufRead) -> Result<Secret<String>, anyhow::Error> {
+    let mut password = String::new();
+    input
+        .read_line(&mut password)
+        .context("Failed to read the password.")?;
+    let password = password.trim_end_matches(['\r', '\n']).to_owned();
+    anyhow::ensure!(!password.is_empty(), "The password cannot be empty.");
+    Ok(Secret::new(password))
+}
+
+async fn get_user_id(pool: &PgPool, username: &str) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!("SELECT user_id FROM users WHERE username = $1", username)
+        .fetch_optional(pool)
+        .await
+}
+
+struct Subscriber {
+    id: Uuid,
+    status: String,
+}
+
+async fn get_subscriber(pool: &PgPool, email: &str) -> Result<Option<Subscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        "SELECT id, status FROM subscriptions WHERE lower(email) = lower($1)",
+        email
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Queued like when the issue was published, subject line test included.
+#[tracing::instrument(skip(pool))]
+async fn requeue_issue(
+    pool: &PgPool,
+    operator: &str,
+    newsletter_issue_id: Uuid,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let n_queued = enqueue_delivery_tasks(&mut transaction, newsletter_issue_id)
+        .await
+        .context("Failed to queue the issue.")?;
+    let target = format!("newsletter_issue:{newsletter_issue_id}");
+    record_command_line_audit_event(
+        transaction.as_mut(),
+        operator,
+        NEWSLETTER_ISSUE_REQUEUED,
+        Some(&target),
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(n_queued)
+}
+
+/// Like the periodic cleanup, with a retention of `older_than_hours`.
+#[tracing::instrument(skip(pool))]
+async fn purge_idempotency(
+    pool: &PgPool,
+    older_than_hours: u32,
+    dry_run: bool,
+    batch_size: i64,
+) -> Result<u64, anyhow::Error> {
+    let older_than = Duration::from_secs(u64::from(older_than_hours) * 60 * 60);
+    if dry_run {
+        let n = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "n!" FROM idempotency
+            WHERE created_at < now() - make_interval(secs => $1)
+            "#,
+            older_than.as_secs_f64()
+        )
+        .fetch_one(pool)
+        .await?;
+        return Ok(n as u64);
+    }
+    delete_expired_keys(pool, older_than, batch_size).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        OutputFormat,
+        Report,
+    };
+
+    fn report() -> Report {
+        Report {
+            columns: vec!["username", "n_deleted", "note"],
+            rows: vec![
+                vec!["ursula".into(), 3.into(), serde_json::Value::Null],
+                vec!["le guin".into(), 12.into(), "ok".into()],
+            ],
+        }
+    }
+
+    #[test]
+    fn tables_are_aligned_on_the_widest_cell() {
+        assert_eq!(
+            report().render(OutputFormat::Table),
+            "username  n_deleted  note\nursula    3\nle guin   12         ok\n"
+        );
+    }
+
+    #[test]
+    fn json_output_is_an_array_of_objects() {
+        let json: serde_json::Value =
+            serde_json::from_str(&report().render(OutputFormat::Json)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                { "username": "ursula", "n_deleted": 3, "note": null },
+                { "username": "le guin", "n_deleted": 12, "note": "ok" }
+            ])
+        );
+    }
+}
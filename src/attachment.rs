@@ -0,0 +1,364 @@
+//! Files attached to newsletter issues, and images embedded in them.
+//!
+//! The database keeps track of the files of an issue, their content lives in
+//! a [`BlobStore`].
+use crate::email_client::Attachment;
+use crate::utils::ErrorCode;
+use anyhow::Context;
+use async_trait::async_trait;
+use sqlx::{
+    PgExecutor,
+    Postgres,
+    Transaction,
+};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Where the content of uploaded files is kept. Swappable so that it can move
+/// off the local disk.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    async fn put(&self, key: &str, content: &[u8]) -> Result<(), anyhow::Error>;
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error>;
+}
+
+/// Keeps blobs as files under a directory of the local disk.
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, content: &[u8]) -> Result<(), anyhow::Error> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        tokio::fs::write(&path, content)
+            .await
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, anyhow::Error> {
+        let path = self.root.join(key);
+        tokio::fs::read(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", path.display()))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AttachmentError {
+    #[error("Attachments need a file name.")]
+    MissingFileName,
+    #[error("{0} is larger than the size limit for a single file.")]
+    FileTooLarge(String),
+    #[error("The attachments of the issue are larger than the size limit.")]
+    TooLargeInTotal,
+    #[error("{file_name} cannot be attached: {content_type} files are not allowed.")]
+    ContentTypeNotAllowed {
+        file_name: String,
+        content_type: String,
+    },
+    #[error("{0} cannot be embedded: it is not an image.")]
+    NotAnImage(String),
+    #[error("Several attachments are named {0}.")]
+    DuplicateFileName(String),
+}
+
+/// Also picks the `attachment-*` message flashed to admins.
+impl ErrorCode for AttachmentError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::MissingFileName => "missing_file_name",
+            Self::FileTooLarge(_) => "file_too_large",
+            Self::TooLargeInTotal => "too_large_in_total",
+            Self::ContentTypeNotAllowed { .. } => "content_type_not_allowed",
+            Self::NotAnImage(_) => "not_an_image",
+            Self::DuplicateFileName(_) => "duplicate_file_name",
+        }
+    }
+}
+
+impl AttachmentError {
+    /// The file the error is about, if it is about a single one.
+    pub fn file_name(&self) -> Option<&str> {
+        match self {
+            Self::FileTooLarge(file_name)
+            | Self::ContentTypeNotAllowed { file_name, .. }
+            | Self::NotAnImage(file_name)
+            | Self::DuplicateFileName(file_name) => Some(file_name),
+            Self::MissingFileName | Self::TooLargeInTotal => None,
+        }
+    }
+}
+
+/// A file uploaded with a newsletter issue.
+#[derive(Debug)]
+pub struct NewAttachment {
+    pub file_name: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+    /// Embedded in the HTML content rather than attached to the email.
+    pub inline: bool,
+}
+
+impl NewAttachment {
+    /// Only the last component of the file name is kept: some browsers send
+    /// the full path of the file.
+    pub fn new(
+        file_name: &str,
+        content_type: &str,
+        content: Vec<u8>,
+        inline: bool,
+    ) -> Result<Self, AttachmentError> {
+        let file_name = file_name
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or_default()
+            .trim();
+        if file_name.is_empty() || file_name.chars().any(char::is_control) {
+            return Err(AttachmentError::MissingFileName);
+        }
+        Ok(Self {
+            file_name: file_name.to_owned(),
+            content_type: content_type.trim().to_lowercase(),
+            content,
+            inline,
+        })
+    }
+}
+
+/// The size and type limits on the files of an issue.
+#[derive(Clone, Debug)]
+pub struct AttachmentPolicy {
+    pub max_file_size_bytes: usize,
+    /// Email providers reject emails over a size, e.g. 10 MB for Postmark.
+    pub max_total_size_bytes: usize,
+    pub allowed_content_types: Vec<String>,
+}
+
+impl AttachmentPolicy {
+    /// Fails on the first attachment breaking a limit.
+    pub fn check(&self, attachments: &[NewAttachment]) -> Result<(), AttachmentError> {
+        let mut file_names = HashSet::new();
+        let mut total_size = 0;
+        for attachment in attachments {
+            if attachment.content.len() > self.max_file_size_bytes {
+                return Err(AttachmentError::FileTooLarge(attachment.file_name.clone()));
+            }
+            if !self
+                .allowed_content_types
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(&attachment.content_type))
+            {
+                return Err(AttachmentError::ContentTypeNotAllowed {
+                    file_name: attachment.file_name.clone(),
+                    content_type: attachment.content_type.clone(),
+                });
+            }
+            if attachment.inline && !attachment.content_type.starts_with("image/") {
+                return Err(AttachmentError::NotAnImage(attachment.file_name.clone()));
+            }
+            // Inline images are referenced by their file name.
+            if !file_names.insert(attachment.file_name.as_str()) {
+                return Err(AttachmentError::DuplicateFileName(
+                    attachment.file_name.clone(),
+                ));
+            }
+            total_size += attachment.content.len();
+        }
+        if total_size > self.max_total_size_bytes {
+            return Err(AttachmentError::TooLargeInTotal);
+        }
+        Ok(())
+    }
+}
+
+fn blob_key(newsletter_issue_id: Uuid, attachment_id: Uuid) -> String {
+    format!("{newsletter_issue_id}/{attachment_id}")
+}
+
+/// The content goes to the blob store right away: it is left behind if the
+/// transaction is rolled back.
+#[tracing::instrument(skip_all)]
+pub async fn store_attachments(
+    transaction: &mut Transaction<'_, Postgres>,
+    blob_store: &dyn BlobStore,
+    newsletter_issue_id: Uuid,
+    attachments: &[NewAttachment],
+) -> Result<(), anyhow::Error> {
+    for attachment in attachments {
+        let attachment_id = Uuid::new_v4();
+        blob_store
+            .put(
+                &blob_key(newsletter_issue_id, attachment_id),
+                &attachment.content,
+            )
+            .await
+            .context("Failed to store the content of an attachment.")?;
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issue_attachments (
+                attachment_id,
+                newsletter_issue_id,
+                file_name,
+                content_type,
+                size_bytes,
+                inline
+            )
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            attachment_id,
+            newsletter_issue_id,
+            attachment.file_name,
+            attachment.content_type,
+            attachment.content.len() as i32,
+            attachment.inline
+        )
+        .execute(transaction.as_mut())
+        .await
+        .context("Failed to store the details of an attachment.")?;
+    }
+    Ok(())
+}
+
+pub struct AttachmentSummary {
+    pub attachment_id: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i32,
+    pub inline: bool,
+}
+
+pub async fn list_attachments(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<AttachmentSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        AttachmentSummary,
+        r#"
+        SELECT attachment_id, file_name, content_type, size_bytes, inline
+        FROM newsletter_issue_attachments
+        WHERE newsletter_issue_id = $1
+        ORDER BY file_name
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// The files of an issue, ready to be sent.
+#[tracing::instrument(skip(executor, blob_store))]
+pub async fn load_attachments(
+    executor: impl PgExecutor<'_>,
+    blob_store: &dyn BlobStore,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<Attachment>, anyhow::Error> {
+    let mut attachments = Vec::new();
+    for a in list_attachments(executor, newsletter_issue_id).await? {
+        let content = blob_store
+            .get(&blob_key(newsletter_issue_id, a.attachment_id))
+            .await?;
+        attachments.push(Attachment {
+            name: a.file_name,
+            content_type: a.content_type,
+            content,
+            inline: a.inline,
+        });
+    }
+    Ok(attachments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AttachmentError,
+        AttachmentPolicy,
+        NewAttachment,
+    };
+    use claim::{
+        assert_err,
+        assert_ok,
+    };
+
+    fn policy() -> AttachmentPolicy {
+        AttachmentPolicy {
+            max_file_size_bytes: 10,
+            max_total_size_bytes: 15,
+            allowed_content_types: vec!["application/pdf".into(), "image/png".into()],
+        }
+    }
+
+    fn attachment(file_name: &str, content_type: &str, size: usize, inline: bool) -> NewAttachment {
+        NewAttachment::new(file_name, content_type, vec![0; size], inline).unwrap()
+    }
+
+    #[test]
+    fn only_the_last_component_of_a_file_name_is_kept() {
+        let attachment = attachment(r"C:\Users\ursula\report.pdf", "application/pdf", 1, false);
+        assert_eq!(attachment.file_name, "report.pdf");
+        assert_err!(NewAttachment::new(
+            "reports/",
+            "application/pdf",
+            vec![],
+            false
+        ));
+    }
+
+    #[test]
+    fn files_within_the_limits_are_accepted() {
+        assert_ok!(policy().check(&[
+            attachment("report.pdf", "application/pdf", 10, false),
+            attachment("logo.png", "IMAGE/PNG", 5, true),
+        ]));
+    }
+
+    #[test]
+    fn size_limits_are_enforced() {
+        assert!(matches!(
+            policy().check(&[attachment("report.pdf", "application/pdf", 11, false)]),
+            Err(AttachmentError::FileTooLarge(_))
+        ));
+        assert!(matches!(
+            policy().check(&[
+                attachment("report.pdf", "application/pdf", 10, false),
+                attachment("annex.pdf", "application/pdf", 6, false),
+            ]),
+            Err(AttachmentError::TooLargeInTotal)
+        ));
+    }
+
+    #[test]
+    fn type_limits_are_enforced() {
+        assert!(matches!(
+            policy().check(&[attachment("run.exe", "application/x-msdownload", 1, false)]),
+            Err(AttachmentError::ContentTypeNotAllowed { .. })
+        ));
+        assert!(matches!(
+            policy().check(&[attachment("report.pdf", "application/pdf", 1, true)]),
+            Err(AttachmentError::NotAnImage(_))
+        ));
+    }
+
+    #[test]
+    fn file_names_must_be_unique() {
+        assert!(matches!(
+            policy().check(&[
+                attachment("logo.png", "image/png", 1, true),
+                attachment("logo.png", "image/png", 1, false),
+            ]),
+            Err(AttachmentError::DuplicateFileName(_))
+        ));
+    }
+}
@@ -0,0 +1,196 @@
+//! An append-only record of what admins did, when, and from where. Events are
+//! written in the transaction of the action they record whenever there is
+//! one, so that an action and its record stand or fall together.
+use crate::authentication::UserId;
+use crate::utils::client_ip;
+use actix_web::dev::Payload;
+use actix_web::{
+    FromRequest,
+    HttpMessage,
+    HttpRequest,
+};
+use chrono::{
+    DateTime,
+    Utc,
+};
+use sqlx::{
+    PgExecutor,
+    PgPool,
+};
+use std::future::{
+    ready,
+    Ready,
+};
+use uuid::Uuid;
+
+pub const NEWSLETTER_ISSUE_PUBLISHED: &str = "newsletter_issue.published";
+pub const PASSWORD_CHANGED: &str = "password.changed";
+pub const LOGGED_OUT: &str = "session.logged_out";
+pub const SUBSCRIBER_ERASED: &str = "subscriber.erased";
+pub const SUPPRESSION_ADDED: &str = "suppression.added";
+pub const SUPPRESSIONS_IMPORTED: &str = "suppressions.imported";
+pub const SUPPRESSION_REMOVED: &str = "suppression.removed";
+pub const API_TOKEN_CREATED: &str = "api_token.created";
+pub const API_TOKEN_REVOKED: &str = "api_token.revoked";
+pub const USER_CREATED: &str = "user.created";
+pub const PASSWORD_RESET: &str = "password.reset";
+pub const SUBSCRIBER_CONFIRMED: &str = "subscriber.confirmed";
+pub const NEWSLETTER_ISSUE_REQUEUED: &str = "newsletter_issue.requeued";
+pub const IDEMPOTENCY_PURGED: &str = "idempotency.purged";
+
+pub const ACTIONS: [&str; 14] = [
+    NEWSLETTER_ISSUE_PUBLISHED,
+    PASSWORD_CHANGED,
+    LOGGED_OUT,
+    SUBSCRIBER_ERASED,
+    SUPPRESSION_ADDED,
+    SUPPRESSIONS_IMPORTED,
+    SUPPRESSION_REMOVED,
+    API_TOKEN_CREATED,
+    API_TOKEN_REVOKED,
+    USER_CREATED,
+    PASSWORD_RESET,
+    SUBSCRIBER_CONFIRMED,
+    NEWSLETTER_ISSUE_REQUEUED,
+    IDEMPOTENCY_PURGED,
+];
+
+/// Who is acting, and from where. Only available behind authentication.
+pub struct AuditContext {
+    pub user_id: Uuid,
+    pub ip_address: Option<String>,
+}
+
+impl FromRequest for AuditContext {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let user_id = req.extensions().get::<UserId>().copied();
+        ready(match user_id {
+            Some(user_id) => Ok(Self {
+                user_id: *user_id,
+                ip_address: client_ip(req),
+            }),
+            None => Err(actix_web::error::ErrorInternalServerError(
+                "Audited routes must require authentication.",
+            )),
+        })
+    }
+}
+
+/// `target` names what the action was applied to, e.g.
+/// `newsletter_issue:<id>`.
+#[tracing::instrument(name = "Record an audit event", skip(executor, context))]
+pub async fn record_audit_event(
+    executor: impl PgExecutor<'_>,
+    context: &AuditContext,
+    action: &str,
+    target: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (
+            audit_event_id,
+            user_id,
+            occurred_at,
+            ip_address,
+            action,
+            target
+        )
+        VALUES ($1, $2, now(), $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        context.user_id,
+        context.ip_address,
+        action,
+        target
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Like [`record_audit_event`], for an action taken from the command line by
+/// `operator`, the account running the command.
+#[tracing::instrument(name = "Record a command line audit event", skip(executor))]
+pub async fn record_command_line_audit_event(
+    executor: impl PgExecutor<'_>,
+    operator: &str,
+    action: &str,
+    target: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (
+            audit_event_id,
+            operator,
+            occurred_at,
+            action,
+            target
+        )
+        VALUES ($1, $2, now(), $3, $4)
+        "#,
+        Uuid::new_v4(),
+        operator,
+        action,
+        target
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub struct AuditEvent {
+    pub occurred_at: DateTime<Utc>,
+    /// Of the user, or of the operator for actions taken from the command
+    /// line.
+    pub username: String,
+    pub from_command_line: bool,
+    pub ip_address: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+}
+
+/// Unset fields match every event.
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub username: Option<String>,
+    pub action: Option<String>,
+}
+
+/// The most recent events first.
+#[tracing::instrument(name = "List audit events", skip(pool))]
+pub async fn list_audit_events(
+    pool: &PgPool,
+    filter: &AuditFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<AuditEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT
+            a.occurred_at,
+            COALESCE(u.username, a.operator) AS "username!",
+            a.operator IS NOT NULL AS "from_command_line!",
+            a.ip_address,
+            a.action,
+            a.target
+        FROM audit_log a
+        LEFT JOIN users u USING (user_id)
+        WHERE
+            ($1::TEXT IS NULL OR COALESCE(u.username, a.operator) = $1) AND
+            ($2::TEXT IS NULL OR a.action = $2)
+        ORDER BY a.occurred_at DESC, a.audit_event_id
+        LIMIT $3
+        OFFSET $4
+        "#,
+        filter.username,
+        filter.action,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,160 @@
+use super::AuthError;
+use anyhow::Context;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use rand::distributions::Alphanumeric;
+use rand::{
+    thread_rng,
+    Rng,
+};
+use secrecy::{
+    ExposeSecret,
+    Secret,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use sqlx::{
+    PgExecutor,
+    PgPool,
+};
+use uuid::Uuid;
+
+const TOKEN_PREFIX: &str = "pc_";
+const TOKEN_LENGTH: usize = 40;
+
+/// A personal API token, as handed out to its owner.
+///
+/// Only the SHA-256 hash of the token is stored: tokens are long random
+/// strings, so a slow password hash would buy us nothing while making every API
+/// request pay for it.
+pub struct ApiToken(Secret<String>);
+
+impl ApiToken {
+    pub fn generate() -> Self {
+        let mut rng = thread_rng();
+        let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(TOKEN_LENGTH)
+            .collect();
+        Self(Secret::new(format!("{TOKEN_PREFIX}{token}")))
+    }
+
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.0.expose_secret().as_bytes()))
+    }
+
+    pub fn expose_secret(&self) -> &str {
+        self.0.expose_secret()
+    }
+}
+
+impl From<Secret<String>> for ApiToken {
+    fn from(token: Secret<String>) -> Self {
+        Self(token)
+    }
+}
+
+pub struct ApiTokenRecord {
+    pub api_token_id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Create API token", skip(executor))]
+pub async fn create_api_token(
+    user_id: Uuid,
+    name: &str,
+    executor: impl PgExecutor<'_>,
+) -> Result<(Uuid, ApiToken), anyhow::Error> {
+    let api_token_id = Uuid::new_v4();
+    let token = ApiToken::generate();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (api_token_id, user_id, name, token_hash, created_at)
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        api_token_id,
+        user_id,
+        name,
+        token.hash(),
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store a new API token in the database.")?;
+    Ok((api_token_id, token))
+}
+
+/// Returns `false` if there was no active token with the given id owned by the
+/// user.
+#[tracing::instrument(name = "Revoke API token", skip(executor))]
+pub async fn revoke_api_token(
+    user_id: Uuid,
+    api_token_id: Uuid,
+    executor: impl PgExecutor<'_>,
+) -> Result<bool, anyhow::Error> {
+    let n_revoked = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = now()
+        WHERE
+            api_token_id = $1 AND
+            user_id = $2 AND
+            revoked_at IS NULL
+        "#,
+        api_token_id,
+        user_id,
+    )
+    .execute(executor)
+    .await
+    .context("Failed to revoke an API token.")?
+    .rows_affected();
+    Ok(n_revoked > 0)
+}
+
+#[tracing::instrument(name = "List API tokens", skip(pool))]
+pub async fn list_api_tokens(
+    user_id: Uuid,
+    pool: &PgPool,
+) -> Result<Vec<ApiTokenRecord>, anyhow::Error> {
+    let tokens = sqlx::query_as!(
+        ApiTokenRecord,
+        r#"
+        SELECT api_token_id, name, created_at, last_used_at, revoked_at
+        FROM api_tokens
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve API tokens.")?;
+    Ok(tokens)
+}
+
+#[tracing::instrument(name = "Validate API token", skip(token, pool))]
+pub async fn validate_api_token(token: ApiToken, pool: &PgPool) -> Result<Uuid, AuthError> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET last_used_at = now()
+        WHERE
+            token_hash = $1 AND
+            revoked_at IS NULL
+        RETURNING user_id
+        "#,
+        token.hash(),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to validate an API token.")?;
+    row.map(|r| r.user_id)
+        .ok_or_else(|| anyhow::anyhow!("Unknown or revoked API token."))
+        .map_err(AuthError::InvalidCredentials)
+}
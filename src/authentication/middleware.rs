@@ -1,3 +1,8 @@
+use super::{
+    validate_api_token,
+    ApiToken,
+    AuthError,
+};
 use crate::session_state::TypedSession;
 use crate::utils::{
     e500,
@@ -9,9 +14,20 @@ use actix_web::dev::{
     ServiceResponse,
 };
 use actix_web::error::InternalError;
+use actix_web::http::header::{
+    AUTHORIZATION,
+    WWW_AUTHENTICATE,
+};
+use actix_web::http::StatusCode;
 use actix_web::FromRequest;
 use actix_web::HttpMessage;
+use actix_web::{
+    web,
+    HttpResponse,
+};
 use actix_web_lab::middleware::Next;
+use secrecy::Secret;
+use sqlx::PgPool;
 use std::ops::Deref;
 use uuid::Uuid;
 
@@ -53,3 +69,54 @@ pub async fn reject_anonymous_users(
         }
     }
 }
+
+/// The API counterpart of `reject_anonymous_users`: it authenticates requests
+/// carrying an `Authorization: Bearer <token>` header and exposes the token
+/// owner as a `UserId` request extension.
+pub async fn reject_invalid_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let token = match bearer_token(&req) {
+        Ok(token) => token,
+        Err(e) => return Err(unauthorized(e)),
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The database pool is not registered as application data"))?
+        .clone();
+
+    match validate_api_token(token, &pool).await {
+        Ok(user_id) => {
+            req.extensions_mut().insert(UserId(user_id));
+            next.call(req).await
+        }
+        Err(AuthError::InvalidCredentials(e)) => Err(unauthorized(e)),
+        Err(AuthError::UnexpectedError(e)) => Err(e500(e)),
+    }
+}
+
+fn bearer_token(req: &ServiceRequest) -> Result<ApiToken, anyhow::Error> {
+    let header_value = req
+        .headers()
+        .get(AUTHORIZATION)
+        .ok_or_else(|| anyhow::anyhow!("The 'Authorization' header was missing"))?
+        .to_str()
+        .map_err(|_| anyhow::anyhow!("The 'Authorization' header was not a valid UTF8 string."))?;
+    let token = header_value
+        .strip_prefix("Bearer ")
+        .ok_or_else(|| anyhow::anyhow!("The authorization scheme was not 'Bearer'."))?;
+    Ok(ApiToken::from(Secret::new(token.trim().to_owned())))
+}
+
+fn unauthorized(e: anyhow::Error) -> actix_web::Error {
+    let response = HttpResponse::build(StatusCode::UNAUTHORIZED)
+        .insert_header((WWW_AUTHENTICATE, r#"Bearer realm="api""#))
+        .json(serde_json::json!({
+            "error": {
+                "code": "unauthorized",
+                "message": "A valid API token is required."
+            }
+        }));
+    InternalError::from_response(e, response).into()
+}
@@ -1,11 +1,23 @@
+mod api_token;
 mod middleware;
 mod password;
 
+pub use api_token::{
+    create_api_token,
+    list_api_tokens,
+    revoke_api_token,
+    validate_api_token,
+    ApiToken,
+    ApiTokenRecord,
+};
 pub use middleware::reject_anonymous_users;
+pub use middleware::reject_invalid_api_tokens;
 pub use middleware::UserId;
 pub use password::{
     change_password,
+    create_user,
     validate_credentials,
     AuthError,
+    CreateUserError,
     Credentials,
 };
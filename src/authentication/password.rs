@@ -14,7 +14,10 @@ use secrecy::{
     ExposeSecret,
     Secret,
 };
-use sqlx::PgPool;
+use sqlx::{
+    PgExecutor,
+    PgPool,
+};
 
 pub struct Credentials {
     pub username: String,
@@ -42,7 +45,7 @@ pub async fn validate_credentials(
     );
 
     if let Some((stored_user_id, stored_password_hash)) =
-        get_stored_credentials(&credentials.username, &pool).await?
+        get_stored_credentials(&credentials.username, pool).await?
     {
         user_id = Some(stored_user_id);
         expected_password_hash = stored_password_hash;
@@ -99,11 +102,11 @@ async fn get_stored_credentials(
     Ok(row)
 }
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
+#[tracing::instrument(name = "Change password", skip(password, executor))]
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: Secret<String>,
-    pool: &PgPool,
+    executor: impl PgExecutor<'_>,
 ) -> Result<(), anyhow::Error> {
     let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
         .await?
@@ -117,12 +120,51 @@ pub async fn change_password(
         password_hash.expose_secret(),
         user_id
     )
-    .execute(pool)
+    .execute(executor)
     .await
     .context("Failed to change user's password in the database.")?;
     Ok(())
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum CreateUserError {
+    #[error("{0} is already taken.")]
+    UsernameTaken(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+#[tracing::instrument(name = "Create user", skip(password, executor))]
+pub async fn create_user(
+    username: &str,
+    password: Secret<String>,
+    executor: impl PgExecutor<'_>,
+) -> Result<uuid::Uuid, CreateUserError> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task.")?
+        .context("Failed to hash password")?;
+    let user_id = uuid::Uuid::new_v4();
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (username) DO NOTHING
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret()
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store a new user in the database.")?
+    .rows_affected();
+    if n_inserted == 0 {
+        return Err(CreateUserError::UsernameTaken(username.to_owned()));
+    }
+    Ok(user_id)
+}
+
 fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
     let password_hash = Argon2::new(
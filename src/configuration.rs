@@ -1,5 +1,24 @@
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::attachment::{
+    AttachmentPolicy,
+    LocalBlobStore,
+};
+use crate::domain::{
+    SubscriberEmail,
+    SubscriberEmailError,
+};
+use crate::email_client::{
+    EmailClient,
+    PickupDirectoryTransport,
+    SenderIdentity,
+};
+use crate::email_options::IssueEmailSettings;
+use crate::idempotency::IdempotencyPolicy;
+use crate::throttle::{
+    deserialize_rate,
+    DomainLimits,
+    Throttle,
+};
+use ipnet::IpNet;
 use secrecy::{
     ExposeSecret,
     Secret,
@@ -12,6 +31,7 @@ use std::convert::{
     TryFrom,
     TryInto,
 };
+use std::path::PathBuf;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
@@ -19,6 +39,10 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    pub signup: SignupSettings,
+    pub attachments: AttachmentSettings,
+    pub delivery: DeliverySettings,
+    pub idempotency: IdempotencySettings,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -28,6 +52,159 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// Origins allowed to call the public signup endpoint from a browser,
+    /// e.g. the marketing site embedding the signup widget.
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+    /// Reverse proxies, e.g. `10.0.0.0/8`, whose `X-Forwarded-For` header is
+    /// believed. Clients are identified by the address of the connection
+    /// otherwise, which is the proxy's when there is one.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNet>,
+    /// How long open connections are given to complete on shutdown.
+    pub shutdown_timeout_seconds: u64,
+    /// Apply missing migrations before serving, rather than with
+    /// `prod_craft migrate`.
+    #[serde(default)]
+    pub migrate_on_startup: bool,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SignupSettings {
+    pub max_requests_per_ip: u32,
+    pub max_requests_per_email_domain: u32,
+    pub rate_limit_window_seconds: u64,
+    #[serde(default)]
+    pub disposable_email_domains: Vec<String>,
+    /// Leading zero bits required from proof-of-work solutions.
+    /// Signups do not need to solve a challenge when unset.
+    pub proof_of_work_difficulty: Option<u8>,
+    pub challenge_ttl_seconds: u64,
+    /// What subscribers agree to when signing up, recorded with their consent.
+    pub consent_wording: String,
+    /// Reject signups for domains without mail servers. This costs a DNS
+    /// lookup per signup.
+    #[serde(default)]
+    pub check_mx_records: bool,
+    /// In graphemes, i.e. in characters as people count them.
+    pub max_name_length: usize,
+    /// Store new addresses in lowercase, mailbox name included. Domains are
+    /// always lowercased.
+    #[serde(default)]
+    pub lowercase_email_local_parts: bool,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct AttachmentSettings {
+    /// The directory uploaded files are kept in.
+    pub storage_path: PathBuf,
+    pub max_file_size_bytes: usize,
+    pub max_total_size_bytes: usize,
+    pub allowed_content_types: Vec<String>,
+}
+
+impl AttachmentSettings {
+    pub fn store(&self) -> LocalBlobStore {
+        LocalBlobStore::new(&self.storage_path)
+    }
+
+    pub fn policy(&self) -> AttachmentPolicy {
+        AttachmentPolicy {
+            max_file_size_bytes: self.max_file_size_bytes,
+            max_total_size_bytes: self.max_total_size_bytes,
+            allowed_content_types: self.allowed_content_types.clone(),
+        }
+    }
+}
+
+/// How many delivery workers run, and how fast they send emails. Nothing is
+/// limited when unset.
+#[derive(serde::Deserialize, Clone)]
+pub struct DeliverySettings {
+    /// Delivery tasks run concurrently, sharing the limits below.
+    pub n_workers: usize,
+    /// Workers are notified of new deliveries. They also look at an empty
+    /// queue this often, in case a notification was missed.
+    pub poll_interval_seconds: u64,
+    /// How long a worker waits after a failure.
+    pub retry_interval_seconds: u64,
+    /// Across all recipients, to stay under the limits of the email provider.
+    /// Zero pauses sends.
+    #[serde(default, deserialize_with = "deserialize_rate")]
+    pub max_sends_per_second: Option<f64>,
+    /// For each recipient domain, to avoid being greylisted.
+    #[serde(default)]
+    pub per_domain: DomainLimits,
+    /// Domains that tolerate more, or less, than the default.
+    #[serde(default)]
+    pub domain_overrides: Vec<DomainOverrideSettings>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct DomainOverrideSettings {
+    pub domain: String,
+    /// Zero pauses sends to the domain.
+    #[serde(default, deserialize_with = "deserialize_rate")]
+    pub max_sends_per_minute: Option<f64>,
+    pub max_concurrent_sends: Option<usize>,
+}
+
+impl DeliverySettings {
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.poll_interval_seconds)
+    }
+
+    pub fn retry_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.retry_interval_seconds)
+    }
+
+    pub fn throttle(&self) -> Throttle {
+        Throttle::new(
+            self.max_sends_per_second,
+            self.per_domain,
+            self.domain_overrides.iter().map(|o| {
+                let limits = DomainLimits {
+                    max_sends_per_minute: o.max_sends_per_minute,
+                    max_concurrent_sends: o.max_concurrent_sends,
+                };
+                (o.domain.clone(), limits)
+            }),
+        )
+    }
+}
+
+/// How long the responses to idempotent requests are kept.
+#[derive(serde::Deserialize, Clone)]
+pub struct IdempotencySettings {
+    /// Past this, a key is treated as new.
+    pub retention_hours: u64,
+    /// How long a request waits for another one with the same key to
+    /// complete.
+    pub in_flight_timeout_milliseconds: u64,
+    /// How often the worker process deletes expired keys.
+    pub cleanup_interval_seconds: u64,
+    /// Expired keys are deleted this many at a time, to keep transactions
+    /// short.
+    pub cleanup_batch_size: i64,
+}
+
+impl IdempotencySettings {
+    pub fn retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.retention_hours * 60 * 60)
+    }
+
+    pub fn policy(&self) -> IdempotencyPolicy {
+        IdempotencyPolicy {
+            retention: self.retention(),
+            in_flight_timeout: std::time::Duration::from_millis(
+                self.in_flight_timeout_milliseconds,
+            ),
+        }
+    }
+
+    pub fn cleanup_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.cleanup_interval_seconds)
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -47,6 +224,24 @@ pub struct EmailClientSettings {
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    /// Other addresses newsletter issues can be sent from, verified with
+    /// the email provider.
+    #[serde(default)]
+    pub sender_identities: Vec<SenderIdentitySettings>,
+    /// The Postmark message stream newsletter issues go through by default.
+    pub newsletter_message_stream: Option<String>,
+    /// Write emails as MIME files to this directory, for a local mail server
+    /// to pick up, instead of sending them through Postmark.
+    #[serde(default)]
+    pub pickup_directory: Option<PathBuf>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SenderIdentitySettings {
+    /// What the publish form refers to the identity by.
+    pub id: String,
+    pub name: String,
+    pub email: String,
 }
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
@@ -65,18 +260,53 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         .add_source(config::File::from(
             configuration_directory.join(&environment_filename),
         ))
-        .add_source(
-            config::Environment::with_prefix("APP")
-                .prefix_separator("_")
-                .separator("__"),
-        )
+        .add_source(environment_variables(None))
+        .add_source(list_environment_variables(None))
         .build()?;
 
     settings.try_deserialize::<Settings>()
 }
+
+/// Settings that are lists, set from the environment as comma separated
+/// values, e.g. `APP_APPLICATION__CORS_ALLOWED_ORIGINS`.
+const LIST_SETTINGS: [&str; 2] = [
+    "application.cors_allowed_origins",
+    "attachments.allowed_content_types",
+];
+
+/// `APP_` environment variables, e.g. `APP_APPLICATION__PORT=5001` for
+/// `application.port`. Values stay strings, however much they look like
+/// numbers or booleans.
+fn environment_variables(variables: Option<config::Map<String, String>>) -> config::Environment {
+    config::Environment::with_prefix("APP")
+        .prefix_separator("_")
+        .separator("__")
+        .source(variables)
+}
+
+/// The environment variables of [`LIST_SETTINGS`] alone, split into lists.
+fn list_environment_variables(
+    variables: Option<config::Map<String, String>>,
+) -> config::Environment {
+    let variables = variables
+        .unwrap_or_else(|| std::env::vars().collect())
+        .into_iter()
+        .filter(|(name, _)| {
+            LIST_SETTINGS
+                .iter()
+                .any(|key| name.to_lowercase() == format!("app_{}", key.replace('.', "__")))
+        })
+        .collect();
+    LIST_SETTINGS.iter().fold(
+        environment_variables(Some(variables))
+            .list_separator(",")
+            .try_parsing(true),
+        |environment, key| environment.with_list_parse_key(key),
+    )
+}
 impl DatabaseSettings {
     pub fn with_db(&self) -> PgConnectOptions {
-        let mut options = self.without_db().database(&self.database_name);
+        let options = self.without_db().database(&self.database_name);
         options.log_statements(tracing::log::LevelFilter::Trace)
     }
 
@@ -89,7 +319,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
@@ -127,6 +357,10 @@ impl TryFrom<String> for Environment {
 impl EmailClientSettings {
     pub fn client(self) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address.");
+        if let Some(directory) = self.pickup_directory {
+            let transport = PickupDirectoryTransport::new(directory);
+            return EmailClient::with_transport(sender_email, Box::new(transport));
+        }
         let timeout = self.timeout();
         EmailClient::new(
             self.base_url,
@@ -135,11 +369,68 @@ impl EmailClientSettings {
             timeout,
         )
     }
-    pub fn sender(&self) -> Result<SubscriberEmail, String> {
+    pub fn sender(&self) -> Result<SubscriberEmail, SubscriberEmailError> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
+
+    pub fn issue_email_settings(&self) -> Result<IssueEmailSettings, SubscriberEmailError> {
+        let sender_identities = self
+            .sender_identities
+            .iter()
+            .map(|s| {
+                let identity = SenderIdentity::new(s.name.clone(), s.email.clone())?;
+                Ok((s.id.clone(), identity))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(IssueEmailSettings {
+            sender_identities,
+            default_message_stream: self.newsletter_message_stream.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        environment_variables,
+        list_environment_variables,
+    };
+
+    #[test]
+    fn only_list_settings_are_parsed_from_the_environment() {
+        let variables: config::Map<String, String> = [
+            ("APP_DATABASE__PASSWORD", "007"),
+            ("APP_EMAIL_CLIENT__AUTHORIZATION_TOKEN", "true"),
+            (
+                "APP_APPLICATION__CORS_ALLOWED_ORIGINS",
+                "https://a.example.com,https://b.example.com",
+            ),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+        let settings = config::Config::builder()
+            .add_source(environment_variables(Some(variables.clone())))
+            .add_source(list_environment_variables(Some(variables)))
+            .build()
+            .unwrap();
+
+        assert_eq!(settings.get_string("database.password").unwrap(), "007");
+        assert_eq!(
+            settings
+                .get_string("email_client.authorization_token")
+                .unwrap(),
+            "true"
+        );
+        assert_eq!(
+            settings
+                .get::<Vec<String>>("application.cors_allowed_origins")
+                .unwrap(),
+            ["https://a.example.com", "https://b.example.com"]
+        );
+    }
 }
@@ -0,0 +1,140 @@
+//! An append-only record of how and when each subscriber gave their consent.
+//! Logs are not an audit record: this is what we show when asked to prove it.
+use crate::utils::client_ip;
+use actix_web::http::header::USER_AGENT;
+use actix_web::HttpRequest;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use sqlx::{
+    PgPool,
+    Postgres,
+    Transaction,
+};
+use uuid::Uuid;
+
+pub const SUBSCRIBED: &str = "subscribed";
+pub const CONFIRMED: &str = "confirmed";
+
+const DEFAULT_SOURCE: &str = "website";
+const MAX_SOURCE_LENGTH: usize = 100;
+
+/// The consent statement shown next to the signup form.
+pub struct ConsentWording(pub String);
+
+/// Where a consent event comes from.
+pub struct ConsentContext {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: String,
+}
+
+impl ConsentContext {
+    /// `source` identifies the form (or widget) the subscriber used.
+    pub fn from_request(request: &HttpRequest, source: Option<String>) -> Self {
+        let source = source
+            .map(|s| s.trim().chars().take(MAX_SOURCE_LENGTH).collect::<String>())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| DEFAULT_SOURCE.into());
+        Self {
+            ip_address: client_ip(request),
+            user_agent: request
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|h| h.to_str().ok())
+                .map(ToOwned::to_owned),
+            source,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentEvent {
+    pub event_type: String,
+    pub occurred_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: String,
+    pub consent_wording: String,
+}
+
+#[tracing::instrument(name = "Record a consent event", skip(transaction, context, wording))]
+pub async fn record_consent_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    event_type: &str,
+    context: &ConsentContext,
+    wording: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_events (
+            consent_event_id,
+            subscriber_id,
+            event_type,
+            occurred_at,
+            ip_address,
+            user_agent,
+            source,
+            consent_wording
+        )
+        VALUES ($1, $2, $3, now(), $4, $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        event_type,
+        context.ip_address,
+        context.user_agent,
+        context.source,
+        wording
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    Ok(())
+}
+
+/// The wording a subscriber agreed to when they last signed up, so that their
+/// confirmation refers to what they were actually shown.
+#[tracing::instrument(
+    name = "Get the consent wording agreed to at signup",
+    skip(transaction)
+)]
+pub async fn get_signup_wording(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT consent_wording
+        FROM consent_events
+        WHERE subscriber_id = $1 AND event_type = $2
+        ORDER BY occurred_at DESC
+        LIMIT 1
+        "#,
+        subscriber_id,
+        SUBSCRIBED
+    )
+    .fetch_optional(transaction.as_mut())
+    .await?;
+    Ok(row.map(|r| r.consent_wording))
+}
+
+#[tracing::instrument(name = "List consent events", skip(pool))]
+pub async fn list_consent_events(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentEvent,
+        r#"
+        SELECT event_type, occurred_at, ip_address, user_agent, source, consent_wording
+        FROM consent_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,33 @@
+//! Checks that an email domain can actually receive email.
+use async_trait::async_trait;
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+
+/// Looks up the mail servers of a domain. Swappable so that tests do not
+/// depend on the network.
+#[async_trait]
+pub trait MxResolver: Send + Sync {
+    async fn has_mx_records(&self, domain: &str) -> Result<bool, anyhow::Error>;
+}
+
+/// Resolves MX records with the DNS configuration of the host.
+pub struct DnsMxResolver(TokioAsyncResolver);
+
+impl DnsMxResolver {
+    pub fn from_system_conf() -> Result<Self, anyhow::Error> {
+        Ok(Self(TokioAsyncResolver::tokio_from_system_conf()?))
+    }
+}
+
+#[async_trait]
+impl MxResolver for DnsMxResolver {
+    async fn has_mx_records(&self, domain: &str) -> Result<bool, anyhow::Error> {
+        // A trailing dot stops the resolver from trying the search domains of
+        // the host first.
+        match self.0.mx_lookup(format!("{domain}.")).await {
+            Ok(lookup) => Ok(lookup.iter().next().is_some()),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -3,5 +3,11 @@ mod subscriber_email;
 mod subscriber_name;
 
 pub use new_subscriber::NewSubscriber;
-pub use subscriber_email::SubscriberEmail;
-pub use subscriber_name::SubscriberName;
+pub use subscriber_email::{
+    SubscriberEmail,
+    SubscriberEmailError,
+};
+pub use subscriber_name::{
+    SubscriberName,
+    SubscriberNameError,
+};
@@ -1,16 +1,101 @@
+use crate::utils::ErrorCode;
 use validator::validate_email;
 
+/// Free email providers whose domain is often mistyped.
+const COMMON_DOMAINS: &[&str] = &[
+    "aol.com",
+    "email.com",
+    "gmail.com",
+    "gmx.com",
+    "googlemail.com",
+    "hotmail.com",
+    "icloud.com",
+    "live.com",
+    "mail.com",
+    "me.com",
+    "msn.com",
+    "outlook.com",
+    "proton.me",
+    "protonmail.com",
+    "yahoo.com",
+    "ymail.com",
+];
+
+/// Top-level domains that do not exist, but that `.com` is often mistyped as.
+const MISTYPED_COM: &[&str] = &["cmo", "cim", "con", "coom", "cpm", "ocm", "vom", "xom"];
+
 #[derive(Debug)]
 pub struct SubscriberEmail(String);
 
+#[derive(Debug, thiserror::Error)]
+pub enum SubscriberEmailError {
+    #[error("{0} is not a valid subscriber email.")]
+    Invalid(String),
+}
+
+/// The `code` of the `email` field in validation errors.
+impl ErrorCode for SubscriberEmailError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Invalid(_) => "invalid",
+        }
+    }
+}
+
 impl SubscriberEmail {
-    pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
-        } else {
-            Err(format!("{} is not a valid subscriber email.", s))
+    /// Domains are normalized to lowercase, in their ASCII (punycode) form.
+    /// The mailbox name is kept as typed, see [`SubscriberEmail::lowercase`].
+    pub fn parse(s: String) -> Result<SubscriberEmail, SubscriberEmailError> {
+        let Some((local_part, domain)) = s.trim().rsplit_once('@') else {
+            return Err(SubscriberEmailError::Invalid(s));
+        };
+        let domain = match idna::domain_to_ascii(domain) {
+            Ok(domain) if !domain.is_empty() => domain,
+            _ => return Err(SubscriberEmailError::Invalid(s)),
+        };
+        let email = format!("{}@{}", local_part, domain);
+        if !validate_email(&email) {
+            return Err(SubscriberEmailError::Invalid(s));
         }
+        Ok(Self(email))
+    }
+
+    /// Lowercases the mailbox name as well, so that the same mailbox always
+    /// maps to the same subscriber. Mailbox names are case-sensitive on
+    /// paper, but hardly any provider treats them that way.
+    pub fn lowercase(self) -> Self {
+        Self(self.0.to_lowercase())
     }
+
+    /// The address the subscriber most likely meant to type, if the domain
+    /// is one typo away from a common one. It may well be a real domain, so
+    /// this is only ever a hint.
+    pub fn suggestion(&self) -> Option<SubscriberEmail> {
+        let (local_part, domain) = self.0.rsplit_once('@')?;
+        suggest_domain(domain).map(|domain| Self(format!("{local_part}@{domain}")))
+    }
+
+    pub fn domain(&self) -> &str {
+        self.0
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default()
+    }
+}
+
+/// A common domain one typo away from `domain`, if `domain` is not one of them.
+fn suggest_domain(domain: &str) -> Option<String> {
+    if COMMON_DOMAINS.contains(&domain) {
+        return None;
+    }
+    if let Some(common) = COMMON_DOMAINS
+        .iter()
+        .find(|common| strsim::osa_distance(domain, common) == 1)
+    {
+        return Some((*common).to_owned());
+    }
+    let (name, tld) = domain.rsplit_once('.')?;
+    MISTYPED_COM.contains(&tld).then(|| format!("{name}.com"))
 }
 
 impl AsRef<str> for SubscriberEmail {
@@ -28,7 +113,10 @@ impl std::fmt::Display for SubscriberEmail {
 #[cfg(test)]
 mod tests {
     use super::SubscriberEmail;
-    use claim::assert_err;
+    use claim::{
+        assert_err,
+        assert_ok,
+    };
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
 
@@ -64,4 +152,52 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn domains_are_normalized_to_lowercase() {
+        let email = assert_ok!(SubscriberEmail::parse(" Ursula@Domain.COM ".into()));
+        assert_eq!(email.as_ref(), "Ursula@domain.com");
+    }
+
+    #[test]
+    fn mailbox_names_are_lowercased_on_demand() {
+        let email = assert_ok!(SubscriberEmail::parse("Ursula@Domain.COM".into()));
+        assert_eq!(email.lowercase().as_ref(), "ursula@domain.com");
+    }
+
+    #[test]
+    fn internationalized_domains_are_converted_to_punycode() {
+        let email = assert_ok!(SubscriberEmail::parse("ursula@bücher.example".into()));
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.example");
+        assert_eq!(email.domain(), "xn--bcher-kva.example");
+    }
+
+    #[test]
+    fn common_domain_typos_come_with_a_suggestion() {
+        for (typo, suggestion) in [
+            ("ursula@gmial.com", "ursula@gmail.com"),
+            ("ursula@hotmail.co", "ursula@hotmail.com"),
+            ("Ursula@yahooo.com", "Ursula@yahoo.com"),
+            ("ursula@domain.con", "ursula@domain.com"),
+        ] {
+            let email = assert_ok!(SubscriberEmail::parse(typo.into()));
+            let suggestion = Some(suggestion.to_owned());
+            assert_eq!(email.suggestion().map(|s| s.to_string()), suggestion);
+        }
+    }
+
+    #[test]
+    fn common_domains_do_not_come_with_a_suggestion() {
+        for email in ["ursula@gmail.com", "ursula@mail.com", "ursula@ymail.com"] {
+            let email = assert_ok!(SubscriberEmail::parse(email.into()));
+            assert!(email.suggestion().is_none());
+        }
+    }
+
+    #[test]
+    fn likely_typos_are_still_valid_addresses() {
+        for email in ["ursula@gmial.com", "ursula@yahooo.com", "ursula@domain.con"] {
+            assert_ok!(SubscriberEmail::parse(email.into()));
+        }
+    }
 }
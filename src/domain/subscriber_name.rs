@@ -1,25 +1,90 @@
+use crate::utils::ErrorCode;
+use unicode_normalization::UnicodeNormalization;
 use unicode_segmentation::UnicodeSegmentation;
 
+pub const DEFAULT_MAX_LENGTH: usize = 256;
+
+const FORBIDDEN_CHARACTERS: [char; 9] = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
+
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
-impl SubscriberName {
-    pub fn parse(s: String) -> Result<SubscriberName, String> {
-        let is_empty_or_whitespace = s.trim().is_empty();
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum SubscriberNameError {
+    #[error("The name cannot be empty.")]
+    Empty,
+    #[error("The name cannot be longer than {max_length} characters.")]
+    TooLong { max_length: usize },
+    #[error("The name cannot contain the character '{0}'.")]
+    ForbiddenCharacter(char),
+}
 
-        let is_too_long = s.graphemes(true).count() > 256;
+/// The `code` of the `name` field in validation errors.
+impl ErrorCode for SubscriberNameError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Empty => "empty",
+            Self::TooLong { .. } => "too_long",
+            Self::ForbiddenCharacter(_) => "forbidden_character",
+        }
+    }
+}
 
-        let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
+impl SubscriberName {
+    pub fn parse(s: String) -> Result<SubscriberName, SubscriberNameError> {
+        Self::parse_with_max_length(s, DEFAULT_MAX_LENGTH)
+    }
 
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("{} is not a valid subscriber name.", s))
-        } else {
-            Ok(Self(s))
+    /// Names are normalized to NFC, so that visually identical names are
+    /// stored identically, after dropping characters that do not render:
+    /// control characters, bidi overrides and other invisible formatting.
+    /// `max_length` counts graphemes, not bytes.
+    pub fn parse_with_max_length(
+        s: String,
+        max_length: usize,
+    ) -> Result<SubscriberName, SubscriberNameError> {
+        let name: String = s
+            .chars()
+            .map(|c| if c.is_whitespace() { ' ' } else { c })
+            .filter(|c| !is_invisible(*c))
+            .nfc()
+            .collect();
+        let name = name.trim_matches(|c: char| c.is_whitespace() || is_joiner(c));
+        if name.is_empty() {
+            return Err(SubscriberNameError::Empty);
+        }
+        if let Some(c) = name.chars().find(|c| FORBIDDEN_CHARACTERS.contains(c)) {
+            return Err(SubscriberNameError::ForbiddenCharacter(c));
         }
+        if name.graphemes(true).count() > max_length {
+            return Err(SubscriberNameError::TooLong { max_length });
+        }
+        Ok(Self(name.to_owned()))
     }
 }
 
+/// Characters that take no space on screen. Joiners are not in the list:
+/// some scripts and emoji sequences need them between two characters.
+fn is_invisible(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{00AD}' // soft hyphen
+            | '\u{061C}' // arabic letter mark
+            | '\u{180E}' // mongolian vowel separator
+            | '\u{200B}' // zero width space
+            | '\u{200E}'..='\u{200F}' // left-to-right and right-to-left marks
+            | '\u{202A}'..='\u{202E}' // bidi embeddings and overrides
+            | '\u{2060}'..='\u{2064}' // word joiner and invisible operators
+            | '\u{2066}'..='\u{2069}' // bidi isolates
+            | '\u{FEFF}' // zero width no-break space
+        )
+}
+
+fn is_joiner(c: char) -> bool {
+    matches!(c, '\u{200C}' | '\u{200D}')
+}
+
 impl AsRef<str> for SubscriberName {
     fn as_ref(&self) -> &str {
         &self.0
@@ -28,7 +93,11 @@ impl AsRef<str> for SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::SubscriberName;
+    use crate::domain::{
+        SubscriberName,
+        SubscriberNameError,
+    };
+    use crate::utils::ErrorCode;
     use claim::{
         assert_err,
         assert_ok,
@@ -71,4 +140,56 @@ mod tests {
         let name = "Ursula Le Guin".to_string();
         assert_ok!(SubscriberName::parse(name));
     }
+
+    #[test]
+    fn names_are_normalized_to_nfc() {
+        // "e" followed by a combining acute accent.
+        let name = assert_ok!(SubscriberName::parse("Rene\u{0301}e".into()));
+        assert_eq!(name.as_ref(), "Ren\u{00E9}e");
+    }
+
+    #[test]
+    fn invisible_and_bidi_control_characters_are_removed() {
+        let name = "\u{202E}Ursula\u{200B} Le\u{0007} Guin\u{2069}\u{FEFF}".to_string();
+        let name = assert_ok!(SubscriberName::parse(name));
+        assert_eq!(name.as_ref(), "Ursula Le Guin");
+    }
+
+    #[test]
+    fn surrounding_whitespace_is_trimmed() {
+        let name = assert_ok!(SubscriberName::parse("\t Ursula Le Guin\n".into()));
+        assert_eq!(name.as_ref(), "Ursula Le Guin");
+    }
+
+    #[test]
+    fn joiners_are_kept_inside_names() {
+        let name = "\u{200D}\u{0645}\u{06CC}\u{200C}\u{062E}\u{0648}\u{0627}\u{0647}\u{0645}";
+        let parsed = assert_ok!(SubscriberName::parse(name.into()));
+        assert_eq!(parsed.as_ref(), &name[3..]);
+    }
+
+    #[test]
+    fn names_made_of_invisible_characters_only_are_rejected() {
+        let name = "\u{200B}\u{202E}\u{200D}".to_string();
+        assert_eq!(
+            SubscriberName::parse(name).unwrap_err(),
+            SubscriberNameError::Empty
+        );
+    }
+
+    #[test]
+    fn the_maximum_length_is_configurable() {
+        assert_ok!(SubscriberName::parse_with_max_length("a".repeat(10), 10));
+        assert_eq!(
+            SubscriberName::parse_with_max_length("a".repeat(11), 10).unwrap_err(),
+            SubscriberNameError::TooLong { max_length: 10 }
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_forbidden_character() {
+        let e = SubscriberName::parse("Ursula <script>".into()).unwrap_err();
+        assert_eq!(e, SubscriberNameError::ForbiddenCharacter('<'));
+        assert_eq!(e.code(), "forbidden_character");
+    }
 }
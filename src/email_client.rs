@@ -1,21 +1,118 @@
-use crate::domain::SubscriberEmail;
+use crate::domain::{
+    SubscriberEmail,
+    SubscriberEmailError,
+};
+use crate::mime_message;
+use anyhow::Context;
+use async_trait::async_trait;
 use reqwest::Client;
 use secrecy::{
     ExposeSecret,
     Secret,
 };
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use uuid::Uuid;
 
-pub struct EmailClient {
+/// A file sent along with an email.
+pub struct Attachment {
+    pub name: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+    /// Embedded in the HTML body, which refers to it as `cid:<name>`.
+    pub inline: bool,
+}
+
+/// A name and address emails can be sent from. It must have been verified
+/// with the email provider.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SenderIdentity {
+    name: String,
+    email: String,
+}
+
+impl SenderIdentity {
+    pub fn new(name: String, email: String) -> Result<Self, SubscriberEmailError> {
+        let email = SubscriberEmail::parse(email)?;
+        Ok(Self {
+            name: name.trim().to_owned(),
+            email: email.as_ref().to_owned(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    /// As in a `From` header, e.g. `"Doe, Jane" <jane@example.com>`.
+    pub fn mailbox(&self) -> String {
+        if self.name.is_empty() {
+            return self.email.clone();
+        }
+        let needs_quotes = self.name.chars().any(|c| "()<>[]:;@\\,.\"".contains(c));
+        if needs_quotes {
+            let name = self.name.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("\"{name}\" <{}>", self.email)
+        } else {
+            format!("{} <{}>", self.name, self.email)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+/// How an email is sent, beyond who it goes to and what it says. By default it
+/// comes from the configured sender, through the default message stream.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EmailOptions {
+    pub sender: Option<SenderIdentity>,
+    pub reply_to: Option<String>,
+    pub headers: Vec<EmailHeader>,
+    /// A Postmark tag, to filter and report on emails.
+    pub tag: Option<String>,
+    /// Postmark metadata, sent back with webhooks.
+    pub metadata: BTreeMap<String, String>,
+    pub message_stream: Option<String>,
+}
+
+/// An email, ready to be handed to an [`EmailTransport`].
+pub struct OutgoingEmail<'a> {
+    /// As in a `From` header.
+    pub from: &'a str,
+    pub to: &'a str,
+    pub subject: &'a str,
+    pub html_body: &'a str,
+    pub text_body: &'a str,
+    pub options: &'a EmailOptions,
+    pub attachments: &'a [Attachment],
+}
+
+/// How emails leave the application. Swappable so that they don't have to go
+/// through Postmark's HTTP API.
+#[async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, email: &OutgoingEmail<'_>) -> Result<(), anyhow::Error>;
+}
+
+/// Sends emails through the Postmark HTTP API.
+pub struct PostmarkTransport {
     http_client: Client,
     base_url: String,
-    sender: SubscriberEmail,
     authorization_token: Secret<String>,
 }
 
-impl EmailClient {
+impl PostmarkTransport {
     pub fn new(
         base_url: String,
-        sender: SubscriberEmail,
         authorization_token: Secret<String>,
         timeout: std::time::Duration,
     ) -> Self {
@@ -23,27 +120,34 @@ impl EmailClient {
         Self {
             http_client,
             base_url,
-            sender,
             authorization_token,
         }
     }
-    pub async fn send_email(
-        &self,
-        recipient: &SubscriberEmail,
-        subject: &str,
-        html_content: &str,
-        text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+}
+
+#[async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, email: &OutgoingEmail<'_>) -> Result<(), anyhow::Error> {
         let url = format!("{}/email", self.base_url);
+        let options = email.options;
         let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
-            subject,
-            html_body: html_content,
-            text_body: text_content,
+            from: email.from,
+            to: email.to,
+            subject: email.subject,
+            html_body: email.html_body,
+            text_body: email.text_body,
+            reply_to: options.reply_to.as_deref(),
+            headers: &options.headers,
+            tag: options.tag.as_deref(),
+            metadata: &options.metadata,
+            message_stream: options.message_stream.as_deref(),
+            attachments: email
+                .attachments
+                .iter()
+                .map(PostmarkAttachment::from)
+                .collect(),
         };
-        let builder = self
-            .http_client
+        self.http_client
             .post(&url)
             .header(
                 "X-Postmark-Server-Token",
@@ -57,6 +161,101 @@ impl EmailClient {
     }
 }
 
+/// Writes emails as MIME messages, one `.eml` file each, to a directory a
+/// local mail server picks them up from.
+pub struct PickupDirectoryTransport {
+    directory: PathBuf,
+}
+
+impl PickupDirectoryTransport {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for PickupDirectoryTransport {
+    async fn send(&self, email: &OutgoingEmail<'_>) -> Result<(), anyhow::Error> {
+        let message = mime_message::render(email);
+        let id = Uuid::new_v4();
+        // Renamed once complete, so that the mail server never picks up half
+        // a message.
+        let partial = self.directory.join(format!("{id}.tmp"));
+        let path = self.directory.join(format!("{id}.eml"));
+        tokio::fs::write(&partial, message)
+            .await
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        tokio::fs::rename(&partial, &path)
+            .await
+            .with_context(|| format!("Failed to move {}", path.display()))?;
+        Ok(())
+    }
+}
+
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Box<dyn EmailTransport>,
+}
+
+impl EmailClient {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let transport = PostmarkTransport::new(base_url, authorization_token, timeout);
+        Self::with_transport(sender, Box::new(transport))
+    }
+
+    pub fn with_transport(sender: SubscriberEmail, transport: Box<dyn EmailTransport>) -> Self {
+        Self { sender, transport }
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.send_email_with(
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            &EmailOptions::default(),
+            &[],
+        )
+        .await
+    }
+
+    pub async fn send_email_with(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        options: &EmailOptions,
+        attachments: &[Attachment],
+    ) -> Result<(), anyhow::Error> {
+        let from = match &options.sender {
+            Some(sender) => sender.mailbox(),
+            None => self.sender.as_ref().to_owned(),
+        };
+        let email = OutgoingEmail {
+            from: &from,
+            to: recipient.as_ref(),
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            options,
+            attachments,
+        };
+        self.transport.send(&email).await
+    }
+}
+
 #[derive(serde::Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct SendEmailRequest<'a> {
@@ -65,11 +264,54 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_stream: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<PostmarkAttachment<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkAttachment<'a> {
+    name: &'a str,
+    /// Base64 encoded.
+    content: String,
+    content_type: &'a str,
+    #[serde(rename = "ContentID", skip_serializing_if = "Option::is_none")]
+    content_id: Option<String>,
+}
+
+impl<'a> From<&'a Attachment> for PostmarkAttachment<'a> {
+    fn from(attachment: &'a Attachment) -> Self {
+        Self {
+            name: &attachment.name,
+            content: base64::encode(&attachment.content),
+            content_type: &attachment.content_type,
+            content_id: attachment
+                .inline
+                .then(|| format!("cid:{}", attachment.name)),
+        }
+    }
 }
 #[cfg(test)]
 mod tests {
     use crate::domain::SubscriberEmail;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{
+        Attachment,
+        EmailClient,
+        EmailHeader,
+        EmailOptions,
+        PickupDirectoryTransport,
+        SenderIdentity,
+    };
     use claim::{
         assert_err,
         assert_ok,
@@ -161,6 +403,150 @@ mod tests {
             .await;
     }
 
+    #[tokio::test]
+    async fn attachments_are_sent_base64_encoded() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let attachments = [
+            Attachment {
+                name: "report.pdf".into(),
+                content_type: "application/pdf".into(),
+                content: b"%PDF".to_vec(),
+                inline: false,
+            },
+            Attachment {
+                name: "logo.png".into(),
+                content_type: "image/png".into(),
+                content: vec![0x89, b'P', b'N', b'G'],
+                inline: true,
+            },
+        ];
+        email_client
+            .send_email_with(
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                &EmailOptions::default(),
+                &attachments,
+            )
+            .await
+            .unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Attachments"],
+            serde_json::json!([
+                {
+                    "Name": "report.pdf",
+                    "Content": "JVBERg==",
+                    "ContentType": "application/pdf"
+                },
+                {
+                    "Name": "logo.png",
+                    "Content": "iVBORw==",
+                    "ContentType": "image/png",
+                    "ContentID": "cid:logo.png"
+                }
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn email_options_are_sent_to_postmark() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let options = EmailOptions {
+            sender: Some(
+                SenderIdentity::new("Jane Doe".into(), "jane@example.com".into()).unwrap(),
+            ),
+            reply_to: Some("replies@example.com".into()),
+            headers: vec![EmailHeader {
+                name: "X-Campaign".into(),
+                value: "spring".into(),
+            }],
+            tag: Some("newsletter".into()),
+            metadata: [("issue".to_owned(), "42".to_owned())].into(),
+            message_stream: Some("broadcast".into()),
+        };
+        email_client
+            .send_email_with(&email(), &subject(), &content(), &content(), &options, &[])
+            .await
+            .unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["From"], "Jane Doe <jane@example.com>");
+        assert_eq!(body["ReplyTo"], "replies@example.com");
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([{ "Name": "X-Campaign", "Value": "spring" }])
+        );
+        assert_eq!(body["Tag"], "newsletter");
+        assert_eq!(body["Metadata"], serde_json::json!({ "issue": "42" }));
+        assert_eq!(body["MessageStream"], "broadcast");
+    }
+
+    #[tokio::test]
+    async fn the_pickup_directory_transport_writes_mime_messages() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir(&directory).unwrap();
+        let transport = PickupDirectoryTransport::new(directory.clone());
+        let email_client = EmailClient::with_transport(email(), Box::new(transport));
+
+        let attachment = Attachment {
+            name: "report.pdf".into(),
+            content_type: "application/pdf".into(),
+            content: b"%PDF".to_vec(),
+            inline: false,
+        };
+        email_client
+            .send_email_with(
+                &email(),
+                "Quarterly report",
+                &content(),
+                &content(),
+                &EmailOptions::default(),
+                &[attachment],
+            )
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let message = std::fs::read_to_string(&files[0]).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(message.contains("\r\nSubject: Quarterly report\r\n"));
+        assert!(message.contains("Content-Disposition: attachment; filename=\"report.pdf\""));
+        assert!(message.contains("JVBERg=="));
+    }
+
+    #[test]
+    fn sender_names_with_special_characters_are_quoted() {
+        let sender =
+            SenderIdentity::new(r#"Doe, "JD" Jane"#.into(), "jane@example.com".into()).unwrap();
+        assert_eq!(sender.mailbox(), r#""Doe, \"JD\" Jane" <jane@example.com>"#);
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_the_server_returns_200() {
         let mock_server = MockServer::start().await;
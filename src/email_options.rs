@@ -0,0 +1,387 @@
+//! How the emails of a newsletter issue are sent: which sender identity they
+//! come from, where replies go, extra headers, and the Postmark tag, metadata
+//! and message stream. They are picked when publishing the issue and stored
+//! with it.
+use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    EmailHeader,
+    EmailOptions,
+    SenderIdentity,
+};
+use crate::utils::ErrorCode;
+use anyhow::Context;
+use sqlx::types::Json;
+use sqlx::{
+    PgExecutor,
+    Postgres,
+    Transaction,
+};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Postmark limits.
+const MAX_TAG_LENGTH: usize = 1000;
+const MAX_METADATA_FIELDS: usize = 10;
+const MAX_METADATA_KEY_LENGTH: usize = 20;
+const MAX_METADATA_VALUE_LENGTH: usize = 80;
+
+/// Headers that are set from the other fields of an email, or by the email
+/// provider.
+const RESERVED_HEADERS: &[&str] = &[
+    "bcc",
+    "cc",
+    "content-transfer-encoding",
+    "content-type",
+    "date",
+    "from",
+    "message-id",
+    "mime-version",
+    "reply-to",
+    "return-path",
+    "sender",
+    "subject",
+    "to",
+];
+
+/// What the author of an issue can pick from, as configured.
+pub struct IssueEmailSettings {
+    /// By id.
+    pub sender_identities: Vec<(String, SenderIdentity)>,
+    pub default_message_stream: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmailOptionsError {
+    #[error("{0} is not a known sender identity.")]
+    UnknownSender(String),
+    #[error("{0} is not a valid reply-to address.")]
+    InvalidReplyTo(String),
+    #[error("{0} is not a valid email header.")]
+    InvalidHeader(String),
+    #[error("The {0} header cannot be set on an issue.")]
+    ReservedHeader(String),
+    #[error("The tag of an issue can be at most 1000 characters long.")]
+    TagTooLong,
+    #[error("{0} is not valid metadata: keys are at most 20 characters long and values 80.")]
+    InvalidMetadata(String),
+    #[error("An issue can carry at most 10 metadata fields.")]
+    TooMuchMetadata,
+}
+
+/// Also picks the `email-options-*` message flashed to admins.
+impl ErrorCode for EmailOptionsError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::UnknownSender(_) => "unknown_sender",
+            Self::InvalidReplyTo(_) => "invalid_reply_to",
+            Self::InvalidHeader(_) => "invalid_header",
+            Self::ReservedHeader(_) => "reserved_header",
+            Self::TagTooLong => "tag_too_long",
+            Self::InvalidMetadata(_) => "invalid_metadata",
+            Self::TooMuchMetadata => "too_much_metadata",
+        }
+    }
+}
+
+impl EmailOptionsError {
+    /// The value the error is about, if any.
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            Self::UnknownSender(value)
+            | Self::InvalidReplyTo(value)
+            | Self::InvalidHeader(value)
+            | Self::ReservedHeader(value)
+            | Self::InvalidMetadata(value) => Some(value),
+            Self::TagTooLong | Self::TooMuchMetadata => None,
+        }
+    }
+}
+
+/// Email options as entered by the author of an issue. Blank fields are left
+/// unset, except for the message stream which falls back to the configured
+/// one.
+#[derive(Debug, Default)]
+pub struct NewEmailOptions {
+    /// The id of a configured sender identity.
+    pub sender: String,
+    pub reply_to: String,
+    pub headers: Vec<(String, String)>,
+    pub tag: String,
+    pub metadata: Vec<(String, String)>,
+    pub message_stream: String,
+}
+
+impl NewEmailOptions {
+    pub fn parse(self, settings: &IssueEmailSettings) -> Result<EmailOptions, EmailOptionsError> {
+        let sender = match self.sender.trim() {
+            "" => None,
+            id => Some(
+                settings
+                    .sender_identities
+                    .iter()
+                    .find(|(i, _)| i == id)
+                    .map(|(_, identity)| identity.clone())
+                    .ok_or_else(|| EmailOptionsError::UnknownSender(id.to_owned()))?,
+            ),
+        };
+        let reply_to = match self.reply_to.trim() {
+            "" => None,
+            reply_to => Some(
+                SubscriberEmail::parse(reply_to.to_owned())
+                    .map_err(|_| EmailOptionsError::InvalidReplyTo(reply_to.to_owned()))?
+                    .as_ref()
+                    .to_owned(),
+            ),
+        };
+        let headers = self
+            .headers
+            .into_iter()
+            .map(|(name, value)| parse_header(name, value))
+            .collect::<Result<_, _>>()?;
+        let tag = match self.tag.trim() {
+            "" => None,
+            tag if tag.chars().count() > MAX_TAG_LENGTH => {
+                return Err(EmailOptionsError::TagTooLong)
+            }
+            tag => Some(tag.to_owned()),
+        };
+        let metadata = parse_metadata(self.metadata)?;
+        let message_stream = match self.message_stream.trim() {
+            "" => settings.default_message_stream.clone(),
+            message_stream => Some(message_stream.to_owned()),
+        };
+        Ok(EmailOptions {
+            sender,
+            reply_to,
+            headers,
+            tag,
+            metadata,
+            message_stream,
+        })
+    }
+}
+
+/// Splits `Name: value` lines, skipping blank ones. The lines without a colon
+/// are returned as errors.
+pub fn split_fields(text: &str) -> Result<Vec<(String, String)>, String> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.split_once(':')
+                .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+                .ok_or_else(|| line.trim().to_owned())
+        })
+        .collect()
+}
+
+fn parse_header(name: String, value: String) -> Result<EmailHeader, EmailOptionsError> {
+    let is_valid_name = !name.is_empty() && name.chars().all(|c| c.is_ascii_graphic() && c != ':');
+    if !is_valid_name || value.contains(['\r', '\n']) {
+        return Err(EmailOptionsError::InvalidHeader(name));
+    }
+    if RESERVED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+        return Err(EmailOptionsError::ReservedHeader(name));
+    }
+    Ok(EmailHeader { name, value })
+}
+
+fn parse_metadata(
+    fields: Vec<(String, String)>,
+) -> Result<BTreeMap<String, String>, EmailOptionsError> {
+    if fields.len() > MAX_METADATA_FIELDS {
+        return Err(EmailOptionsError::TooMuchMetadata);
+    }
+    let mut metadata = BTreeMap::new();
+    for (key, value) in fields {
+        let key = key.trim().to_owned();
+        let is_valid = !key.is_empty()
+            && key.chars().count() <= MAX_METADATA_KEY_LENGTH
+            && value.chars().count() <= MAX_METADATA_VALUE_LENGTH
+            && !metadata.contains_key(&key);
+        if !is_valid {
+            return Err(EmailOptionsError::InvalidMetadata(key));
+        }
+        metadata.insert(key, value);
+    }
+    Ok(metadata)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn store_email_options(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    options: &EmailOptions,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            sender_name = $2,
+            sender_email = $3,
+            reply_to = $4,
+            headers = $5,
+            tag = $6,
+            metadata = $7,
+            message_stream = $8
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+        options.sender.as_ref().map(|s| s.name()),
+        options.sender.as_ref().map(|s| s.email()),
+        options.reply_to,
+        Json(&options.headers) as _,
+        options.tag,
+        Json(&options.metadata) as _,
+        options.message_stream
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    Ok(())
+}
+
+pub async fn get_email_options(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+) -> Result<EmailOptions, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            sender_name,
+            sender_email,
+            reply_to,
+            headers AS "headers: Json<Vec<EmailHeader>>",
+            tag,
+            metadata AS "metadata: Json<BTreeMap<String, String>>",
+            message_stream
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_one(executor)
+    .await?;
+    let sender = match (r.sender_name, r.sender_email) {
+        (Some(name), Some(email)) => Some(
+            SenderIdentity::new(name, email)
+                .context("The sender identity of a newsletter issue is invalid.")?,
+        ),
+        _ => None,
+    };
+    Ok(EmailOptions {
+        sender,
+        reply_to: r.reply_to,
+        headers: r.headers.0,
+        tag: r.tag,
+        metadata: r.metadata.0,
+        message_stream: r.message_stream,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        split_fields,
+        EmailOptionsError,
+        IssueEmailSettings,
+        NewEmailOptions,
+    };
+    use crate::email_client::SenderIdentity;
+    use claim::assert_err;
+
+    fn settings() -> IssueEmailSettings {
+        IssueEmailSettings {
+            sender_identities: vec![(
+                "editor".into(),
+                SenderIdentity::new("The Editor".into(), "editor@example.com".into()).unwrap(),
+            )],
+            default_message_stream: Some("broadcast".into()),
+        }
+    }
+
+    #[test]
+    fn blank_options_fall_back_to_the_defaults() {
+        let options = NewEmailOptions::default().parse(&settings()).unwrap();
+        assert_eq!(options.sender, None);
+        assert_eq!(options.message_stream.as_deref(), Some("broadcast"));
+    }
+
+    #[test]
+    fn the_sender_must_be_a_configured_identity() {
+        let options = NewEmailOptions {
+            sender: "editor".into(),
+            ..Default::default()
+        };
+        let options = options.parse(&settings()).unwrap();
+        assert_eq!(options.sender.unwrap().email(), "editor@example.com");
+
+        let options = NewEmailOptions {
+            sender: "ceo".into(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            options.parse(&settings()),
+            Err(EmailOptionsError::UnknownSender(_))
+        ));
+    }
+
+    #[test]
+    fn headers_set_from_other_fields_are_rejected() {
+        for name in ["From", "reply-to", "Subject"] {
+            let options = NewEmailOptions {
+                headers: vec![(name.into(), "value".into())],
+                ..Default::default()
+            };
+            assert!(matches!(
+                options.parse(&settings()),
+                Err(EmailOptionsError::ReservedHeader(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn header_names_must_be_valid() {
+        for name in ["", "X Campaign", "X-Caf\u{e9}"] {
+            let options = NewEmailOptions {
+                headers: vec![(name.into(), "value".into())],
+                ..Default::default()
+            };
+            assert!(matches!(
+                options.parse(&settings()),
+                Err(EmailOptionsError::InvalidHeader(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn metadata_must_fit_postmark_limits() {
+        let options = NewEmailOptions {
+            metadata: (0..11).map(|i| (i.to_string(), "value".into())).collect(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            options.parse(&settings()),
+            Err(EmailOptionsError::TooMuchMetadata)
+        ));
+
+        let options = NewEmailOptions {
+            metadata: vec![("a-very-long-metadata-key".into(), "value".into())],
+            ..Default::default()
+        };
+        assert!(matches!(
+            options.parse(&settings()),
+            Err(EmailOptionsError::InvalidMetadata(_))
+        ));
+    }
+
+    #[test]
+    fn fields_are_split_on_the_first_colon() {
+        assert_eq!(
+            split_fields("X-Campaign: spring\n\nX-Link: https://example.com").unwrap(),
+            [
+                ("X-Campaign".to_owned(), "spring".to_owned()),
+                ("X-Link".to_owned(), "https://example.com".to_owned())
+            ]
+        );
+        assert_err!(split_fields("no colon here"));
+    }
+}
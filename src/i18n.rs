@@ -0,0 +1,223 @@
+//! Translated copy for public pages, emails and the admin UI.
+//!
+//! Messages live in `locales/<locale>/main.ftl` (Fluent syntax) and are
+//! compiled into the binary. A message missing from a catalogue falls back to
+//! English.
+use actix_web::dev::Payload;
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::{
+    FromRequest,
+    HttpRequest,
+};
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{
+    FluentArgs,
+    FluentResource,
+};
+use once_cell::sync::Lazy;
+use std::convert::Infallible;
+use std::future::{
+    ready,
+    Ready,
+};
+
+pub use fluent_bundle::FluentValue;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    Sw,
+}
+
+static EN: Lazy<FluentBundle<FluentResource>> =
+    Lazy::new(|| load(Locale::En, include_str!("../locales/en/main.ftl")));
+static SW: Lazy<FluentBundle<FluentResource>> =
+    Lazy::new(|| load(Locale::Sw, include_str!("../locales/sw/main.ftl")));
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::Sw];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Sw => "sw",
+        }
+    }
+
+    /// Regional variants are accepted, e.g. `sw-KE`.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let language = tag.trim().split(['-', '_']).next()?.to_ascii_lowercase();
+        Self::ALL.into_iter().find(|l| l.as_str() == language)
+    }
+
+    /// The supported locale the client prefers, given the value of its
+    /// `Accept-Language` header.
+    pub fn negotiate(accept_language: &str) -> Self {
+        let mut preferences: Vec<(f32, &str)> = accept_language
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map(|q| q.parse().unwrap_or(0.0))
+                    .unwrap_or(1.0);
+                Some((quality, tag))
+            })
+            .collect();
+        // A stable sort: ties keep the order the client listed them in.
+        preferences.sort_by(|a, b| b.0.total_cmp(&a.0));
+        preferences
+            .into_iter()
+            .filter(|(quality, _)| *quality > 0.0)
+            .find_map(|(_, tag)| Self::parse(tag))
+            .unwrap_or_default()
+    }
+
+    pub fn from_accept_language(request: &HttpRequest) -> Self {
+        request
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|h| h.to_str().ok())
+            .map(Self::negotiate)
+            .unwrap_or_default()
+    }
+
+    pub fn t(&self, id: &str) -> String {
+        self.t_args(id, &[])
+    }
+
+    pub fn t_args(&self, id: &str, args: &[(&str, FluentValue)]) -> String {
+        let args = (!args.is_empty()).then(|| {
+            let mut fluent_args = FluentArgs::new();
+            for (name, value) in args {
+                fluent_args.set(*name, value.clone());
+            }
+            fluent_args
+        });
+        format(self.bundle(), id, args.as_ref())
+            .or_else(|| format(Self::En.bundle(), id, args.as_ref()))
+            .unwrap_or_else(|| {
+                tracing::error!(message_id = %id, "Unknown message.");
+                id.to_owned()
+            })
+    }
+
+    fn bundle(&self) -> &'static FluentBundle<FluentResource> {
+        match self {
+            Self::En => &EN,
+            Self::Sw => &SW,
+        }
+    }
+}
+
+impl std::fmt::Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl FromRequest for Locale {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(Self::from_accept_language(req)))
+    }
+}
+
+fn load(locale: Locale, source: &'static str) -> FluentBundle<FluentResource> {
+    let resource = FluentResource::try_new(source.to_owned())
+        .unwrap_or_else(|_| panic!("Failed to parse the {} catalogue.", locale));
+    let mut bundle = FluentBundle::new_concurrent(vec![locale
+        .as_str()
+        .parse()
+        .expect("Invalid language identifier.")]);
+    // Isolation marks around placeables would end up in links and HTML
+    // attributes.
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .unwrap_or_else(|_| panic!("Duplicate messages in the {} catalogue.", locale));
+    bundle
+}
+
+fn format(
+    bundle: &FluentBundle<FluentResource>,
+    id: &str,
+    args: Option<&FluentArgs>,
+) -> Option<String> {
+    let pattern = bundle.get_message(id)?.value()?;
+    let mut errors = vec![];
+    let message = bundle.format_pattern(pattern, args, &mut errors);
+    if !errors.is_empty() {
+        tracing::error!(message_id = %id, ?errors, "Failed to format a message.");
+    }
+    Some(message.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Locale;
+    use fluent_bundle::FluentResource;
+    use fluent_syntax::ast::Entry;
+    use std::collections::BTreeSet;
+
+    fn message_ids(source: &str) -> BTreeSet<String> {
+        FluentResource::try_new(source.to_owned())
+            .unwrap()
+            .entries()
+            .filter_map(|entry| match entry {
+                Entry::Message(m) => Some(m.id.name.to_owned()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_catalogue_has_the_same_messages() {
+        let en = message_ids(include_str!("../locales/en/main.ftl"));
+        let sw = message_ids(include_str!("../locales/sw/main.ftl"));
+        assert_eq!(en, sw);
+    }
+
+    #[test]
+    fn the_preferred_supported_locale_is_picked() {
+        assert_eq!(Locale::negotiate("sw"), Locale::Sw);
+        assert_eq!(
+            Locale::negotiate("fr-FR, sw-KE;q=0.8, en;q=0.5"),
+            Locale::Sw
+        );
+        assert_eq!(Locale::negotiate("en;q=0.4, sw;q=0.9"), Locale::Sw);
+        assert_eq!(Locale::negotiate("sw, en"), Locale::Sw);
+    }
+
+    #[test]
+    fn english_is_the_fallback() {
+        assert_eq!(Locale::negotiate(""), Locale::En);
+        assert_eq!(Locale::negotiate("*"), Locale::En);
+        assert_eq!(Locale::negotiate("fr, de;q=0.5"), Locale::En);
+        assert_eq!(Locale::negotiate("sw;q=0"), Locale::En);
+    }
+
+    #[test]
+    fn messages_are_formatted_with_their_arguments() {
+        assert_eq!(
+            Locale::Sw.t_args("dashboard-welcome", &[("username", "Ursula".into())]),
+            "Karibu Ursula!"
+        );
+        assert_eq!(
+            Locale::En.t_args(
+                "suppressions-imported",
+                &[("n_added", 2.into()), ("n_invalid", 1.into())]
+            ),
+            "2 new email address(es) suppressed, 1 invalid line(s) skipped."
+        );
+    }
+
+    #[test]
+    fn unknown_messages_are_rendered_as_their_id() {
+        assert_eq!(Locale::Sw.t("no-such-message"), "no-such-message");
+    }
+}
@@ -0,0 +1,91 @@
+use crate::configuration::Settings;
+use crate::startup::get_connection_pool;
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Deletes expired keys every `idempotency.cleanup_interval_seconds`, until
+/// shutdown.
+pub async fn run_cleanup_until_stopped(
+    configuration: Settings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let pool = get_connection_pool(&configuration.database);
+    let settings = configuration.idempotency;
+    loop {
+        if let Err(e) =
+            delete_expired_keys(&pool, settings.retention(), settings.cleanup_batch_size).await
+        {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to delete expired idempotency keys."
+            );
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(settings.cleanup_interval()) => {}
+            _ = shutdown.cancelled() => return Ok(()),
+        }
+    }
+}
+
+/// Deletes the keys saved longer ago than `retention`, `batch_size` at a
+/// time. Returns how many were deleted. The size of the table afterwards is
+/// recorded on the span.
+#[tracing::instrument(
+    name = "Delete expired idempotency keys",
+    skip(pool),
+    fields(
+        n_deleted = tracing::field::Empty,
+        n_remaining = tracing::field::Empty,
+        table_size_bytes = tracing::field::Empty
+    )
+)]
+pub async fn delete_expired_keys(
+    pool: &PgPool,
+    retention: Duration,
+    batch_size: i64,
+) -> Result<u64, anyhow::Error> {
+    let batch_size = batch_size.max(1);
+    let mut n_deleted = 0;
+    loop {
+        // Keys still being processed are locked, and skipped
+        let n_deleted_in_batch = sqlx::query!(
+            r#"
+            DELETE FROM idempotency
+            WHERE (scope, idempotency_key) IN (
+                SELECT scope, idempotency_key
+                FROM idempotency
+                WHERE created_at < now() - make_interval(secs => $1)
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            "#,
+            retention.as_secs_f64(),
+            batch_size
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+        n_deleted += n_deleted_in_batch;
+        if n_deleted_in_batch < batch_size as u64 {
+            break;
+        }
+    }
+    let size = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS "n_rows!",
+            pg_total_relation_size('idempotency') AS "n_bytes!"
+        FROM idempotency
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+    let span = tracing::Span::current();
+    span.record("n_deleted", n_deleted);
+    span.record("n_remaining", size.n_rows);
+    span.record("table_size_bytes", size.n_bytes);
+    tracing::info!("Deleted expired idempotency keys.");
+    Ok(n_deleted)
+}
@@ -0,0 +1,52 @@
+use sha2::{
+    Digest,
+    Sha256,
+};
+
+/// A hash of the payload of a request, to tell a retry from a different
+/// request reusing its idempotency key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestFingerprint(String);
+
+impl RequestFingerprint {
+    /// Hashes `payload` as JSON, with the keys of objects sorted: maps
+    /// iterated in a different order give the same fingerprint.
+    pub fn of(payload: &impl serde::Serialize) -> Result<Self, serde_json::Error> {
+        let payload = serde_json::to_vec(&serde_json::to_value(payload)?)?;
+        Ok(Self(hex::encode(Sha256::digest(payload))))
+    }
+}
+
+impl AsRef<str> for RequestFingerprint {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestFingerprint;
+    use std::collections::{
+        BTreeMap,
+        HashMap,
+    };
+
+    #[test]
+    fn the_order_of_map_entries_does_not_matter() {
+        let entries = [("title", "a"), ("text_content", "b"), ("tag", "c")];
+        let a: HashMap<_, _> = entries.into_iter().collect();
+        let b: BTreeMap<_, _> = entries.into_iter().rev().collect();
+        assert_eq!(
+            RequestFingerprint::of(&a).unwrap(),
+            RequestFingerprint::of(&b).unwrap()
+        );
+    }
+
+    #[test]
+    fn different_payloads_have_different_fingerprints() {
+        assert_ne!(
+            RequestFingerprint::of(&serde_json::json!({ "title": "a" })).unwrap(),
+            RequestFingerprint::of(&serde_json::json!({ "title": "b" })).unwrap()
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Applies the idempotency protocol to the routes it wraps: requests carrying
+//! an idempotency key are processed once per user, or per client IP for
+//! anonymous requests, and retries get the response to the first one.
+//!
+//! Handlers make their changes through the `KeyTransaction` of the request,
+//! committed along with the response. The responses of requests that did not
+//! use it, or that failed, are not saved and their key is released.
+use super::{
+    save_response,
+    try_processing,
+    IdempotencyError,
+    IdempotencyKey,
+    IdempotencyPolicy,
+    IdempotencyScope,
+    NextAction,
+    RequestFingerprint,
+};
+use crate::authentication::UserId;
+use crate::utils::{
+    client_ip,
+    e400,
+};
+use actix_multipart::Multipart;
+use actix_web::body::MessageBody;
+use actix_web::dev::{
+    Payload,
+    ServiceRequest,
+    ServiceResponse,
+};
+use actix_web::error::PayloadError;
+use actix_web::http::header::{
+    HeaderMap,
+    HeaderValue,
+};
+use actix_web::web::{
+    Bytes,
+    BytesMut,
+};
+use actix_web::{
+    web,
+    FromRequest,
+    HttpMessage,
+    HttpRequest,
+};
+use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use futures_util::future::LocalBoxFuture;
+use futures_util::{
+    Stream,
+    StreamExt,
+    TryStreamExt,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use sqlx::{
+    PgPool,
+    Postgres,
+    Transaction,
+};
+use std::future::{
+    ready,
+    Ready,
+};
+use std::pin::Pin;
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+use std::sync::Arc;
+use tokio::sync::{
+    MappedMutexGuard,
+    Mutex,
+    MutexGuard,
+};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// The key of form submissions, e.g. a hidden field generated with the form.
+pub const IDEMPOTENCY_KEY_FIELD: &str = "idempotency_key";
+/// Set on replayed responses.
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+/// The transaction holding the idempotency key of a request.
+#[derive(Clone)]
+pub struct KeyTransaction(Arc<KeyTransactionState>);
+
+struct KeyTransactionState {
+    transaction: Mutex<Option<Transaction<'static, Postgres>>>,
+    used: AtomicBool,
+}
+
+impl KeyTransaction {
+    fn new(transaction: Transaction<'static, Postgres>) -> Self {
+        Self(Arc::new(KeyTransactionState {
+            transaction: Mutex::new(Some(transaction)),
+            used: AtomicBool::new(false),
+        }))
+    }
+
+    pub async fn lock(&self) -> MappedMutexGuard<'_, Transaction<'static, Postgres>> {
+        self.0.used.store(true, Ordering::Relaxed);
+        MutexGuard::map(self.0.transaction.lock().await, |t| {
+            t.as_mut().expect("The response was already saved.")
+        })
+    }
+
+    /// `None` if the handler did not use the transaction.
+    async fn take_if_used(&self) -> Option<Transaction<'static, Postgres>> {
+        let transaction = self.0.transaction.lock().await.take();
+        transaction.filter(|_| self.0.used.load(Ordering::Relaxed))
+    }
+}
+
+/// Fails for requests without an idempotency key.
+impl FromRequest for KeyTransaction {
+    type Error = IdempotencyError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Self>()
+                .cloned()
+                .ok_or(IdempotencyError::MissingKey),
+        )
+    }
+}
+
+/// Reads the key from the `Idempotency-Key` header or, for forms, from the
+/// `idempotency_key` field. Requests without a key are passed through.
+///
+/// Requests with a key, and forms, are read in full before the handler runs,
+/// to fingerprint them: larger ones than `max_payload_size` are rejected. It
+/// should be no larger than what the handler accepts.
+pub fn idempotent<B>(
+    max_payload_size: usize,
+) -> impl Fn(ServiceRequest, Next<B>) -> LocalBoxFuture<'static, Result<ServiceResponse, actix_web::Error>>
+where
+    B: MessageBody + 'static,
+{
+    move |req, next| Box::pin(apply_idempotency(req, next, max_payload_size))
+}
+
+async fn apply_idempotency(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+    max_payload_size: usize,
+) -> Result<ServiceResponse, actix_web::Error> {
+    let user_id = req.extensions().get::<UserId>().copied();
+    let scope = match user_id {
+        Some(user_id) => Some(IdempotencyScope::User(*user_id)),
+        None => client_ip(req.request()).map(IdempotencyScope::Client),
+    };
+    let Some(scope) = scope.filter(|_| !req.method().is_safe()) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let header_key = req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|key| key.to_str().map(ToOwned::to_owned))
+        .transpose()
+        .map_err(|_| {
+            IdempotencyError::InvalidKey("The idempotency key is not valid UTF8.".into())
+        })?;
+    let content_type = req.mime_type()?.map(|m| m.essence_str().to_owned());
+    let is_form = matches!(
+        content_type.as_deref(),
+        Some("application/x-www-form-urlencoded" | "multipart/form-data")
+    );
+    if header_key.is_none() && !is_form {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+
+    let body = read_payload(req.take_payload(), max_payload_size).await?;
+    let (field_key, payload) = parse_payload(req.headers(), content_type.as_deref(), &body).await?;
+    req.set_payload(bytes_to_payload(body));
+    let Some(key) = header_key.or(field_key) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let key =
+        IdempotencyKey::try_from(key).map_err(|e| IdempotencyError::InvalidKey(e.to_string()))?;
+    let fingerprint = RequestFingerprint::of(&(req.method().as_str(), req.path(), payload))
+        .context("Failed to fingerprint the request.")
+        .map_err(IdempotencyError::from)?;
+    let pool = app_data::<PgPool>(&req)?;
+    let policy = app_data::<IdempotencyPolicy>(&req)?;
+
+    let transaction = match try_processing(&pool, &key, &fingerprint, &scope, &policy).await? {
+        NextAction::StartProcessing(transaction) => transaction,
+        NextAction::ReturnSavedResponse(mut saved_response) => {
+            saved_response.headers_mut().insert(
+                IDEMPOTENT_REPLAYED_HEADER.try_into().unwrap(),
+                HeaderValue::from_static("true"),
+            );
+            return Ok(req.into_response(saved_response));
+        }
+    };
+    let key_transaction = KeyTransaction::new(transaction);
+    req.extensions_mut().insert(key_transaction.clone());
+    let response = next.call(req).await?.map_into_boxed_body();
+    response
+        .request()
+        .extensions_mut()
+        .remove::<KeyTransaction>();
+    let status = response.status();
+    let transaction = key_transaction.take_if_used().await;
+    let Some(transaction) =
+        transaction.filter(|_| !status.is_client_error() && !status.is_server_error())
+    else {
+        // Rolled back when dropped
+        return Ok(response);
+    };
+    let (request, response) = response.into_parts();
+    let response = save_response(transaction, &key, &scope, response)
+        .await
+        .map_err(IdempotencyError::from)?;
+    Ok(ServiceResponse::new(request, response))
+}
+
+fn app_data<T: 'static>(req: &ServiceRequest) -> Result<web::Data<T>, IdempotencyError> {
+    let data = req.app_data::<web::Data<T>>().cloned().with_context(|| {
+        format!(
+            "{} is missing from the app data.",
+            std::any::type_name::<T>()
+        )
+    })?;
+    Ok(data)
+}
+
+async fn read_payload(
+    mut payload: Payload,
+    max_payload_size: usize,
+) -> Result<Bytes, actix_web::Error> {
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > max_payload_size {
+            return Err(PayloadError::Overflow.into());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
+
+fn bytes_to_payload(body: Bytes) -> Payload {
+    let stream: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> =
+        Box::pin(futures_util::stream::once(ready(Ok(body))));
+    Payload::from(stream)
+}
+
+/// The key in the form fields if any, and what of the payload is
+/// fingerprinted. Form fields are sorted by name, and files stand for the
+/// hash of their content.
+async fn parse_payload(
+    headers: &HeaderMap,
+    content_type: Option<&str>,
+    body: &Bytes,
+) -> Result<(Option<String>, serde_json::Value), actix_web::Error> {
+    let mut fields: Vec<(String, serde_json::Value)> = match content_type {
+        Some("application/x-www-form-urlencoded") => {
+            serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+                .map_err(e400)?
+                .into_iter()
+                .map(|(name, value)| (name, value.into()))
+                .collect()
+        }
+        Some("multipart/form-data") => {
+            let stream = futures_util::stream::once(ready(Ok::<_, PayloadError>(body.clone())));
+            let mut multipart = Multipart::new(headers, stream);
+            let mut fields = Vec::new();
+            while let Some(mut field) = multipart.try_next().await? {
+                let name = field.name().unwrap_or_default().to_owned();
+                let file_name = field
+                    .content_disposition()
+                    .and_then(|d| d.get_filename())
+                    .map(ToOwned::to_owned);
+                let content_type = field.content_type().map(|t| t.to_string());
+                // Files are hashed as they are read rather than copied.
+                let mut hasher = Sha256::new();
+                let mut text = BytesMut::new();
+                while let Some(chunk) = field.try_next().await? {
+                    if file_name.is_some() {
+                        hasher.update(&chunk);
+                    } else {
+                        text.extend_from_slice(&chunk);
+                    }
+                }
+                let value = match file_name {
+                    Some(file_name) => serde_json::json!({
+                        "file_name": file_name,
+                        "content_type": content_type,
+                        "content_sha256": hex::encode(hasher.finalize()),
+                    }),
+                    None => String::from_utf8_lossy(&text).into_owned().into(),
+                };
+                fields.push((name, value));
+            }
+            fields
+        }
+        Some("application/json") => {
+            let payload = serde_json::from_slice(body)
+                .unwrap_or_else(|_| hex::encode(Sha256::digest(body)).into());
+            return Ok((None, payload));
+        }
+        _ => return Ok((None, hex::encode(Sha256::digest(body)).into())),
+    };
+    // Fields of the same name keep their order
+    fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let key = fields
+        .iter()
+        .find(|(name, _)| name == IDEMPOTENCY_KEY_FIELD)
+        .and_then(|(_, value)| value.as_str())
+        .map(ToOwned::to_owned);
+    Ok((key, serde_json::to_value(fields)?))
+}
@@ -1,10 +1,29 @@
+mod cleanup;
+mod fingerprint;
 mod key;
+mod middleware;
 mod persistence;
+mod scope;
 
+pub use cleanup::{
+    delete_expired_keys,
+    run_cleanup_until_stopped,
+};
+pub use fingerprint::RequestFingerprint;
 pub use key::IdempotencyKey;
+pub use middleware::{
+    idempotent,
+    KeyTransaction,
+    IDEMPOTENCY_KEY_FIELD,
+    IDEMPOTENCY_KEY_HEADER,
+    IDEMPOTENT_REPLAYED_HEADER,
+};
 pub use persistence::get_saved_response;
 pub use persistence::save_response;
 pub use persistence::{
     try_processing,
+    IdempotencyError,
+    IdempotencyPolicy,
     NextAction,
 };
+pub use scope::IdempotencyScope;
@@ -1,15 +1,24 @@
 use super::IdempotencyKey;
+use super::IdempotencyScope;
+use super::RequestFingerprint;
+use crate::routes::error_chain_fmt;
 use actix_web::body::to_bytes;
 use actix_web::http::StatusCode;
-use actix_web::HttpResponse;
-use anyhow::Ok;
+use actix_web::{
+    HttpResponse,
+    ResponseError,
+};
+use anyhow::Context;
 use sqlx::postgres::PgHasArrayType;
 use sqlx::PgPool;
 use sqlx::{
     Postgres,
     Transaction,
 };
-use uuid::Uuid;
+use std::time::Duration;
+
+/// The SQLSTATE of `lock_timeout` running out.
+const LOCK_NOT_AVAILABLE: &str = "55P03";
 
 #[derive(Debug, sqlx::Type)]
 #[sqlx(type_name = "header_pair")]
@@ -24,42 +33,69 @@ impl PgHasArrayType for HeaderPairRecord {
     }
 }
 
-pub async fn get_saved_response(
+/// A request saved along with its key.
+struct SavedRequest {
+    /// Unset for requests saved before fingerprints were.
+    fingerprint: Option<String>,
+    /// Unset while the request is being processed.
+    response: Option<HttpResponse>,
+}
+
+async fn get_saved_request(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
-) -> Result<Option<HttpResponse>, anyhow::Error> {
-    let saved_response = sqlx::query!(
+    scope: &IdempotencyScope,
+) -> Result<Option<SavedRequest>, anyhow::Error> {
+    let saved_request = sqlx::query!(
         r#"
         SELECT
-            response_status_code as "response_status_code!",
-            response_headers as "response_headers!: Vec<HeaderPairRecord>",
-            response_body as "response_body!"
+            request_fingerprint,
+            response_status_code,
+            response_headers as "response_headers: Vec<HeaderPairRecord>",
+            response_body
         FROM idempotency
         WHERE
-            user_id = $1 AND
+            scope = $1 AND
             idempotency_key = $2
         "#,
-        user_id,
+        scope.to_string(),
         idempotency_key.as_ref()
     )
     .fetch_optional(pool)
     .await?;
-    if let Some(r) = saved_response {
-        let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
-        let mut response = HttpResponse::build(status_code);
-        for HeaderPairRecord { name, value } in r.response_headers {
-            response.append_header((name, value));
+    let Some(r) = saved_request else {
+        return Ok(None);
+    };
+    let response = match (r.response_status_code, r.response_headers, r.response_body) {
+        (Some(status_code), Some(headers), Some(body)) => {
+            let status_code = StatusCode::from_u16(status_code.try_into()?)?;
+            let mut response = HttpResponse::build(status_code);
+            for HeaderPairRecord { name, value } in headers {
+                response.append_header((name, value));
+            }
+            Some(response.body(body))
         }
-        Ok(Some(response.body(r.response_body)))
-    } else {
-        Ok(None)
-    }
+        _ => None,
+    };
+    Ok(Some(SavedRequest {
+        fingerprint: r.request_fingerprint,
+        response,
+    }))
+}
+
+pub async fn get_saved_response(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    scope: &IdempotencyScope,
+) -> Result<Option<HttpResponse>, anyhow::Error> {
+    let saved_request = get_saved_request(pool, idempotency_key, scope).await?;
+    Ok(saved_request.and_then(|r| r.response))
 }
+
 pub async fn save_response(
     mut transaction: Transaction<'static, Postgres>,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
+    scope: &IdempotencyScope,
     http_response: HttpResponse,
 ) -> Result<HttpResponse, anyhow::Error> {
     let (response_head, body) = http_response.into_parts();
@@ -82,10 +118,10 @@ pub async fn save_response(
             response_headers = $4,
             response_body = $5
         WHERE
-            user_id = $1 AND
+            scope = $1 AND
             idempotency_key = $2
         "#,
-        user_id,
+        scope.to_string(),
         idempotency_key.as_ref(),
         status_code,
         headers,
@@ -104,34 +140,157 @@ pub enum NextAction {
     ReturnSavedResponse(HttpResponse),
 }
 
+/// How idempotent requests are handled.
+#[derive(Clone, Copy, Debug)]
+pub struct IdempotencyPolicy {
+    /// How long the response to a request is replayed for. Past that, its key
+    /// is treated as new.
+    pub retention: Duration,
+    /// How long a request waits for another one with the same key to
+    /// complete, before giving up with a conflict.
+    pub in_flight_timeout: Duration,
+}
+
+#[derive(thiserror::Error)]
+pub enum IdempotencyError {
+    #[error("The request has no idempotency key.")]
+    MissingKey,
+    #[error("{0}")]
+    InvalidKey(String),
+    #[error("A request with the same idempotency key is still being processed.")]
+    InFlight,
+    #[error("The idempotency key was already used for a different request.")]
+    KeyReused,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for IdempotencyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IdempotencyError {
+    fn code(&self) -> &'static str {
+        match self {
+            IdempotencyError::MissingKey | IdempotencyError::InvalidKey(_) => "validation_error",
+            IdempotencyError::InFlight => "conflict",
+            IdempotencyError::KeyReused => "idempotency_key_reused",
+            IdempotencyError::UnexpectedError(_) => "internal_error",
+        }
+    }
+}
+
+/// Rendered like the errors of the API.
+impl ResponseError for IdempotencyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            IdempotencyError::MissingKey | IdempotencyError::InvalidKey(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            IdempotencyError::InFlight => StatusCode::CONFLICT,
+            IdempotencyError::KeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            IdempotencyError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let message = match self {
+            IdempotencyError::UnexpectedError(_) => "Something went wrong.".to_string(),
+            e => e.to_string(),
+        };
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": {
+                "code": self.code(),
+                "message": message
+            }
+        }))
+    }
+}
+
+/// The key is held by the returned transaction until the response is saved:
+/// if processing fails, the transaction is rolled back and the key released.
+/// Meanwhile, requests with the same key wait for it, up to
+/// `policy.in_flight_timeout`.
+///
+/// Requests reusing a key with a different `fingerprint` are rejected.
 pub async fn try_processing(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
-) -> Result<NextAction, anyhow::Error> {
-    let mut transaction = pool.begin().await?;
+    fingerprint: &RequestFingerprint,
+    scope: &IdempotencyScope,
+    policy: &IdempotencyPolicy,
+) -> Result<NextAction, IdempotencyError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to start a transaction.")?;
+    sqlx::query!(
+        "SELECT set_config('lock_timeout', $1, true)",
+        format!("{}ms", policy.in_flight_timeout.as_millis())
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to set the lock timeout.")?;
     let n_inserted_rows = sqlx::query!(
         r#"
         INSERT INTO idempotency (
-            user_id,
+            scope,
             idempotency_key,
+            request_fingerprint,
+            user_id,
             created_at
         )
-        VALUES ($1, $2, now())
-        ON CONFLICT DO NOTHING
+        VALUES ($1, $2, $4, $5, now())
+        ON CONFLICT (scope, idempotency_key) DO UPDATE
+        SET
+            request_fingerprint = $4,
+            created_at = now(),
+            response_status_code = NULL,
+            response_headers = NULL,
+            response_body = NULL
+        WHERE idempotency.created_at < now() - make_interval(secs => $3)
         "#,
-        user_id,
-        idempotency_key.as_ref()
+        scope.to_string(),
+        idempotency_key.as_ref(),
+        policy.retention.as_secs_f64(),
+        fingerprint.as_ref(),
+        scope.user_id()
     )
     .execute(&mut *transaction)
-    .await?
-    .rows_affected();
+    .await;
+    let n_inserted_rows = match n_inserted_rows {
+        Ok(result) => result.rows_affected(),
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(LOCK_NOT_AVAILABLE) => {
+            return Err(IdempotencyError::InFlight)
+        }
+        Err(e) => {
+            return Err(anyhow::Error::from(e)
+                .context("Failed to store the key.")
+                .into())
+        }
+    };
     if n_inserted_rows > 0 {
-        Ok(NextAction::StartProcessing(transaction))
-    } else {
-        let saved_response = get_saved_response(pool, idempotency_key, user_id)
-            .await?
-            .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
-        Ok(NextAction::ReturnSavedResponse(saved_response))
+        // Processing is not held to the timeout
+        sqlx::query!("SET LOCAL lock_timeout TO DEFAULT")
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to reset the lock timeout.")?;
+        return Ok(NextAction::StartProcessing(transaction));
+    }
+    let saved_request = get_saved_request(pool, idempotency_key, scope)
+        .await?
+        .context("The key was neither stored nor found.")?;
+    if saved_request
+        .fingerprint
+        .is_some_and(|f| f != fingerprint.as_ref())
+    {
+        return Err(IdempotencyError::KeyReused);
+    }
+    // A key without a response is still being processed
+    match saved_request.response {
+        Some(saved_response) => Ok(NextAction::ReturnSavedResponse(saved_response)),
+        None => Err(IdempotencyError::InFlight),
     }
 }
@@ -0,0 +1,28 @@
+use uuid::Uuid;
+
+/// Whom an idempotency key belongs to: keys only have to be unique within
+/// their scope.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IdempotencyScope {
+    User(Uuid),
+    /// Anonymous requests, by client IP.
+    Client(String),
+}
+
+impl IdempotencyScope {
+    pub fn user_id(&self) -> Option<Uuid> {
+        match self {
+            IdempotencyScope::User(user_id) => Some(*user_id),
+            IdempotencyScope::Client(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for IdempotencyScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IdempotencyScope::User(user_id) => write!(f, "user:{user_id}"),
+            IdempotencyScope::Client(ip) => write!(f, "client:{ip}"),
+        }
+    }
+}
@@ -1,86 +1,390 @@
 use crate::{
+    attachment::{
+        load_attachments,
+        BlobStore,
+    },
     configuration::Settings,
+    migrations::check_schema,
     startup::get_connection_pool,
 };
 use crate::{
     domain::SubscriberEmail,
     email_client::EmailClient,
+    email_options::get_email_options,
+    subject_test::{
+        decide_due_subject_tests,
+        record_delivery,
+        subject_line,
+        track_email,
+    },
+    suppression::is_suppressed,
+    throttle::Throttle,
 };
+use anyhow::Context;
+use sqlx::postgres::PgListener;
 use sqlx::{
     PgPool,
     Postgres,
     Transaction,
 };
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 use tracing::{
     field::display,
+    Instrument,
     Span,
 };
 use uuid::Uuid;
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
-    let connection_pool = get_connection_pool(&configuration.database);
-    let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+/// The channel workers listen on to learn about new deliveries.
+const NEW_TASKS_CHANNEL: &str = "issue_delivery_queue";
+
+/// Wakes up idle workers once the transaction commits. Call it whenever
+/// deliveries are queued or released.
+pub async fn notify_new_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("SELECT pg_notify($1, '')", NEW_TASKS_CHANNEL)
+        .execute(transaction.as_mut())
+        .await?;
+    Ok(())
+}
+
+/// What the workers share.
+#[derive(Clone)]
+struct Worker {
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    blob_store: Arc<dyn BlobStore>,
+    throttle: Arc<Throttle>,
+    base_url: String,
+    /// How long to wait for a notification before looking at an empty queue
+    /// again, in case one was missed.
+    poll_interval: Duration,
+    /// How long to wait after a failure.
+    retry_interval: Duration,
+}
+
+/// Runs the configured number of workers until shutdown. Deliveries in
+/// progress are completed, and their transaction committed, before exiting.
+/// If a worker fails, the others are shut down.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let worker = Worker {
+        pool: get_connection_pool(&configuration.database),
+        email_client: Arc::new(configuration.email_client.client()),
+        blob_store: Arc::new(configuration.attachments.store()),
+        throttle: Arc::new(configuration.delivery.throttle()),
+        base_url: configuration.application.base_url,
+        poll_interval: configuration.delivery.poll_interval(),
+        retry_interval: configuration.delivery.retry_interval(),
+    };
+    check_schema(&worker.pool)
+        .await
+        .context("Refusing to deliver with a database schema this binary does not expect.")?;
+    let (wake_up, wake_up_receiver) = watch::channel(());
+    let mut workers = JoinSet::new();
+    for worker_id in 0..configuration.delivery.n_workers.max(1) {
+        workers.spawn(
+            worker_loop(worker.clone(), wake_up_receiver.clone(), shutdown.clone())
+                .instrument(tracing::info_span!("Delivery worker", worker_id)),
+        );
+    }
+    workers.spawn(
+        decide_subject_tests_until_stopped(worker.pool.clone(), shutdown.clone())
+            .instrument(tracing::info_span!("Subject line tests timer")),
+    );
+    workers.spawn(
+        listen_for_new_tasks(
+            worker.pool.clone(),
+            wake_up,
+            worker.retry_interval,
+            shutdown.clone(),
+        )
+        .instrument(tracing::info_span!("Delivery queue listener")),
+    );
+    let mut outcome = Ok(());
+    while let Some(worker_outcome) = workers.join_next().await {
+        if let Err(e) = worker_outcome.map_err(anyhow::Error::from).and_then(|o| o) {
+            shutdown.cancel();
+            outcome = Err(e);
+        }
+    }
+    outcome
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
+/// Forwards notifications of new deliveries to the workers. Workers fall back
+/// to polling while the database cannot be listened to.
+async fn listen_for_new_tasks(
+    pool: PgPool,
+    wake_up: watch::Sender<()>,
+    retry_interval: Duration,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
+        let mut listener = match listen(&pool).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to listen for new deliveries."
+                );
+                sleep_until_shutdown(retry_interval, &shutdown).await;
+                continue;
+            }
+        };
+        loop {
+            let notification = tokio::select! {
+                n = listener.try_recv() => n,
+                _ = shutdown.cancelled() => break,
+            };
+            match notification {
+                Ok(Some(_)) => {
+                    wake_up.send_replace(());
+                }
+                // The listener reconnects on its own, notifications may have
+                // been missed in the meantime.
+                Ok(None) => {
+                    tracing::warn!("Lost the connection listening for new deliveries.");
+                    wake_up.send_replace(());
+                }
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to receive notifications of new deliveries."
+                    );
+                    sleep_until_shutdown(retry_interval, &shutdown).await;
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn listen(pool: &PgPool) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(NEW_TASKS_CHANNEL).await?;
+    Ok(listener)
+}
+
+/// How often we look for subject line tests that are due a winner.
+const SUBJECT_TESTS_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Runs next to the workers, which the deliveries released by a decision
+/// wake up.
+async fn decide_subject_tests_until_stopped(
+    pool: PgPool,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
+        // Errors are logged, the next check will retry.
+        let _ = decide_due_subject_tests(&pool).await;
+        sleep_until_shutdown(SUBJECT_TESTS_CHECK_INTERVAL, &shutdown).await;
+    }
+    Ok(())
+}
+
+async fn worker_loop(
+    worker: Worker,
+    mut wake_up: watch::Receiver<()>,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_cancelled() {
+        // Notifications sent from now on are for deliveries we may not see.
+        wake_up.borrow_and_update();
+        // A task is never interrupted: it commits or rolls back on its own.
+        match try_execute_task(
+            &worker.pool,
+            &worker.email_client,
+            &*worker.blob_store,
+            &worker.throttle,
+            &worker.base_url,
+        )
+        .await
+        {
             Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(worker.poll_interval) => {}
+                    Ok(()) = wake_up.changed() => {}
+                    _ = shutdown.cancelled() => {}
+                }
+            }
+            Ok(ExecutionOutcome::Throttled(wait)) => {
+                sleep_until_shutdown(wait, &shutdown).await;
             }
             Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                sleep_until_shutdown(worker.retry_interval, &shutdown).await;
             }
             Ok(ExecutionOutcome::TaskCompleted) => {}
         }
     }
+    tracing::info!("Delivery worker stopped.");
+    Ok(())
+}
+
+/// Sleeps, unless shutdown comes first.
+async fn sleep_until_shutdown(duration: Duration, shutdown: &CancellationToken) {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => {}
+        _ = shutdown.cancelled() => {}
+    }
 }
 
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
+    /// Only deliveries to throttled domains are pending. Try again after the
+    /// wait.
+    Throttled(Duration),
+}
+
+/// What happened to a delivery, as tallied in the delivery report of the
+/// issue variant it was for.
+#[derive(Clone, Copy, Debug)]
+enum DeliveryOutcome {
+    Sent,
+    Failed,
+    Skipped,
+}
+
+impl DeliveryOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sent => "sent",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
 }
 
 #[tracing::instrument(
     skip_all,
     fields(
         newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
+        subscriber_email=tracing::field::Empty,
+        locale=tracing::field::Empty,
+        recipient_domain=tracing::field::Empty,
+        throttled_ms=tracing::field::Empty
     ),
     err
 )]
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    blob_store: &dyn BlobStore,
+    throttle: &Throttle,
+    base_url: &str,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
-        return Ok(ExecutionOutcome::EmptyQueue);
-    }
-    let (transaction, issue_id, email) = task.unwrap();
+    let (throttled_domains, next_ready) = throttle.throttled_domains();
+    let task = dequeue_task(pool, &throttled_domains).await?;
+    let Some((
+        mut transaction,
+        Task {
+            issue_id,
+            email,
+            domain,
+            locale,
+            subject_variant,
+        },
+    )) = task
+    else {
+        return match next_ready {
+            Some(wait) if has_pending_tasks(pool).await? => {
+                tracing::info!(
+                    domains = ?throttled_domains,
+                    wait_ms = wait.as_millis(),
+                    "Every pending delivery is to a throttled domain.",
+                );
+                Ok(ExecutionOutcome::Throttled(wait))
+            }
+            _ => Ok(ExecutionOutcome::EmptyQueue),
+        };
+    };
     Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
-    match SubscriberEmail::parse(email.clone()) {
+        .record("newsletter_issue_id", display(issue_id))
+        .record("subscriber_email", display(&email))
+        .record("locale", display(&locale))
+        .record("recipient_domain", display(&domain));
+    // The address may have been suppressed after the issue was published.
+    if is_suppressed(pool, &email).await? {
+        tracing::info!("Skipping a suppressed email address.");
+        delete_task(
+            transaction,
+            issue_id,
+            &email,
+            &locale,
+            DeliveryOutcome::Skipped,
+        )
+        .await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let outcome = match SubscriberEmail::parse(email.clone()) {
         Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
+            // Another task may have used up the allowance of the domain since
+            // it was dequeued.
+            let _permit = match throttle.try_acquire(&domain) {
+                Ok(permit) => permit,
+                Err(wait) => {
+                    transaction.rollback().await?;
+                    return Ok(ExecutionOutcome::Throttled(wait));
+                }
+            };
+            let waited = throttle.until_ready().await;
+            if !waited.is_zero() {
+                Span::current().record("throttled_ms", waited.as_millis());
+                tracing::info!(
+                    wait_ms = waited.as_millis(),
+                    "Waited for the global send rate."
+                );
+            }
+            let issue = get_issue(pool, issue_id, &locale).await?;
+            let options = get_email_options(pool, issue_id).await?;
+            let attachments = load_attachments(pool, blob_store, issue_id).await?;
+            let subject = subject_line(pool, issue_id, subject_variant)
+                .await?
+                .unwrap_or(issue.title);
+            // Only deliveries to the sample of a subject line test are
+            // tracked.
+            let tracking = subject_variant.map(|variant_index| (Uuid::new_v4(), variant_index));
+            let html_content = match tracking {
+                Some((tracking_id, _)) => track_email(&issue.html_content, base_url, tracking_id),
+                None => issue.html_content,
+            };
+            match email_client
+                .send_email_with(
                     &email,
-                    &issue.title,
-                    &issue.html_content,
+                    &subject,
+                    &html_content,
                     &issue.text_content,
+                    &options,
+                    &attachments,
                 )
                 .await
             {
-                tracing::error!(
-                    error.cause_chain = ?e,
-                    error.message = %e,
-                    "Failed to deliver issue to a confirmed subscriber. \
-                        Skipping.",
-                );
+                Ok(()) => {
+                    if let Some((tracking_id, variant_index)) = tracking {
+                        record_delivery(&mut transaction, tracking_id, issue_id, variant_index)
+                            .await?;
+                    }
+                    DeliveryOutcome::Sent
+                }
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscriber. \
+                            Skipping.",
+                    );
+                    DeliveryOutcome::Failed
+                }
             }
         }
         Err(e) => {
@@ -90,47 +394,103 @@ pub async fn try_execute_task(
                 "Skipping a confirmed subscriber. \
                     Their stored contact details are invalid",
             );
+            DeliveryOutcome::Skipped
         }
-    }
-    delete_task(transaction, issue_id, &email).await?;
+    };
+    delete_task(transaction, issue_id, &email, &locale, outcome).await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+struct Task {
+    issue_id: Uuid,
+    email: String,
+    /// Lowercase, what deliveries are throttled by.
+    domain: String,
+    /// The issue variant picked for the subscriber.
+    locale: String,
+    /// The subject line assigned to the subscriber, if they are part of the
+    /// sample of a subject line test.
+    subject_variant: Option<i32>,
+}
+
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+    throttled_domains: &[String],
+) -> Result<Option<(PgTransaction, Task)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
     let r = sqlx::query!(
         r#"
-        SELECT newsletter_issue_id, subscriber_email
+        SELECT
+            newsletter_issue_id,
+            subscriber_email,
+            lower(split_part(subscriber_email, '@', -1)) AS "domain!",
+            locale,
+            subject_variant
         FROM issue_delivery_queue
+        WHERE
+            NOT held AND
+            lower(split_part(subscriber_email, '@', -1)) <> ALL($1)
         FOR UPDATE
         SKIP LOCKED
         LIMIT 1
         "#,
+        throttled_domains
     )
     .fetch_optional(&mut *transaction)
     .await?;
     if let Some(r) = r {
         Ok(Some((
             transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
+            Task {
+                issue_id: r.newsletter_issue_id,
+                email: r.subscriber_email,
+                domain: r.domain,
+                locale: r.locale,
+                subject_variant: r.subject_variant,
+            },
         )))
     } else {
         Ok(None)
     }
 }
 
+async fn has_pending_tasks(pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM issue_delivery_queue WHERE NOT held) AS "exists!""#
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(r.exists)
+}
+
 #[tracing::instrument(skip_all)]
 async fn delete_task(
     mut transaction: PgTransaction,
     issue_id: Uuid,
     email: &str,
+    locale: &str,
+    outcome: DeliveryOutcome,
 ) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issue_variants
+        SET
+            n_sent = n_sent + ($3 = 'sent')::INTEGER,
+            n_failed = n_failed + ($3 = 'failed')::INTEGER,
+            n_skipped = n_skipped + ($3 = 'skipped')::INTEGER
+        WHERE
+            newsletter_issue_id = $1 AND
+            locale = $2
+        "#,
+        issue_id,
+        locale,
+        outcome.as_str()
+    )
+    .execute(&mut *transaction)
+    .await?;
     sqlx::query!(
         r#"
         DELETE FROM issue_delivery_queue
@@ -153,17 +513,28 @@ struct NewsletterIssue {
     html_content: String,
 }
 
+/// The variant of the issue in `locale`, or its default variant if it was not
+/// translated to it.
 #[tracing::instrument(skip_all)]
-async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+async fn get_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+    locale: &str,
+) -> Result<NewsletterIssue, anyhow::Error> {
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-        SELECT title, text_content, html_content
-        FROM newsletter_issues
+        SELECT v.title, v.text_content, v.html_content
+        FROM newsletter_issue_variants v
+        JOIN newsletter_issues i USING (newsletter_issue_id)
         WHERE
-            newsletter_issue_id = $1
+            v.newsletter_issue_id = $1 AND
+            v.locale IN ($2, i.default_locale)
+        ORDER BY v.locale = $2 DESC
+        LIMIT 1
         "#,
-        issue_id
+        issue_id,
+        locale
     )
     .fetch_one(pool)
     .await?;
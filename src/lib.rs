@@ -1,11 +1,25 @@
+pub mod admin;
+pub mod attachment;
+pub mod audit;
 pub mod authentication;
 pub mod configuration;
+pub mod consent;
+pub mod deliverability;
 pub mod domain;
 pub mod email_client;
+pub mod email_options;
+pub mod i18n;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod migrations;
+pub mod mime_message;
 pub mod routes;
 pub mod session_state;
+pub mod signup_protection;
 pub mod startup;
+pub mod subject_test;
+pub mod subscriber_data;
+pub mod suppression;
 pub mod telemetry;
+pub mod throttle;
 pub mod utils;
@@ -1,6 +1,17 @@
+use anyhow::Context;
+use clap::{
+    Parser,
+    Subcommand,
+};
+use prod_craft::admin::AdminArgs;
 use prod_craft::configuration::get_configuration;
+use prod_craft::idempotency::run_cleanup_until_stopped;
 use prod_craft::issue_delivery_worker::run_worker_until_stopped;
-use prod_craft::startup::Application;
+use prod_craft::migrations::migrate_database;
+use prod_craft::startup::{
+    get_connection_pool,
+    Application,
+};
 use prod_craft::telemetry::{
     get_subscriber,
     init_subscriber,
@@ -9,22 +20,126 @@ use std::fmt::{
     Debug,
     Display,
 };
-use tokio::task::JoinError;
+use tokio::signal::unix::{
+    signal,
+    SignalKind,
+};
+use tokio::task::{
+    JoinError,
+    JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "The newsletter web application and its delivery workers."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the web application and the API.
+    Serve,
+    /// Deliver newsletter issues.
+    Worker,
+    /// Serve and deliver in the same process. The default.
+    All,
+    /// Apply the database migrations, then exit.
+    Migrate,
+    /// Run an operational task, then exit.
+    Admin(AdminArgs),
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let subscriber = get_subscriber("prod_craft".into(), "info".into(), std::io::stdout);
-    init_subscriber(subscriber);
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::All);
+    // Admin commands print their outcome to stdout.
+    if let Command::Admin(_) = command {
+        init_subscriber(get_subscriber(
+            "prod_craft".into(),
+            "info".into(),
+            std::io::stderr,
+        ));
+    } else {
+        init_subscriber(get_subscriber(
+            "prod_craft".into(),
+            "info".into(),
+            std::io::stdout,
+        ));
+    }
 
     let configuration = get_configuration().expect("Failed to read configuration");
-    let application = Application::build(configuration.clone()).await?;
-    let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    if let Command::Admin(args) = command {
+        let operator = std::env::var("USER").unwrap_or_else(|_| "unknown".into());
+        let report = prod_craft::admin::execute(
+            args.command,
+            &operator,
+            &configuration,
+            &mut std::io::stdin().lock(),
+        )
+        .await?;
+        print!("{}", report.render(args.format));
+        return Ok(());
+    }
+    if let Command::Migrate = command {
+        let pool = get_connection_pool(&configuration.database);
+        migrate_database(&pool)
+            .await
+            .context("Failed to migrate the database.")?;
+        tracing::info!("The database is up to date.");
+        return Ok(());
+    }
 
+    let shutdown = CancellationToken::new();
+    let mut tasks: Vec<(&str, JoinHandle<anyhow::Result<()>>)> = Vec::new();
+    if let Command::Serve | Command::All = command {
+        let application = Application::build(configuration.clone()).await?;
+        let shutdown = shutdown.clone();
+        tasks.push((
+            "API",
+            tokio::spawn(async move { Ok(application.run_until_shutdown(shutdown).await?) }),
+        ));
+    }
+    if let Command::Worker | Command::All = command {
+        tasks.push((
+            "Idempotency cleanup",
+            tokio::spawn(run_cleanup_until_stopped(
+                configuration.clone(),
+                shutdown.clone(),
+            )),
+        ));
+        tasks.push((
+            "Background worker",
+            tokio::spawn(run_worker_until_stopped(configuration, shutdown.clone())),
+        ));
+    }
+    tokio::spawn(shut_down_on_signal(shutdown.clone()));
+
+    // Whichever task exits first, the others are shut down gracefully.
+    futures_util::future::join_all(tasks.into_iter().map(|(task_name, task)| {
+        let shutdown = shutdown.clone();
+        async move {
+            report_exit(task_name, task.await);
+            shutdown.cancel();
+        }
+    }))
+    .await;
+    Ok(())
+}
+
+async fn shut_down_on_signal(shutdown: CancellationToken) -> std::io::Result<()> {
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
     tokio::select! {
-        o = application_task => report_exit("API", o),
-        o = worker_task => report_exit("Background worker", o),
-    };
+        _ = sigterm.recv() => tracing::info!("Received SIGTERM, shutting down."),
+        _ = sigint.recv() => tracing::info!("Received SIGINT, shutting down."),
+    }
+    shutdown.cancel();
     Ok(())
 }
 
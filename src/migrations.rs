@@ -0,0 +1,145 @@
+//! The migrations under `migrations/`, embedded in the binary.
+//!
+//! Deployments apply them with `prod_craft migrate`, or on startup when
+//! `application.migrate_on_startup` is set. Either way, Postgres advisory
+//! locks keep replicas starting at the same time from applying them twice.
+//! Before serving, the schema is checked against the migrations the binary
+//! was built with.
+use sqlx::migrate::{
+    AppliedMigration,
+    Migrate,
+    MigrateError,
+    Migrator,
+};
+use sqlx::PgPool;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SchemaError {
+    #[error("The database is behind, migrations {0:?} have not been applied.")]
+    Behind(Vec<i64>),
+    #[error("The database is ahead, migrations {0:?} are unknown to this binary.")]
+    Ahead(Vec<i64>),
+    #[error("Migrations {0:?} were changed after being applied.")]
+    Modified(Vec<i64>),
+    #[error("Migration {0} failed part way through.")]
+    Dirty(i64),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CheckSchemaError {
+    #[error(transparent)]
+    Mismatch(#[from] SchemaError),
+    #[error("Failed to list the applied migrations.")]
+    UnexpectedError(#[from] MigrateError),
+}
+
+/// Applies the migrations the database is missing, while holding an advisory
+/// lock.
+#[tracing::instrument(name = "Migrate the database", skip(pool))]
+pub async fn migrate_database(pool: &PgPool) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await
+}
+
+/// Fails unless the database has exactly the migrations of this binary
+/// applied.
+#[tracing::instrument(name = "Check the database schema", skip(pool))]
+pub async fn check_schema(pool: &PgPool) -> Result<(), CheckSchemaError> {
+    let mut connection = pool.acquire().await.map_err(MigrateError::from)?;
+    connection.ensure_migrations_table().await?;
+    if let Some(version) = connection.dirty_version().await? {
+        return Err(SchemaError::Dirty(version).into());
+    }
+    let applied = connection.list_applied_migrations().await?;
+    let known: Vec<AppliedMigration> = MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| AppliedMigration {
+            version: m.version,
+            checksum: m.checksum.clone(),
+        })
+        .collect();
+    compare(&applied, &known)?;
+    Ok(())
+}
+
+fn compare(applied: &[AppliedMigration], known: &[AppliedMigration]) -> Result<(), SchemaError> {
+    let versions = |migrations: &[AppliedMigration], others: &[AppliedMigration]| -> Vec<i64> {
+        migrations
+            .iter()
+            .filter(|m| !others.iter().any(|o| o.version == m.version))
+            .map(|m| m.version)
+            .collect()
+    };
+    let unknown = versions(applied, known);
+    if !unknown.is_empty() {
+        return Err(SchemaError::Ahead(unknown));
+    }
+    let missing = versions(known, applied);
+    if !missing.is_empty() {
+        return Err(SchemaError::Behind(missing));
+    }
+    let modified: Vec<i64> = applied
+        .iter()
+        .filter(|a| {
+            known
+                .iter()
+                .any(|k| k.version == a.version && k.checksum != a.checksum)
+        })
+        .map(|a| a.version)
+        .collect();
+    if !modified.is_empty() {
+        return Err(SchemaError::Modified(modified));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compare,
+        SchemaError,
+    };
+    use claim::assert_ok;
+    use sqlx::migrate::AppliedMigration;
+
+    fn migration(version: i64, checksum: &'static [u8]) -> AppliedMigration {
+        AppliedMigration {
+            version,
+            checksum: checksum.into(),
+        }
+    }
+
+    #[test]
+    fn the_schema_matches_when_every_migration_is_applied() {
+        let migrations = [migration(1, b"a"), migration(2, b"b")];
+        assert_ok!(compare(&migrations, &migrations));
+    }
+
+    #[test]
+    fn a_database_missing_migrations_is_behind() {
+        let known = [migration(1, b"a"), migration(2, b"b"), migration(3, b"c")];
+        assert_eq!(
+            compare(&known[..1], &known),
+            Err(SchemaError::Behind(vec![2, 3]))
+        );
+    }
+
+    #[test]
+    fn a_database_with_unknown_migrations_is_ahead() {
+        let applied = [migration(1, b"a"), migration(2, b"b")];
+        assert_eq!(
+            compare(&applied, &applied[..1]),
+            Err(SchemaError::Ahead(vec![2]))
+        );
+    }
+
+    #[test]
+    fn migrations_changed_after_being_applied_are_reported() {
+        assert_eq!(
+            compare(&[migration(1, b"a")], &[migration(1, b"b")]),
+            Err(SchemaError::Modified(vec![1]))
+        );
+    }
+}
@@ -0,0 +1,395 @@
+//! Emails written out as MIME messages (RFC 5322 and 2045), for transports
+//! that take whole messages rather than the fields of an HTTP API.
+//!
+//! The text and HTML bodies are alternatives of each other, images embedded
+//! in the HTML are related to it, and other attachments are mixed in next to
+//! them.
+use crate::email_client::{
+    Attachment,
+    OutgoingEmail,
+};
+use uuid::Uuid;
+
+/// Encoded words can be at most 75 characters long. The base64 of 39 bytes
+/// leaves room for the name of the header on the first line.
+const ENCODED_WORD_BYTES: usize = 39;
+const BASE64_LINE_LENGTH: usize = 76;
+
+/// The message, with CRLF line endings.
+pub fn render(email: &OutgoingEmail<'_>) -> String {
+    let options = email.options;
+    let mut headers = vec![
+        ("Date".to_owned(), chrono::Utc::now().to_rfc2822()),
+        ("Message-ID".to_owned(), message_id(email.from)),
+        ("From".to_owned(), mailbox(email.from)),
+        ("To".to_owned(), mailbox(email.to)),
+        ("Subject".to_owned(), unstructured(email.subject)),
+    ];
+    if let Some(reply_to) = &options.reply_to {
+        headers.push(("Reply-To".to_owned(), mailbox(reply_to)));
+    }
+    for header in &options.headers {
+        headers.push((header_name(&header.name), unstructured(&header.value)));
+    }
+    // The headers Postmark reads the options of messages sent over SMTP from.
+    if let Some(tag) = &options.tag {
+        headers.push(("X-PM-Tag".to_owned(), unstructured(tag)));
+    }
+    for (key, value) in &options.metadata {
+        headers.push((
+            format!("X-PM-Metadata-{}", header_name(key)),
+            unstructured(value),
+        ));
+    }
+    if let Some(stream) = &options.message_stream {
+        headers.push(("X-PM-Message-Stream".to_owned(), unstructured(stream)));
+    }
+    headers.push(("MIME-Version".to_owned(), "1.0".to_owned()));
+
+    let mut body = multipart(
+        "alternative",
+        vec![
+            Part::text("text/plain", email.text_body),
+            Part::text("text/html", email.html_body),
+        ],
+    );
+    let (inline, attached): (Vec<&Attachment>, Vec<&Attachment>) =
+        email.attachments.iter().partition(|a| a.inline);
+    if !inline.is_empty() {
+        let mut parts = vec![body];
+        parts.extend(inline.into_iter().map(Part::attachment));
+        body = multipart("related", parts);
+    }
+    if !attached.is_empty() {
+        let mut parts = vec![body];
+        parts.extend(attached.into_iter().map(Part::attachment));
+        body = multipart("mixed", parts);
+    }
+
+    let mut message = String::new();
+    for (name, value) in headers {
+        message.push_str(&format!("{name}: {value}\r\n"));
+    }
+    body.write(&mut message);
+    message
+}
+
+struct Part {
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Part {
+    fn text(content_type: &str, text: &str) -> Self {
+        Self {
+            headers: vec![
+                ("Content-Type", format!("{content_type}; charset=utf-8")),
+                ("Content-Transfer-Encoding", "base64".to_owned()),
+            ],
+            body: base64_lines(text.as_bytes()),
+        }
+    }
+
+    fn attachment(attachment: &Attachment) -> Self {
+        let mut headers = vec![
+            (
+                "Content-Type",
+                strip_line_breaks(&attachment.content_type).into_owned(),
+            ),
+            ("Content-Transfer-Encoding", "base64".to_owned()),
+        ];
+        if attachment.inline {
+            // The HTML refers to it as `cid:<name>`.
+            let content_id: String = attachment
+                .name
+                .chars()
+                .filter(|c| !matches!(c, '<' | '>' | '\r' | '\n'))
+                .collect();
+            headers.push(("Content-ID", format!("<{content_id}>")));
+            headers.push(("Content-Disposition", "inline".to_owned()));
+        } else {
+            headers.push((
+                "Content-Disposition",
+                format!("attachment; {}", file_name(&attachment.name)),
+            ));
+        }
+        Self {
+            headers,
+            body: base64_lines(&attachment.content),
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        for (name, value) in &self.headers {
+            out.push_str(&format!("{name}: {value}\r\n"));
+        }
+        out.push_str("\r\n");
+        out.push_str(&self.body);
+    }
+}
+
+fn multipart(subtype: &str, parts: Vec<Part>) -> Part {
+    let boundary = format!("=_{}", Uuid::new_v4().simple());
+    let mut body = String::new();
+    for part in parts {
+        body.push_str(&format!("--{boundary}\r\n"));
+        part.write(&mut body);
+    }
+    body.push_str(&format!("--{boundary}--\r\n"));
+    Part {
+        headers: vec![(
+            "Content-Type",
+            format!("multipart/{subtype};\r\n boundary=\"{boundary}\""),
+        )],
+        body,
+    }
+}
+
+fn base64_lines(content: &[u8]) -> String {
+    let encoded = base64::encode(content);
+    let mut lines = String::with_capacity(encoded.len() + encoded.len() / 38 + 2);
+    for line in encoded.as_bytes().chunks(BASE64_LINE_LENGTH) {
+        // Base64 is ASCII.
+        lines.push_str(std::str::from_utf8(line).unwrap());
+        lines.push_str("\r\n");
+    }
+    lines
+}
+
+/// Line breaks in a header value would start a new header, or the body.
+fn strip_line_breaks(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains(['\r', '\n']) {
+        value.replace(['\r', '\n'], " ").into()
+    } else {
+        value.into()
+    }
+}
+
+fn header_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_graphic() && *c != ':')
+        .collect()
+}
+
+/// Free text, as RFC 2047 encoded words unless it is plain ASCII.
+fn unstructured(value: &str) -> String {
+    let value = strip_line_breaks(value);
+    if value.is_ascii() {
+        return value.into_owned();
+    }
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in value.chars() {
+        if chunk.len() + c.len_utf8() > ENCODED_WORD_BYTES {
+            words.push(encoded_word(&chunk));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    words.push(encoded_word(&chunk));
+    words.join("\r\n ")
+}
+
+fn encoded_word(text: &str) -> String {
+    format!("=?utf-8?B?{}?=", base64::encode(text))
+}
+
+/// An address, with its display name encoded if it is not plain ASCII.
+fn mailbox(value: &str) -> String {
+    let value = strip_line_breaks(value);
+    match value.rsplit_once('<') {
+        Some((name, address)) if !name.is_ascii() => {
+            let name = name.trim();
+            let name = name
+                .strip_prefix('"')
+                .and_then(|n| n.strip_suffix('"'))
+                .map(|n| n.replace("\\\"", "\"").replace("\\\\", "\\"))
+                .unwrap_or_else(|| name.to_owned());
+            format!("{} <{address}", unstructured(&name))
+        }
+        _ => value.into_owned(),
+    }
+}
+
+fn message_id(from: &str) -> String {
+    let domain = from
+        .rsplit('@')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('>');
+    format!("<{}@{}>", Uuid::new_v4(), header_name(domain))
+}
+
+/// The `filename` parameter, percent-encoded as in RFC 2231 unless the name
+/// is plain ASCII.
+fn file_name(name: &str) -> String {
+    if name.is_ascii() {
+        let name: String = strip_line_breaks(name)
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"");
+        return format!("filename=\"{name}\"");
+    }
+    let mut encoded = String::new();
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    format!("filename*=utf-8''{encoded}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use crate::email_client::{
+        Attachment,
+        EmailHeader,
+        EmailOptions,
+        OutgoingEmail,
+    };
+
+    fn email<'a>(options: &'a EmailOptions, attachments: &'a [Attachment]) -> OutgoingEmail<'a> {
+        OutgoingEmail {
+            from: "The Editor <editor@example.com>",
+            to: "jane@example.com",
+            subject: "Spring issue",
+            html_body: "<p>Hello</p>",
+            text_body: "Hello",
+            options,
+            attachments,
+        }
+    }
+
+    fn attachment(name: &str, inline: bool) -> Attachment {
+        Attachment {
+            name: name.into(),
+            content_type: "image/png".into(),
+            content: vec![0x89, b'P', b'N', b'G'],
+            inline,
+        }
+    }
+
+    /// The content types of the parts, in order, nested ones included.
+    fn content_types(message: &str) -> Vec<&str> {
+        message
+            .lines()
+            .filter_map(|line| line.strip_prefix("Content-Type: "))
+            .map(|value| value.split(';').next().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn a_message_without_attachments_has_alternative_bodies() {
+        let options = EmailOptions::default();
+        let message = render(&email(&options, &[]));
+
+        assert_eq!(
+            content_types(&message),
+            ["multipart/alternative", "text/plain", "text/html"]
+        );
+        assert!(message.contains("\r\nFrom: The Editor <editor@example.com>\r\n"));
+        assert!(message.contains("\r\nSubject: Spring issue\r\n"));
+        assert!(message.contains(&base64::encode("<p>Hello</p>")));
+        assert!(message.ends_with("--\r\n"));
+    }
+
+    #[test]
+    fn attachments_are_mime_parts() {
+        let options = EmailOptions::default();
+        let attachments = [attachment("logo.png", true), attachment("map.png", false)];
+        let message = render(&email(&options, &attachments));
+
+        assert_eq!(
+            content_types(&message),
+            [
+                "multipart/mixed",
+                "multipart/related",
+                "multipart/alternative",
+                "text/plain",
+                "text/html",
+                "image/png",
+                "image/png",
+            ]
+        );
+        assert!(message.contains("Content-ID: <logo.png>\r\nContent-Disposition: inline\r\n"));
+        assert!(message.contains("Content-Disposition: attachment; filename=\"map.png\"\r\n"));
+        assert!(message.contains("\r\n\r\niVBORw==\r\n"));
+    }
+
+    #[test]
+    fn options_are_written_as_headers() {
+        let options = EmailOptions {
+            reply_to: Some("replies@example.com".into()),
+            headers: vec![EmailHeader {
+                name: "X-Campaign".into(),
+                value: "spring".into(),
+            }],
+            tag: Some("newsletter".into()),
+            metadata: [("issue".to_owned(), "42".to_owned())].into(),
+            message_stream: Some("broadcast".into()),
+            ..Default::default()
+        };
+        let message = render(&email(&options, &[]));
+
+        for header in [
+            "Reply-To: replies@example.com",
+            "X-Campaign: spring",
+            "X-PM-Tag: newsletter",
+            "X-PM-Metadata-issue: 42",
+            "X-PM-Message-Stream: broadcast",
+        ] {
+            assert!(message.contains(&format!("\r\n{header}\r\n")), "{header}");
+        }
+    }
+
+    #[test]
+    fn non_ascii_headers_are_encoded() {
+        let options = EmailOptions::default();
+        let attachments = [attachment("carte postale é.png", false)];
+        let mut email = email(&options, &attachments);
+        email.from = "\"Amélie, l'éditrice\" <editor@example.com>";
+        email.subject = "Habari za wiki — été";
+        let message = render(&email);
+
+        assert!(message.contains(&format!(
+            "\r\nFrom: =?utf-8?B?{}?= <editor@example.com>\r\n",
+            base64::encode("Amélie, l'éditrice")
+        )));
+        assert!(message.contains(&format!(
+            "\r\nSubject: =?utf-8?B?{}?=\r\n",
+            base64::encode("Habari za wiki — été")
+        )));
+        assert!(message.contains("filename*=utf-8''carte%20postale%20%C3%A9.png\r\n"));
+    }
+
+    #[test]
+    fn line_breaks_cannot_inject_headers() {
+        let options = EmailOptions {
+            headers: vec![EmailHeader {
+                name: "X-Campaign".into(),
+                value: "spring\r\nBcc: everyone@example.com".into(),
+            }],
+            ..Default::default()
+        };
+        let mut email = email(&options, &[]);
+        email.subject = "Spring\nBcc: everyone@example.com";
+        let message = render(&email);
+
+        assert!(!message.contains("\nBcc:"));
+    }
+
+    #[test]
+    fn long_lines_are_wrapped() {
+        let options = EmailOptions::default();
+        let text = "a".repeat(1000);
+        let subject = "é".repeat(100);
+        let mut email = email(&options, &[]);
+        email.text_body = &text;
+        email.subject = &subject;
+        let message = render(&email);
+
+        assert!(message.split("\r\n").all(|line| line.len() <= 78));
+    }
+}
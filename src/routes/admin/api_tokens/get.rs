@@ -0,0 +1,104 @@
+use crate::authentication::{
+    list_api_tokens,
+    UserId,
+};
+use crate::i18n::Locale;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn api_tokens_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let tokens = list_api_tokens(*user_id, &pool).await.map_err(e500)?;
+    let mut tokens_html = String::new();
+    for token in tokens {
+        let last_used_at = token
+            .last_used_at
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_else(|| locale.t("api-tokens-never-used"));
+        let action = match token.revoked_at {
+            Some(revoked_at) => locale.t_args(
+                "api-tokens-revoked-on",
+                &[("revoked_at", revoked_at.to_rfc3339().into())],
+            ),
+            None => format!(
+                r#"<form action="/admin/api_tokens/{}/revoke" method="post">
+                <button type="submit">{}</button>
+            </form>"#,
+                token.api_token_id,
+                locale.t("api-tokens-revoke"),
+            ),
+        };
+        writeln!(
+            tokens_html,
+            r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{last_used_at}</td>
+            <td>{action}</td>
+        </tr>"#,
+            htmlescape::encode_minimal(&token.name),
+            token.created_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+
+    let title = locale.t("api-tokens-title");
+    let name = locale.t("api-tokens-name");
+    let created_at = locale.t("api-tokens-created-at");
+    let last_used_at = locale.t("api-tokens-last-used-at");
+    let new_name = locale.t("api-tokens-new-name");
+    let new_name_placeholder = locale.t("api-tokens-new-name-placeholder");
+    let create = locale.t("api-tokens-create");
+    let back = locale.t("back");
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{locale}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr>
+            <th>{name}</th>
+            <th>{created_at}</th>
+            <th>{last_used_at}</th>
+            <th></th>
+        </tr>
+        {tokens_html}
+    </table>
+    <form action="/admin/api_tokens" method="post">
+        <label>{new_name}
+            <input
+                type="text"
+                placeholder="{new_name_placeholder}"
+                name="name"
+            >
+        </label>
+        <button type="submit">{create}</button>
+    </form>
+    <p><a href="/admin/dashboard">{back}</a></p>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,119 @@
+use crate::audit::{
+    record_audit_event,
+    AuditContext,
+    API_TOKEN_CREATED,
+    API_TOKEN_REVOKED,
+};
+use crate::i18n::Locale;
+use crate::utils::{
+    e500,
+    see_other,
+};
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+}
+
+#[tracing::instrument(
+    name = "Create an API token",
+    skip_all,
+    fields(user_id=%audit.user_id)
+)]
+pub async fn create_api_token(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim().to_owned();
+    if name.is_empty() {
+        FlashMessage::error(locale.t("api-tokens-empty-name")).send();
+        return Ok(see_other("/admin/api_tokens"));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let (api_token_id, token) =
+        crate::authentication::create_api_token(audit.user_id, &name, transaction.as_mut())
+            .await
+            .map_err(e500)?;
+    let target = format!("api_token:{api_token_id}");
+    record_audit_event(
+        transaction.as_mut(),
+        &audit,
+        API_TOKEN_CREATED,
+        Some(&target),
+    )
+    .await
+    .context("Failed to record the creation of an API token.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create an API token.")
+        .map_err(e500)?;
+    // This is the only time the token is ever shown: we only store its hash.
+    FlashMessage::info(locale.t_args(
+        "api-tokens-created",
+        &[("token", token.expose_secret().into())],
+    ))
+    .send();
+    Ok(see_other("/admin/api_tokens"))
+}
+
+#[tracing::instrument(
+    name = "Revoke an API token",
+    skip_all,
+    fields(user_id=%audit.user_id)
+)]
+pub async fn revoke_api_token(
+    api_token_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let api_token_id = api_token_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let revoked =
+        crate::authentication::revoke_api_token(audit.user_id, api_token_id, transaction.as_mut())
+            .await
+            .map_err(e500)?;
+    if revoked {
+        let target = format!("api_token:{api_token_id}");
+        record_audit_event(
+            transaction.as_mut(),
+            &audit,
+            API_TOKEN_REVOKED,
+            Some(&target),
+        )
+        .await
+        .context("Failed to record the revocation of an API token.")
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to revoke an API token.")
+        .map_err(e500)?;
+    if revoked {
+        FlashMessage::info(locale.t("api-tokens-revoked")).send();
+    } else {
+        FlashMessage::error(locale.t("api-tokens-not-revoked")).send();
+    }
+    Ok(see_other("/admin/api_tokens"))
+}
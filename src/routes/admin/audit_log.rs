@@ -0,0 +1,160 @@
+use crate::audit::{
+    list_audit_events,
+    AuditFilter,
+    ACTIONS,
+};
+use crate::i18n::Locale;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+const PAGE_SIZE: i64 = 50;
+
+/// Blank fields, as sent by the filter form, match every event.
+#[derive(serde::Deserialize)]
+pub struct AuditLogParameters {
+    #[serde(default)]
+    user: String,
+    #[serde(default)]
+    action: String,
+    #[serde(default)]
+    offset: i64,
+}
+
+impl AuditLogParameters {
+    fn filter(&self) -> AuditFilter {
+        let non_blank = |s: &str| Some(s.trim().to_owned()).filter(|s| !s.is_empty());
+        AuditFilter {
+            username: non_blank(&self.user),
+            action: non_blank(&self.action),
+        }
+    }
+
+    fn next_page(&self) -> String {
+        let query = serde_urlencoded::to_string([
+            ("user", self.user.as_str()),
+            ("action", self.action.as_str()),
+            ("offset", &(self.offset + PAGE_SIZE).to_string()),
+        ])
+        .unwrap();
+        format!("/admin/audit_log?{query}")
+    }
+}
+
+pub async fn audit_log(
+    parameters: web::Query<AuditLogParameters>,
+    pool: web::Data<PgPool>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let offset = parameters.offset.max(0);
+    let events = list_audit_events(&pool, &parameters.filter(), PAGE_SIZE, offset)
+        .await
+        .context("Failed to retrieve audit events.")
+        .map_err(e500)?;
+
+    let unknown = locale.t("audit-log-unknown");
+    let mut rows_html = String::new();
+    for e in &events {
+        let user = if e.from_command_line {
+            locale.t_args(
+                "audit-log-command-line",
+                &[("operator", e.username.as_str().into())],
+            )
+        } else {
+            e.username.clone()
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            e.occurred_at.to_rfc3339(),
+            encode_minimal(&user),
+            encode_minimal(e.ip_address.as_deref().unwrap_or(&unknown)),
+            e.action,
+            encode_minimal(e.target.as_deref().unwrap_or_default()),
+        )
+        .unwrap();
+    }
+    let mut actions_html = String::new();
+    for action in ACTIONS {
+        let selected = if parameters.action == action {
+            " selected"
+        } else {
+            ""
+        };
+        writeln!(
+            actions_html,
+            r#"<option value="{action}"{selected}>{action}</option>"#
+        )
+        .unwrap();
+    }
+    let older_html = if events.len() as i64 == PAGE_SIZE {
+        format!(
+            r#"<p><a href="{}">{}</a></p>"#,
+            encode_minimal(&parameters.next_page()),
+            locale.t("audit-log-older")
+        )
+    } else {
+        String::new()
+    };
+
+    let title = locale.t("audit-log-title");
+    let occurred_at = locale.t("audit-log-occurred-at");
+    let user = locale.t("audit-log-user");
+    let ip_address = locale.t("audit-log-ip-address");
+    let action = locale.t("audit-log-action");
+    let target = locale.t("audit-log-target");
+    let any_action = locale.t("audit-log-any-action");
+    let filter = locale.t("audit-log-filter");
+    let back = locale.t("back");
+    let user_value = encode_minimal(&parameters.user);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{locale}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <form action="/admin/audit_log" method="get">
+        <label>{user}
+            <input type="text" name="user" value="{user_value}">
+        </label>
+        <label>{action}
+            <select name="action">
+                <option value="">{any_action}</option>
+                {actions_html}
+            </select>
+        </label>
+        <button type="submit">{filter}</button>
+    </form>
+    <table>
+        <tr>
+            <th>{occurred_at}</th>
+            <th>{user}</th>
+            <th>{ip_address}</th>
+            <th>{action}</th>
+            <th>{target}</th>
+        </tr>
+        {rows_html}
+    </table>
+    {older_html}
+    <p><a href="/admin/dashboard">{back}</a></p>
+</body>
+</html>"#,
+        )))
+}
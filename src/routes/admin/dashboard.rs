@@ -1,3 +1,4 @@
+use crate::i18n::Locale;
 use crate::session_state::TypedSession;
 use crate::utils::e500;
 use actix_web::http::header::LOCATION;
@@ -13,6 +14,7 @@ use uuid::Uuid;
 pub async fn admin_dashboard(
     session: TypedSession,
     pool: web::Data<PgPool>,
+    locale: Locale,
 ) -> Result<HttpResponse, actix_web::Error> {
     let username = if let Some(user_id) = session.get_user_id().map_err(e500)? {
         get_username(user_id, &pool).await.map_err(e500)?
@@ -21,23 +23,36 @@ pub async fn admin_dashboard(
             .insert_header((LOCATION, "/login"))
             .finish());
     };
+    let title = locale.t("dashboard-title");
+    let welcome = locale.t_args("dashboard-welcome", &[("username", username.into())]);
+    let actions = locale.t("dashboard-actions");
+    let subscribers = locale.t("dashboard-subscribers");
+    let suppressions = locale.t("dashboard-suppressions");
+    let change_password = locale.t("dashboard-change-password");
+    let api_tokens = locale.t("dashboard-api-tokens");
+    let audit_log = locale.t("dashboard-audit-log");
+    let logout = locale.t("dashboard-logout");
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
             r#"<!DOCTYPE html>
-<html lang="en">
+<html lang="{locale}">
 <head>
     <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Admin dashboard</title>
+    <title>{title}</title>
 </head>
 <body>
-    <p>Welcome {username}!</p>
-    <p>Available actions:</p>
+    <p>{welcome}</p>
+    <p>{actions}</p>
     <ol>
-        <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/subscribers">{subscribers}</a></li>
+        <li><a href="/admin/suppressions">{suppressions}</a></li>
+        <li><a href="/admin/password">{change_password}</a></li>
+        <li><a href="/admin/api_tokens">{api_tokens}</a></li>
+        <li><a href="/admin/audit_log">{audit_log}</a></li>
         <li>
           <form name="logoutForm" action="/admin/logout" method="post">
-            <input type="submit" value="Logout">
+            <input type="submit" value="{logout}">
           </form>
         </li>
     </ol>
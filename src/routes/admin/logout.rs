@@ -1,17 +1,38 @@
+use crate::audit::{
+    record_audit_event,
+    AuditContext,
+    LOGGED_OUT,
+};
+use crate::i18n::Locale;
 use crate::session_state::TypedSession;
 use crate::utils::{
     e500,
     see_other,
 };
-use actix_web::HttpResponse;
+use actix_web::{
+    web,
+    HttpResponse,
+};
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
 
-pub async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+pub async fn log_out(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
     if session.get_user_id().map_err(e500)?.is_none() {
         Ok(see_other("/login"))
     } else {
+        // Sessions are not stored in the database: there is no transaction
+        record_audit_event(pool.get_ref(), &audit, LOGGED_OUT, None)
+            .await
+            .context("Failed to record a logout.")
+            .map_err(e500)?;
         session.log_out();
-        FlashMessage::info("You have successfully logged out.").send();
+        FlashMessage::info(locale.t("logout-done")).send();
         Ok(see_other("/login"))
     }
 }
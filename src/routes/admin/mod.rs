@@ -1,9 +1,17 @@
+mod api_tokens;
+mod audit_log;
 mod dashboard;
 mod logout;
 mod newsletter;
 mod password;
+mod subscribers;
+mod suppressions;
 
+pub use api_tokens::*;
+pub use audit_log::audit_log;
 pub use dashboard::admin_dashboard;
 pub use logout::log_out;
 pub use newsletter::*;
 pub use password::*;
+pub use subscribers::*;
+pub use suppressions::*;
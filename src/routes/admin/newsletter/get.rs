@@ -1,10 +1,71 @@
+use crate::attachment::list_attachments;
+use crate::email_options::{
+    get_email_options,
+    IssueEmailSettings,
+};
+use crate::i18n::Locale;
+use crate::subject_test::{
+    get_subject_test,
+    subject_test_results,
+    Metric,
+    DEFAULT_SAMPLE_PERCENT,
+    DEFAULT_WAIT_MINUTES,
+};
+use crate::utils::e500;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{
+    web,
+    HttpResponse,
+};
 use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
 use std::fmt::Write;
+use uuid::Uuid;
+
+#[derive(serde::Serialize)]
+pub(crate) struct VariantDeliveryStatus {
+    pub locale: String,
+    pub pending_deliveries: i64,
+    pub sent: i32,
+    pub failed: i32,
+    pub skipped: i32,
+}
+
+/// Empty if there is no such issue: every issue has at least its default
+/// variant.
+pub(crate) async fn variant_delivery_statuses(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<VariantDeliveryStatus>, sqlx::Error> {
+    sqlx::query_as!(
+        VariantDeliveryStatus,
+        r#"
+        SELECT
+            v.locale,
+            (SELECT COUNT(*) FROM issue_delivery_queue q
+             WHERE
+                q.newsletter_issue_id = v.newsletter_issue_id AND
+                q.locale = v.locale) as "pending_deliveries!",
+            v.n_sent as sent,
+            v.n_failed as failed,
+            v.n_skipped as skipped
+        FROM newsletter_issue_variants v
+        WHERE v.newsletter_issue_id = $1
+        ORDER BY v.locale
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(pool)
+    .await
+}
 
 pub async fn publish_newsletter_form(
     flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    issue_email_settings: web::Data<IssueEmailSettings>,
+    locale: Locale,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
@@ -12,48 +73,427 @@ pub async fn publish_newsletter_form(
     }
 
     let idempotency_key = uuid::Uuid::new_v4();
+    let title = locale.t("newsletter-title");
+    let issue_title = locale.t("newsletter-issue-title");
+    let issue_title_placeholder = locale.t("newsletter-issue-title-placeholder");
+    let text_content = locale.t("newsletter-text-content");
+    let text_content_placeholder = locale.t("newsletter-text-content-placeholder");
+    let html_content = locale.t("newsletter-html-content");
+    let html_content_placeholder = locale.t("newsletter-html-content-placeholder");
+    let subject_lines = locale.t("newsletter-subject-lines");
+    let sample_percent = locale.t("newsletter-sample-percent");
+    let wait_minutes = locale.t("newsletter-wait-minutes");
+    let metric = locale.t("newsletter-metric");
+    let metric_opens = locale.t("newsletter-metric-opens");
+    let metric_clicks = locale.t("newsletter-metric-clicks");
+    let opens = Metric::Opens.as_str();
+    let clicks = Metric::Clicks.as_str();
+    let attachments = locale.t("newsletter-attachments");
+    let inline_images = locale.t("newsletter-inline-images");
+    let sender = locale.t("newsletter-sender");
+    let default_sender = locale.t("newsletter-sender-default");
+    let reply_to = locale.t("newsletter-reply-to");
+    let headers = locale.t("newsletter-headers");
+    let tag = locale.t("newsletter-tag");
+    let metadata = locale.t("newsletter-metadata");
+    let message_stream = locale.t("newsletter-message-stream");
+    let default_message_stream = encode_minimal(
+        issue_email_settings
+            .default_message_stream
+            .as_deref()
+            .unwrap_or_default(),
+    );
+    let issues = locale.t("newsletter-issues");
+    let submit = locale.t("newsletter-submit");
+    let back = locale.t("back");
+
+    let published_issues = sqlx::query!(
+        r#"
+        SELECT i.newsletter_issue_id, v.title, i.published_at
+        FROM newsletter_issues i
+        JOIN newsletter_issue_variants v
+            ON v.newsletter_issue_id = i.newsletter_issue_id AND v.locale = i.default_locale
+        ORDER BY i.published_at DESC
+        "#,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve newsletter issues.")
+    .map_err(e500)?;
+    let mut issues_html = String::new();
+    for issue in published_issues {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/admin/newsletters/{}">{}</a> ({})</li>"#,
+            issue.newsletter_issue_id,
+            encode_minimal(&issue.title),
+            issue.published_at,
+        )
+        .unwrap();
+    }
+
+    let mut senders_html = String::new();
+    for (id, identity) in &issue_email_settings.sender_identities {
+        writeln!(
+            senders_html,
+            r#"<option value="{}">{}</option>"#,
+            encode_minimal(id),
+            encode_minimal(&identity.mailbox()),
+        )
+        .unwrap();
+    }
+
+    let mut translations_html = String::new();
+    for translation in Locale::ALL.into_iter().filter(|l| *l != Locale::default()) {
+        let legend = locale.t_args(
+            "newsletter-translation",
+            &[(
+                "language",
+                locale.t(&format!("language-{translation}")).into(),
+            )],
+        );
+        writeln!(
+            translations_html,
+            r#"<fieldset lang="{translation}">
+        <legend>{legend}</legend>
+        <label>{issue_title}<br>
+            <input type="text" name="title_{translation}">
+        </label>
+        <br>
+        <label>{text_content}<br>
+            <textarea name="text_content_{translation}" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <label>{html_content}<br>
+            <textarea name="html_content_{translation}" rows="20" cols="50"></textarea>
+        </label>
+    </fieldset>"#
+        )
+        .unwrap();
+    }
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
             r#"<!DOCTYPE html>
-<html lang="en">
+<html lang="{locale}">
 <head>
     <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Publish Newsletter Issue</title>
+    <title>{title}</title>
 </head>
 <body>
     {msg_html}
-    <form action="/admin/newsletters" method="post">
-        <label>Title:<br>
+    <form action="/admin/newsletters" method="post" enctype="multipart/form-data">
+        <label>{issue_title}<br>
             <input
                 type="text"
-                placeholder="Enter the issue title"
+                placeholder="{issue_title_placeholder}"
                 name="title"
             >
         </label>
         <br>
-        <label>Plain text content:<br>
+        <label>{text_content}<br>
             <textarea
-                placeholder="Enter the content in plain text"
+                placeholder="{text_content_placeholder}"
                 name="text_content"
                 rows="20"
                 cols="50"
             ></textarea>
         </label>
         <br>
-        <label>HTML content:<br>
+        <label>{html_content}<br>
             <textarea
-                placeholder="Enter the content in HTML format"
+                placeholder="{html_content_placeholder}"
                 name="html_content"
                 rows="20"
                 cols="50"
             ></textarea>
         </label>
         <br>
+        {translations_html}
+        <label>{attachments}<br>
+            <input type="file" name="attachments" multiple>
+        </label>
+        <br>
+        <label>{inline_images}<br>
+            <input type="file" name="inline_images" accept="image/*" multiple>
+        </label>
+        <br>
+        <label>{sender}
+            <select name="sender">
+                <option value="">{default_sender}</option>
+                {senders_html}
+            </select>
+        </label>
+        <br>
+        <label>{reply_to}
+            <input type="email" name="reply_to">
+        </label>
+        <br>
+        <label>{headers}<br>
+            <textarea name="headers" rows="3" cols="50"></textarea>
+        </label>
+        <br>
+        <label>{tag}
+            <input type="text" name="tag">
+        </label>
+        <br>
+        <label>{metadata}<br>
+            <textarea name="metadata" rows="3" cols="50"></textarea>
+        </label>
+        <br>
+        <label>{message_stream}
+            <input type="text" name="message_stream" value="{default_message_stream}">
+        </label>
+        <br>
+        <label>{subject_lines}<br>
+            <textarea name="subject_lines" rows="5" cols="50"></textarea>
+        </label>
+        <br>
+        <label>{sample_percent}
+            <input type="number" name="sample_percent" min="1" max="100" value="{DEFAULT_SAMPLE_PERCENT}">
+        </label>
+        <br>
+        <label>{wait_minutes}
+            <input type="number" name="wait_minutes" min="1" value="{DEFAULT_WAIT_MINUTES}">
+        </label>
+        <br>
+        <label>{metric}
+            <select name="metric">
+                <option value="{opens}">{metric_opens}</option>
+                <option value="{clicks}">{metric_clicks}</option>
+            </select>
+        </label>
+        <br>
         <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
-        <button type="submit">Publish</button>
+        <button type="submit">{submit}</button>
     </form>
-    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    <p>{issues}</p>
+    <ul>
+        {issues_html}
+    </ul>
+    <p><a href="/admin/dashboard">{back}</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn newsletter_issue_details(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let issue = sqlx::query!(
+        r#"
+        SELECT v.title, i.published_at
+        FROM newsletter_issues i
+        JOIN newsletter_issue_variants v
+            ON v.newsletter_issue_id = i.newsletter_issue_id AND v.locale = i.default_locale
+        WHERE i.newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a newsletter issue.")
+    .map_err(e500)?;
+    let Some(issue) = issue else {
+        return Ok(HttpResponse::NotFound().body(locale.t("issue-not-found")));
+    };
+
+    let mut deliveries_html = String::new();
+    for v in variant_delivery_statuses(&pool, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the delivery status of a newsletter issue.")
+        .map_err(e500)?
+    {
+        writeln!(
+            deliveries_html,
+            r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            v.locale, v.pending_deliveries, v.sent, v.failed, v.skipped,
+        )
+        .unwrap();
+    }
+
+    let email_options = get_email_options(pool.get_ref(), newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+    let mut email_options_html = String::new();
+    let fields = [
+        ("issue-sender", email_options.sender.map(|s| s.mailbox())),
+        ("issue-reply-to", email_options.reply_to),
+        ("issue-tag", email_options.tag),
+        ("issue-message-stream", email_options.message_stream),
+    ];
+    for (id, value) in fields {
+        if let Some(value) = value {
+            writeln!(
+                email_options_html,
+                "<p>{}</p>",
+                locale.t_args(id, &[("value", encode_minimal(&value).into())]),
+            )
+            .unwrap();
+        }
+    }
+
+    let mut attachments_html = String::new();
+    for a in list_attachments(pool.get_ref(), newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the attachments of a newsletter issue.")
+        .map_err(e500)?
+    {
+        let inline = if a.inline {
+            format!(", {}", locale.t("issue-attachment-inline"))
+        } else {
+            String::new()
+        };
+        writeln!(
+            attachments_html,
+            "<li>{} ({}, {} B{inline})</li>",
+            encode_minimal(&a.file_name),
+            encode_minimal(&a.content_type),
+            a.size_bytes,
+        )
+        .unwrap();
+    }
+    if !attachments_html.is_empty() {
+        attachments_html = format!(
+            r#"<p>{}</p>
+    <ul>
+        {attachments_html}
+    </ul>"#,
+            locale.t("issue-attachments"),
+        );
+    }
+
+    let subject_test_html = match get_subject_test(pool.get_ref(), newsletter_issue_id)
+        .await
+        .context("Failed to retrieve the subject line test of a newsletter issue.")
+        .map_err(e500)?
+    {
+        Some(test) => {
+            let metric = locale.t(&format!("newsletter-metric-{}", test.metric));
+            let status = match test.decided_at {
+                Some(decided_at) => locale.t_args(
+                    "issue-subject-test-decided",
+                    &[
+                        ("metric", metric.into()),
+                        ("decided_at", decided_at.to_rfc3339().into()),
+                    ],
+                ),
+                None => locale.t_args(
+                    "issue-subject-test-pending",
+                    &[
+                        ("metric", metric.into()),
+                        ("decide_at", test.decide_at.to_rfc3339().into()),
+                        ("sample_percent", test.sample_percent.into()),
+                    ],
+                ),
+            };
+            let mut results_html = String::new();
+            for r in subject_test_results(pool.get_ref(), newsletter_issue_id)
+                .await
+                .context("Failed to retrieve the results of a subject line test.")
+                .map_err(e500)?
+            {
+                let winner = if test.winner == Some(r.variant_index) {
+                    format!(" <b>({})</b>", locale.t("issue-winner"))
+                } else {
+                    String::new()
+                };
+                writeln!(
+                    results_html,
+                    r#"<tr>
+            <td>{}{winner}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{:.1}%</td>
+            <td>{}</td>
+            <td>{:.1}%</td>
+        </tr>"#,
+                    encode_minimal(&r.subject),
+                    r.n_sent,
+                    r.n_opened,
+                    r.open_rate() * 100.0,
+                    r.n_clicked,
+                    r.click_rate() * 100.0,
+                )
+                .unwrap();
+            }
+            format!(
+                r#"<p>{}</p>
+    <p>{status}</p>
+    <table>
+        <tr>
+            <th>{}</th>
+            <th>{}</th>
+            <th>{}</th>
+            <th>{}</th>
+            <th>{}</th>
+            <th>{}</th>
+        </tr>
+        {results_html}
+    </table>"#,
+                locale.t("issue-subject-test"),
+                locale.t("issue-subject"),
+                locale.t("issue-sent"),
+                locale.t("issue-opened"),
+                locale.t("issue-open-rate"),
+                locale.t("issue-clicked"),
+                locale.t("issue-click-rate"),
+            )
+        }
+        None => String::new(),
+    };
+
+    let title = locale.t_args(
+        "issue-title",
+        &[("title", encode_minimal(&issue.title).into())],
+    );
+    let published_at = locale.t_args(
+        "issue-published-at",
+        &[("published_at", issue.published_at.into())],
+    );
+    let deliveries = locale.t("issue-deliveries");
+    let language = locale.t("issue-locale");
+    let pending = locale.t("issue-pending");
+    let sent = locale.t("issue-sent");
+    let failed = locale.t("issue-failed");
+    let skipped = locale.t("issue-skipped");
+    let back = locale.t("back");
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{locale}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <p>{title}</p>
+    <p>{published_at}</p>
+    {email_options_html}
+    <p>{deliveries}</p>
+    <table>
+        <tr>
+            <th>{language}</th>
+            <th>{pending}</th>
+            <th>{sent}</th>
+            <th>{failed}</th>
+            <th>{skipped}</th>
+        </tr>
+        {deliveries_html}
+    </table>
+    {attachments_html}
+    {subject_test_html}
+    <p><a href="/admin/newsletters">{back}</a></p>
 </body>
 </html>"#,
         )))
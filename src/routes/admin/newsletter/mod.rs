@@ -1,5 +1,17 @@
 mod get;
 mod post;
 
-pub use get::publish_newsletter_form;
-pub use post::publish_newsletter;
+pub(crate) use get::variant_delivery_statuses;
+pub use get::{
+    newsletter_issue_details,
+    publish_newsletter_form,
+};
+pub(crate) use post::{
+    enqueue_delivery_tasks,
+    insert_newsletter_issue,
+    IssueVariant,
+};
+pub use post::{
+    publish_newsletter,
+    resend_success_message,
+};
@@ -1,27 +1,65 @@
-use crate::authentication::UserId;
+use crate::attachment::{
+    store_attachments,
+    AttachmentError,
+    AttachmentPolicy,
+    BlobStore,
+    NewAttachment,
+};
+use crate::audit::{
+    record_audit_event,
+    AuditContext,
+    NEWSLETTER_ISSUE_PUBLISHED,
+};
+use crate::email_client::EmailOptions;
+use crate::email_options::{
+    split_fields,
+    store_email_options,
+    EmailOptionsError,
+    IssueEmailSettings,
+    NewEmailOptions,
+};
+use crate::i18n::Locale;
 use crate::idempotency::{
-    save_response,
-    try_processing,
-    IdempotencyKey,
-    NextAction,
+    KeyTransaction,
+    IDEMPOTENT_REPLAYED_HEADER,
 };
-use crate::utils::e400;
+use crate::issue_delivery_worker::notify_new_tasks;
+use crate::subject_test::{
+    insert_subject_test,
+    Metric,
+    SubjectTest,
+    SubjectTestError,
+    DEFAULT_SAMPLE_PERCENT,
+    DEFAULT_WAIT_MINUTES,
+};
+use crate::suppression::suppressed_emails;
 use crate::utils::{
+    e400,
     e500,
     see_other,
+    ErrorCode,
+};
+use actix_multipart::Multipart;
+use actix_web::body::MessageBody;
+use actix_web::dev::{
+    ServiceRequest,
+    ServiceResponse,
 };
 use actix_web::{
     web,
     HttpResponse,
 };
 use actix_web_flash_messages::FlashMessage;
+use actix_web_lab::middleware::Next;
 use anyhow::Context;
+use futures_util::TryStreamExt;
+use htmlescape::encode_minimal;
 use sqlx::{
     Executor,
-    PgPool,
     Postgres,
     Transaction,
 };
+use std::collections::HashMap;
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -29,102 +67,413 @@ pub struct FormData {
     title: String,
     text_content: String,
     html_content: String,
-    idempotency_key: String,
+    /// Alternative subject lines to A/B test against the title, one per
+    /// line.
+    #[serde(default)]
+    subject_lines: String,
+    #[serde(default)]
+    sample_percent: String,
+    #[serde(default)]
+    wait_minutes: String,
+    #[serde(default)]
+    metric: String,
+    /// The id of a sender identity, the default sender if blank.
+    #[serde(default)]
+    sender: String,
+    #[serde(default)]
+    reply_to: String,
+    /// `Name: value` lines.
+    #[serde(default)]
+    headers: String,
+    #[serde(default)]
+    tag: String,
+    /// `key: value` lines.
+    #[serde(default)]
+    metadata: String,
+    #[serde(default)]
+    message_stream: String,
+    /// Optional translations, as `title_<locale>`, `text_content_<locale>`
+    /// and `html_content_<locale>`.
+    #[serde(flatten)]
+    translations: HashMap<String, String>,
+}
+
+/// The content of a newsletter issue in one language.
+pub(crate) struct IssueVariant {
+    pub locale: Locale,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+impl FormData {
+    /// `None` if no alternative subject line was given. Blank settings get
+    /// their default value.
+    fn subject_test(&self) -> Result<Option<SubjectTest>, SubjectTestError> {
+        if self.subject_lines.trim().is_empty() {
+            return Ok(None);
+        }
+        let metric = match self.metric.trim() {
+            "" => Metric::default(),
+            metric => Metric::parse(metric)?,
+        };
+        let sample_percent = match self.sample_percent.trim() {
+            "" => DEFAULT_SAMPLE_PERCENT,
+            n => n
+                .parse()
+                .map_err(|_| SubjectTestError::InvalidSamplePercent)?,
+        };
+        let wait_minutes = match self.wait_minutes.trim() {
+            "" => DEFAULT_WAIT_MINUTES,
+            n => n.parse().map_err(|_| SubjectTestError::InvalidWait)?,
+        };
+        SubjectTest::new(
+            &self.title,
+            self.subject_lines.lines().map(ToOwned::to_owned),
+            metric,
+            sample_percent,
+            wait_minutes,
+        )
+        .map(Some)
+    }
+
+    fn email_options(
+        &self,
+        settings: &IssueEmailSettings,
+    ) -> Result<EmailOptions, EmailOptionsError> {
+        NewEmailOptions {
+            sender: self.sender.clone(),
+            reply_to: self.reply_to.clone(),
+            headers: split_fields(&self.headers).map_err(EmailOptionsError::InvalidHeader)?,
+            tag: self.tag.clone(),
+            metadata: split_fields(&self.metadata).map_err(EmailOptionsError::InvalidMetadata)?,
+            message_stream: self.message_stream.clone(),
+        }
+        .parse(settings)
+    }
+
+    /// Translations left blank in the form are skipped.
+    fn into_variants(mut self) -> (IssueVariant, Vec<IssueVariant>) {
+        let default_variant = IssueVariant {
+            locale: Locale::default(),
+            title: self.title,
+            text_content: self.text_content,
+            html_content: self.html_content,
+        };
+        let translations = Locale::ALL
+            .into_iter()
+            .filter(|locale| *locale != default_variant.locale)
+            .filter_map(|locale| {
+                let mut field = |name: &str| {
+                    self.translations
+                        .remove(&format!("{name}_{locale}"))
+                        .unwrap_or_default()
+                };
+                let variant = IssueVariant {
+                    locale,
+                    title: field("title"),
+                    text_content: field("text_content"),
+                    html_content: field("html_content"),
+                };
+                let is_blank = [&variant.title, &variant.text_content, &variant.html_content]
+                    .iter()
+                    .all(|f| f.trim().is_empty());
+                (!is_blank).then_some(variant)
+            })
+            .collect();
+        (default_variant, translations)
+    }
+}
+
+/// Text fields are small, but HTML content can get long.
+const MAX_TEXT_FIELD_SIZE: usize = 1024 * 1024;
+
+/// Reads the publish form, sent as `multipart/form-data` to carry the files
+/// of the issue: the `attachments` and the `inline_images` fields. An error is
+/// returned as soon as a file breaks the attachment policy.
+async fn read_form(
+    mut payload: Multipart,
+    policy: &AttachmentPolicy,
+) -> Result<Result<(FormData, Vec<NewAttachment>), AttachmentError>, actix_web::Error> {
+    let mut text_fields = Vec::new();
+    let mut attachments = Vec::new();
+    while let Some(mut field) = payload.try_next().await? {
+        let name = field.name().unwrap_or_default().to_owned();
+        let inline = match name.as_str() {
+            "attachments" => false,
+            "inline_images" => true,
+            _ => {
+                let value = field.bytes(MAX_TEXT_FIELD_SIZE).await.map_err(e400)??;
+                let value = String::from_utf8(value.to_vec()).map_err(e400)?;
+                text_fields.push((name, value));
+                continue;
+            }
+        };
+        let file_name = field
+            .content_disposition()
+            .and_then(|d| d.get_filename())
+            .unwrap_or_default()
+            .to_owned();
+        let content_type = field
+            .content_type()
+            .map(|t| t.essence_str().to_owned())
+            .unwrap_or_default();
+        let Ok(content) = field.bytes(policy.max_file_size_bytes).await else {
+            return Ok(Err(AttachmentError::FileTooLarge(file_name)));
+        };
+        let content = content?;
+        // Browsers send an empty file when none was picked.
+        if file_name.is_empty() && content.is_empty() {
+            continue;
+        }
+        match NewAttachment::new(&file_name, &content_type, content.to_vec(), inline) {
+            Ok(attachment) => attachments.push(attachment),
+            Err(e) => return Ok(Err(e)),
+        }
+    }
+    if let Err(e) = policy.check(&attachments) {
+        return Ok(Err(e));
+    }
+    // Deserialized as if it had been sent as `x-www-form-urlencoded`.
+    let form = serde_urlencoded::to_string(&text_fields).map_err(e500)?;
+    let form = serde_urlencoded::from_str(&form).map_err(e400)?;
+    Ok(Ok((form, attachments)))
+}
+
+fn success_message(locale: Locale) -> FlashMessage {
+    FlashMessage::info(locale.t("newsletter-accepted"))
+}
+
+/// Replayed responses tell the author again that their issue was accepted.
+pub async fn resend_success_message(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let response = next.call(req).await?;
+    if response.headers().contains_key(IDEMPOTENT_REPLAYED_HEADER) {
+        success_message(Locale::from_accept_language(response.request())).send();
+    }
+    Ok(response)
+}
+
+/// Sends the author back to the form, with the reason their issue was
+/// rejected.
+fn reject(message: String) -> HttpResponse {
+    FlashMessage::error(message).send();
+    see_other("/admin/newsletters")
 }
 
-fn success_message() -> FlashMessage {
-    FlashMessage::info("The newsletter issue has been accepted - emails will go out shortly.")
+fn email_options_error_message(e: &EmailOptionsError, locale: Locale) -> String {
+    locale.t_args(
+        &format!("email-options-{}", e.code().replace('_', "-")),
+        &[(
+            "value",
+            encode_minimal(e.value().unwrap_or_default()).into(),
+        )],
+    )
+}
+
+fn attachment_error_message(e: &AttachmentError, locale: Locale) -> String {
+    let content_type = match e {
+        AttachmentError::ContentTypeNotAllowed { content_type, .. } => content_type.as_str(),
+        _ => "",
+    };
+    locale.t_args(
+        &format!("attachment-{}", e.code().replace('_', "-")),
+        &[
+            (
+                "file_name",
+                encode_minimal(e.file_name().unwrap_or_default()).into(),
+            ),
+            ("content_type", encode_minimal(content_type).into()),
+        ],
+    )
 }
 
 #[tracing::instrument(
     name = "Publish a newsletter issue",
     skip_all,
-    fields(user_id=%&*user_id)
+    fields(user_id=%audit.user_id)
 )]
 pub async fn publish_newsletter(
-    form: web::Form<FormData>,
-    pool: web::Data<PgPool>,
-    user_id: web::ReqData<UserId>,
+    payload: Multipart,
+    blob_store: web::Data<dyn BlobStore>,
+    attachment_policy: web::Data<AttachmentPolicy>,
+    issue_email_settings: web::Data<IssueEmailSettings>,
+    transaction: KeyTransaction,
+    audit: AuditContext,
+    locale: Locale,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let user_id = user_id.into_inner();
-    let FormData {
-        title,
-        text_content,
-        html_content,
-        idempotency_key,
-    } = form.0;
-    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
-    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
-        .await
-        .map_err(e500)?
-    {
-        NextAction::StartProcessing(t) => t,
-        NextAction::ReturnSavedResponse(saved_response) => {
-            success_message().send();
-            return Ok(saved_response);
+    let (form, attachments) = match read_form(payload, &attachment_policy).await? {
+        Ok(form) => form,
+        Err(e) => return Ok(reject(attachment_error_message(&e, locale))),
+    };
+    let email_options = match form.email_options(&issue_email_settings) {
+        Ok(email_options) => email_options,
+        Err(e) => return Ok(reject(email_options_error_message(&e, locale))),
+    };
+    let subject_test = form.subject_test();
+    let (default_variant, translations) = form.into_variants();
+    let subject_test = match subject_test {
+        Ok(Some(_)) if !translations.is_empty() => Err(SubjectTestError::Translated),
+        subject_test => subject_test,
+    };
+    let subject_test = match subject_test {
+        Ok(subject_test) => subject_test,
+        Err(e) => {
+            return Ok(reject(
+                locale.t(&format!("subject-test-{}", e.code().replace('_', "-"))),
+            ))
         }
     };
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
-        .await
-        .context("Failed to store newsletter issue details")
-        .map_err(e500)?;
+    let mut transaction = transaction.lock().await;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &default_variant,
+        &translations,
+        subject_test.as_ref(),
+        &email_options,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    store_attachments(
+        &mut transaction,
+        blob_store.get_ref(),
+        issue_id,
+        &attachments,
+    )
+    .await
+    .map_err(e500)?;
     enqueue_delivery_tasks(&mut transaction, issue_id)
         .await
         .context("Failed to enqueue delivery tasks")
         .map_err(e500)?;
-    let response = see_other("/admin/newsletters");
-    let response = save_response(transaction, &idempotency_key, *user_id, response)
-        .await
-        .map_err(e500)?;
-    success_message().send();
-    Ok(response)
+    let target = format!("newsletter_issue:{issue_id}");
+    record_audit_event(
+        transaction.as_mut(),
+        &audit,
+        NEWSLETTER_ISSUE_PUBLISHED,
+        Some(&target),
+    )
+    .await
+    .context("Failed to record the publication of a newsletter issue")
+    .map_err(e500)?;
+    success_message(locale).send();
+    Ok(see_other("/admin/newsletters"))
 }
 
+/// Subscribers whose locale has no translation get the default variant.
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub(crate) async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
-    title: &str,
-    text_content: &str,
-    html_content: &str,
+    default_variant: &IssueVariant,
+    translations: &[IssueVariant],
+    subject_test: Option<&SubjectTest>,
+    email_options: &EmailOptions,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     let query = sqlx::query!(
         r#"
         INSERT INTO newsletter_issues (
             newsletter_issue_id, 
-            title, 
-            text_content, 
-            html_content,
+            default_locale,
             published_at
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, now())
         "#,
         newsletter_issue_id,
-        title,
-        text_content,
-        html_content
+        default_variant.locale.as_str()
     );
     transaction.execute(query).await?;
+    for variant in std::iter::once(default_variant).chain(translations) {
+        let query = sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issue_variants (
+                newsletter_issue_id,
+                locale,
+                title,
+                text_content,
+                html_content
+            )
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            newsletter_issue_id,
+            variant.locale.as_str(),
+            variant.title,
+            variant.text_content,
+            variant.html_content
+        );
+        transaction.execute(query).await?;
+    }
+    if let Some(subject_test) = subject_test {
+        insert_subject_test(transaction, newsletter_issue_id, subject_test).await?;
+    }
+    store_email_options(transaction, newsletter_issue_id, email_options).await?;
     Ok(newsletter_issue_id)
 }
 
+/// When the subject line of the issue is being A/B tested, only a random
+/// sample of the subscribers gets it for now, spread evenly over the
+/// candidate subject lines. Deliveries to everybody else are held until
+/// there is a winner.
+///
+/// Subscribers the issue is queued for already are left alone. Returns how
+/// many deliveries were queued.
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub(crate) async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
+) -> Result<u64, sqlx::Error> {
+    let subscribers =
+        sqlx::query_scalar!(r#"SELECT email FROM subscriptions WHERE status = 'confirmed'"#)
+            .fetch_all(transaction.as_mut())
+            .await?;
+    let suppressed = suppressed_emails(transaction.as_mut(), subscribers).await?;
     let query = sqlx::query!(
         r#"
+        WITH recipients AS (
+            SELECT
+                s.email,
+                COALESCE(v.locale, i.default_locale) AS locale,
+                row_number() OVER (ORDER BY random()) AS position,
+                count(*) OVER () AS n_recipients
+            FROM subscriptions s
+            JOIN newsletter_issues i ON i.newsletter_issue_id = $1
+            LEFT JOIN newsletter_issue_variants v
+                ON v.newsletter_issue_id = $1 AND v.locale = s.locale
+            WHERE s.status = 'confirmed' AND s.email <> ALL($2)
+        ),
+        test AS (
+            SELECT t.sample_percent, count(*) AS n_subject_lines
+            FROM subject_tests t
+            JOIN subject_test_variants tv USING (newsletter_issue_id)
+            WHERE t.newsletter_issue_id = $1 AND t.winner IS NULL
+            GROUP BY t.sample_percent
+        )
         INSERT INTO issue_delivery_queue (
             newsletter_issue_id, 
-            subscriber_email
+            subscriber_email,
+            locale,
+            subject_variant,
+            held
         )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        SELECT
+            $1,
+            r.email,
+            r.locale,
+            CASE WHEN r.position <= ceil(r.n_recipients * t.sample_percent / 100.0)
+                THEN ((r.position - 1) % t.n_subject_lines)::INTEGER
+            END,
+            COALESCE(r.position > ceil(r.n_recipients * t.sample_percent / 100.0), false)
+        FROM recipients r
+        LEFT JOIN test t ON true
+        ON CONFLICT DO NOTHING
         "#,
         newsletter_issue_id,
+        &suppressed
     );
-    transaction.execute(query).await?;
-    Ok(())
+    let n_queued = transaction.execute(query).await?.rows_affected();
+    notify_new_tasks(transaction).await?;
+    Ok(n_queued)
 }
@@ -1,3 +1,4 @@
+use crate::i18n::Locale;
 use crate::session_state::TypedSession;
 use crate::utils::{
     e500,
@@ -11,6 +12,7 @@ use std::fmt::Write;
 pub async fn change_password_form(
     session: TypedSession,
     flash_messages: IncomingFlashMessages,
+    locale: Locale,
 ) -> Result<HttpResponse, actix_web::Error> {
     if session.get_user_id().map_err(e500)?.is_none() {
         return Ok(see_other("/login"));
@@ -20,45 +22,54 @@ pub async fn change_password_form(
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+    let title = locale.t("password-title");
+    let current = locale.t("password-current");
+    let current_placeholder = locale.t("password-current-placeholder");
+    let new = locale.t("password-new");
+    let new_placeholder = locale.t("password-new-placeholder");
+    let check = locale.t("password-check");
+    let check_placeholder = locale.t("password-check-placeholder");
+    let submit = locale.t("password-submit");
+    let back = locale.t("back");
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
             r#"<!DOCTYPE html>
-<html lang="en">
+<html lang="{locale}">
 <head>
     <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Change Password</title>
+    <title>{title}</title>
 </head>
 <body>
     {msg_html}
     <form action="/admin/password" method="post">
-        <label>Current password
+        <label>{current}
             <input
                 type="password"
-                placeholder="Enter current password"
+                placeholder="{current_placeholder}"
                 name="current_password"
             >
         </label>
         <br>
-        <label>New password
+        <label>{new}
             <input
             type="password"
-            placeholder="Enter new password"
+            placeholder="{new_placeholder}"
             name="new_password"
             >
         </label>
         <br>
-        <label>Confirm new password
+        <label>{check}
             <input
             type="password"
-            placeholder="Type the new password again"
+            placeholder="{check_placeholder}"
             name="new_password_check"
         >
         </label>
         <br>
-        <button type="submit">Change password</button>
+        <button type="submit">{submit}</button>
     </form>
-    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    <p><a href="/admin/dashboard">{back}</a></p>
 </body>
 </html>"#,
         )))
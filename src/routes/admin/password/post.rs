@@ -1,9 +1,14 @@
+use crate::audit::{
+    record_audit_event,
+    AuditContext,
+    PASSWORD_CHANGED,
+};
 use crate::authentication::{
     validate_credentials,
     AuthError,
     Credentials,
-    UserId,
 };
+use crate::i18n::Locale;
 use crate::routes::admin::dashboard::get_username;
 use crate::utils::{
     e500,
@@ -14,6 +19,7 @@ use actix_web::{
     HttpResponse,
 };
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
 use secrecy::ExposeSecret;
 use secrecy::Secret;
 use sqlx::PgPool;
@@ -26,27 +32,21 @@ pub struct FormData {
 }
 
 pub async fn change_password(
-    form: web::Data<FormData>,
+    form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    user_id: web::ReqData<UserId>,
+    audit: AuditContext,
+    locale: Locale,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let user_id = user_id.into_inner();
-
     if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        FlashMessage::error(locale.t("password-mismatch")).send();
         return Ok(see_other("/admin/password"));
     }
-    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
-        FlashMessage::error(
-            "You entered two different new passwords - the field values must match.",
-        )
-        .send();
-    }
     let new_password = form.new_password.expose_secret();
-    if !is_password_strong(&new_password) {
-        FlashMessage::error("Password must be between 12 and 128 characters long.").send();
+    if !is_password_strong(new_password) {
+        FlashMessage::error(locale.t("password-too-weak")).send();
         return Ok(see_other("/admin/password"));
     }
-    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let username = get_username(audit.user_id, &pool).await.map_err(e500)?;
 
     let credentials = Credentials {
         username,
@@ -55,16 +55,34 @@ pub async fn change_password(
     if let Err(e) = validate_credentials(credentials, &pool).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
-                FlashMessage::error("The current password is incorrect.").send();
+                FlashMessage::error(locale.t("password-incorrect")).send();
                 Ok(see_other("/admin/password"))
             }
-            AuthError::UnexpectedError(_) => Err(e500(e).into()),
+            AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
-    crate::authentication::change_password(*user_id, form.new_password.clone(), &pool)
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    crate::authentication::change_password(
+        audit.user_id,
+        form.new_password.clone(),
+        transaction.as_mut(),
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_event(transaction.as_mut(), &audit, PASSWORD_CHANGED, None)
+        .await
+        .context("Failed to record a password change.")
+        .map_err(e500)?;
+    transaction
+        .commit()
         .await
+        .context("Failed to commit SQL transaction to change a password.")
         .map_err(e500)?;
-    FlashMessage::error("Your password has been changed.").send();
+    FlashMessage::error(locale.t("password-changed")).send();
     Ok(see_other("/admin/password"))
 }
 
@@ -0,0 +1,231 @@
+use crate::consent::list_consent_events;
+use crate::i18n::Locale;
+use crate::utils::e500;
+use actix_web::http::header::{
+    ContentDisposition,
+    ContentType,
+};
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct SubscriberRecord {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    locale: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+pub async fn subscribers_list(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let subscribers = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, status, locale, subscribed_at
+        FROM subscriptions
+        ORDER BY subscribed_at DESC
+        "#,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve subscribers.")
+    .map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for s in subscribers {
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td><a href="/admin/subscribers/{}">{}</a></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            s.id,
+            encode_minimal(&s.email),
+            encode_minimal(&s.name),
+            s.status,
+            encode_minimal(&s.locale),
+            s.subscribed_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+    let title = locale.t("subscribers-title");
+    let email = locale.t("subscribers-email");
+    let name = locale.t("subscribers-name");
+    let status = locale.t("subscribers-status");
+    let language = locale.t("subscribers-locale");
+    let subscribed_at = locale.t("subscribers-subscribed-at");
+    let back = locale.t("back");
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{locale}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr>
+            <th>{email}</th>
+            <th>{name}</th>
+            <th>{status}</th>
+            <th>{language}</th>
+            <th>{subscribed_at}</th>
+        </tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">{back}</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, status, locale, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a subscriber.")
+    .map_err(e500)?;
+    let Some(subscriber) = subscriber else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let consent_events = list_consent_events(&pool, subscriber_id)
+        .await
+        .context("Failed to retrieve consent events.")
+        .map_err(e500)?;
+
+    let unknown = locale.t("subscriber-consent-unknown");
+    let mut consent_html = String::new();
+    for e in consent_events {
+        writeln!(
+            consent_html,
+            r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            e.event_type,
+            e.occurred_at.to_rfc3339(),
+            encode_minimal(e.ip_address.as_deref().unwrap_or(&unknown)),
+            encode_minimal(e.user_agent.as_deref().unwrap_or(&unknown)),
+            encode_minimal(&e.source),
+            encode_minimal(&e.consent_wording),
+        )
+        .unwrap();
+    }
+    let email = encode_minimal(&subscriber.email);
+    let name = encode_minimal(&subscriber.name);
+    let status = subscriber.status;
+    let subscriber_locale = encode_minimal(&subscriber.locale);
+    let subscribed_at = subscriber.subscribed_at.to_rfc3339();
+    let title = locale.t_args("subscriber-title", &[("email", email.as_str().into())]);
+    let email_label = locale.t("subscribers-email");
+    let name_label = locale.t("subscribers-name");
+    let status_label = locale.t("subscribers-status");
+    let locale_label = locale.t("subscribers-locale");
+    let subscribed_at_label = locale.t("subscribers-subscribed-at");
+    let consent_history = locale.t("subscriber-consent-history");
+    let event = locale.t("subscriber-consent-event");
+    let occurred_at = locale.t("subscriber-consent-occurred-at");
+    let ip_address = locale.t("subscriber-consent-ip-address");
+    let user_agent = locale.t("subscriber-consent-user-agent");
+    let source = locale.t("subscriber-consent-source");
+    let wording = locale.t("subscriber-consent-wording");
+    let export = locale.t("subscriber-export");
+    let erase = locale.t("subscriber-erase");
+    let back = locale.t("back");
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{locale}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <p>{email_label}: {email}</p>
+    <p>{name_label}: {name}</p>
+    <p>{status_label}: {status}</p>
+    <p>{locale_label}: {subscriber_locale}</p>
+    <p>{subscribed_at_label}: {subscribed_at}</p>
+    <p>{consent_history}</p>
+    <table>
+        <tr>
+            <th>{event}</th>
+            <th>{occurred_at}</th>
+            <th>{ip_address}</th>
+            <th>{user_agent}</th>
+            <th>{source}</th>
+            <th>{wording}</th>
+        </tr>
+        {consent_html}
+    </table>
+    <p><a href="/admin/subscribers/{subscriber_id}/export">{export}</a></p>
+    <form action="/admin/subscribers/{subscriber_id}/erase" method="post">
+        <button type="submit">{erase}</button>
+    </form>
+    <p><a href="/admin/subscribers">{back}</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn export_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    match crate::subscriber_data::export_subscriber_data(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(export) => Ok(HttpResponse::Ok()
+            .insert_header(ContentDisposition::attachment(format!(
+                "subscriber-{subscriber_id}.json"
+            )))
+            .json(export)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
@@ -0,0 +1,9 @@
+mod get;
+mod post;
+
+pub use get::{
+    export_subscriber,
+    subscriber_details,
+    subscribers_list,
+};
+pub use post::erase_subscriber;
@@ -0,0 +1,59 @@
+use crate::audit::{
+    record_audit_event,
+    AuditContext,
+    SUBSCRIBER_ERASED,
+};
+use crate::i18n::Locale;
+use crate::utils::{
+    e500,
+    see_other,
+};
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[tracing::instrument(name = "Erase a subscriber", skip(pool, audit, locale))]
+pub async fn erase_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let erased = crate::subscriber_data::erase_subscriber_data(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?;
+    if erased {
+        let target = format!("subscriber:{subscriber_id}");
+        record_audit_event(
+            transaction.as_mut(),
+            &audit,
+            SUBSCRIBER_ERASED,
+            Some(&target),
+        )
+        .await
+        .context("Failed to record the erasure of a subscriber.")
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")
+        .map_err(e500)?;
+    if erased {
+        FlashMessage::info(locale.t("subscriber-erased")).send();
+    } else {
+        FlashMessage::error(locale.t("subscriber-not-found")).send();
+    }
+    Ok(see_other("/admin/subscribers"))
+}
@@ -0,0 +1,111 @@
+use crate::i18n::Locale;
+use crate::suppression::{
+    list_suppressions,
+    REASONS,
+};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn suppressions_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let remove = locale.t("suppressions-remove");
+    let suppressions = list_suppressions(&pool).await.map_err(e500)?;
+    let mut suppressions_html = String::new();
+    for s in suppressions {
+        writeln!(
+            suppressions_html,
+            r#"<tr>
+            <td><code>{hash}</code></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>
+                <form action="/admin/suppressions/{hash}/remove" method="post">
+                    <button type="submit">{remove}</button>
+                </form>
+            </td>
+        </tr>"#,
+            s.reason,
+            s.created_at.to_rfc3339(),
+            hash = s.email_hash,
+        )
+        .unwrap();
+    }
+    let mut reasons_html = String::new();
+    for reason in REASONS {
+        writeln!(
+            reasons_html,
+            r#"<option value="{reason}">{reason}</option>"#
+        )
+        .unwrap();
+    }
+
+    let title = locale.t("suppressions-title");
+    let explanation = locale.t("suppressions-explanation");
+    let email_hash = locale.t("suppressions-email-hash");
+    let reason = locale.t("suppressions-reason");
+    let created_at = locale.t("suppressions-created-at");
+    let email = locale.t("suppressions-email");
+    let email_placeholder = locale.t("suppressions-email-placeholder");
+    let add = locale.t("suppressions-add");
+    let import_emails = locale.t("suppressions-import-emails");
+    let import = locale.t("suppressions-import");
+    let back = locale.t("back");
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{locale}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    {msg_html}
+    <p>{explanation}</p>
+    <table>
+        <tr>
+            <th>{email_hash}</th>
+            <th>{reason}</th>
+            <th>{created_at}</th>
+            <th></th>
+        </tr>
+        {suppressions_html}
+    </table>
+    <form action="/admin/suppressions" method="post">
+        <label>{email}
+            <input type="text" placeholder="{email_placeholder}" name="email">
+        </label>
+        <label>{reason}
+            <select name="reason">{reasons_html}</select>
+        </label>
+        <button type="submit">{add}</button>
+    </form>
+    <form action="/admin/suppressions/import" method="post">
+        <label>{import_emails}
+            <textarea name="emails" rows="10" cols="50"></textarea>
+        </label>
+        <label>{reason}
+            <select name="reason">{reasons_html}</select>
+        </label>
+        <button type="submit">{import}</button>
+    </form>
+    <p><a href="/admin/dashboard">{back}</a></p>
+</body>
+</html>"#,
+        )))
+}
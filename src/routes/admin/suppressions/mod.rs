@@ -0,0 +1,9 @@
+mod get;
+mod post;
+
+pub use get::suppressions_form;
+pub use post::{
+    add_suppression,
+    import_suppressions,
+    remove_suppression,
+};
@@ -0,0 +1,178 @@
+use crate::audit::{
+    record_audit_event,
+    AuditContext,
+    SUPPRESSIONS_IMPORTED,
+    SUPPRESSION_ADDED,
+    SUPPRESSION_REMOVED,
+};
+use crate::domain::SubscriberEmail;
+use crate::i18n::Locale;
+use crate::routes::email_error;
+use crate::suppression::{
+    email_hash,
+    suppress_email,
+    REASONS,
+};
+use crate::utils::{
+    e500,
+    see_other,
+};
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct SuppressionFormData {
+    email: String,
+    reason: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ImportFormData {
+    emails: String,
+    reason: String,
+}
+
+#[tracing::instrument(name = "Add a suppression", skip_all)]
+pub async fn add_suppression(
+    form: web::Form<SuppressionFormData>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let SuppressionFormData { email, reason } = form.0;
+    if !REASONS.contains(&reason.as_str()) {
+        FlashMessage::error(locale.t("suppressions-unknown-reason")).send();
+        return Ok(see_other("/admin/suppressions"));
+    }
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(email_error(e, locale).message).send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let added = suppress_email(transaction.as_mut(), email.as_ref(), &reason)
+        .await
+        .map_err(e500)?;
+    if added {
+        // Suppressions only keep a hash of the address, and so does the log
+        let target = format!("suppression:{}", email_hash(email.as_ref()));
+        record_audit_event(
+            transaction.as_mut(),
+            &audit,
+            SUPPRESSION_ADDED,
+            Some(&target),
+        )
+        .await
+        .context("Failed to record a new suppression.")
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to add a suppression.")
+        .map_err(e500)?;
+    if added {
+        FlashMessage::info(locale.t("suppressions-added")).send();
+    } else {
+        FlashMessage::info(locale.t("suppressions-already-added")).send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
+
+#[tracing::instrument(name = "Import suppressions", skip_all)]
+pub async fn import_suppressions(
+    form: web::Form<ImportFormData>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ImportFormData { emails, reason } = form.0;
+    if !REASONS.contains(&reason.as_str()) {
+        FlashMessage::error(locale.t("suppressions-unknown-reason")).send();
+        return Ok(see_other("/admin/suppressions"));
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let (mut n_added, mut n_invalid) = (0, 0);
+    for line in emails.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let Ok(email) = SubscriberEmail::parse(line.to_owned()) else {
+            n_invalid += 1;
+            continue;
+        };
+        if suppress_email(transaction.as_mut(), email.as_ref(), &reason)
+            .await
+            .map_err(e500)?
+        {
+            n_added += 1;
+        }
+    }
+    record_audit_event(transaction.as_mut(), &audit, SUPPRESSIONS_IMPORTED, None)
+        .await
+        .context("Failed to record an import of suppressions.")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import suppressions.")
+        .map_err(e500)?;
+    FlashMessage::info(locale.t_args(
+        "suppressions-imported",
+        &[("n_added", n_added.into()), ("n_invalid", n_invalid.into())],
+    ))
+    .send();
+    Ok(see_other("/admin/suppressions"))
+}
+
+#[tracing::instrument(name = "Remove a suppression", skip(pool, audit, locale))]
+pub async fn remove_suppression(
+    email_hash: web::Path<String>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let removed = crate::suppression::remove_suppression(transaction.as_mut(), &email_hash)
+        .await
+        .map_err(e500)?;
+    if removed {
+        let target = format!("suppression:{email_hash}");
+        record_audit_event(
+            transaction.as_mut(),
+            &audit,
+            SUPPRESSION_REMOVED,
+            Some(&target),
+        )
+        .await
+        .context("Failed to record the removal of a suppression.")
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to remove a suppression.")
+        .map_err(e500)?;
+    if removed {
+        FlashMessage::info(locale.t("suppressions-removed")).send();
+    } else {
+        FlashMessage::error(locale.t("suppressions-not-found")).send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
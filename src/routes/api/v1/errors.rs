@@ -0,0 +1,56 @@
+use crate::routes::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{
+    HttpResponse,
+    ResponseError,
+};
+
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ApiError {
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::ValidationError(_) => "validation_error",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::UnexpectedError(_) => "internal_error",
+        }
+    }
+}
+
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // Never leak the details of unexpected failures to API clients.
+        let message = match self {
+            ApiError::UnexpectedError(_) => "Something went wrong.".to_string(),
+            e => e.to_string(),
+        };
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": {
+                "code": self.code(),
+                "message": message
+            }
+        }))
+    }
+}
@@ -0,0 +1,16 @@
+mod errors;
+mod newsletter_issues;
+mod subscribers;
+
+pub use errors::ApiError;
+pub use newsletter_issues::{
+    create_newsletter_issue,
+    get_delivery_status,
+    get_newsletter_issue,
+    list_newsletter_issues,
+};
+pub use subscribers::{
+    get_subscriber,
+    list_subscriber_consent_events,
+    list_subscribers,
+};
@@ -0,0 +1,328 @@
+use super::ApiError;
+use crate::audit::{
+    record_audit_event,
+    AuditContext,
+    NEWSLETTER_ISSUE_PUBLISHED,
+};
+use crate::email_client::EmailOptions;
+use crate::email_options::{
+    get_email_options,
+    IssueEmailSettings,
+    NewEmailOptions,
+};
+use crate::i18n::Locale;
+use crate::idempotency::KeyTransaction;
+use crate::routes::{
+    enqueue_delivery_tasks,
+    insert_newsletter_issue,
+    variant_delivery_statuses,
+    IssueVariant,
+};
+use crate::subject_test::{
+    Metric,
+    SubjectTest,
+    SubjectTestError,
+    DEFAULT_SAMPLE_PERCENT,
+    DEFAULT_WAIT_MINUTES,
+};
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+#[derive(serde::Serialize)]
+pub struct NewsletterIssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct NewsletterIssueVariant {
+    locale: String,
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+/// The top-level content fields are those of the default variant, in
+/// `default_locale`.
+#[derive(serde::Deserialize)]
+pub struct NewIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    default_locale: Option<String>,
+    #[serde(default)]
+    translations: Vec<NewIssueTranslation>,
+    subject_test: Option<NewSubjectTest>,
+    /// The id of a configured sender identity, the default sender if unset.
+    sender: Option<String>,
+    reply_to: Option<String>,
+    #[serde(default)]
+    headers: Vec<NewHeader>,
+    tag: Option<String>,
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+    message_stream: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewHeader {
+    name: String,
+    value: String,
+}
+
+/// Alternative subject lines to A/B test against the title.
+#[derive(serde::Deserialize)]
+pub struct NewSubjectTest {
+    subject_lines: Vec<String>,
+    metric: Option<String>,
+    sample_percent: Option<i32>,
+    wait_minutes: Option<i64>,
+}
+
+impl NewSubjectTest {
+    fn parse(self, title: &str) -> Result<SubjectTest, SubjectTestError> {
+        let metric = match self.metric {
+            Some(metric) => Metric::parse(&metric)?,
+            None => Metric::default(),
+        };
+        SubjectTest::new(
+            title,
+            self.subject_lines,
+            metric,
+            self.sample_percent.unwrap_or(DEFAULT_SAMPLE_PERCENT),
+            self.wait_minutes.unwrap_or(DEFAULT_WAIT_MINUTES),
+        )
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct NewIssueTranslation {
+    locale: String,
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+struct ParsedIssue {
+    default_variant: IssueVariant,
+    translations: Vec<IssueVariant>,
+    subject_test: Option<SubjectTest>,
+    email_options: EmailOptions,
+}
+
+impl NewIssue {
+    fn parse(self, settings: &IssueEmailSettings) -> Result<ParsedIssue, ApiError> {
+        let email_options = NewEmailOptions {
+            sender: self.sender.unwrap_or_default(),
+            reply_to: self.reply_to.unwrap_or_default(),
+            headers: self
+                .headers
+                .into_iter()
+                .map(|h| (h.name, h.value))
+                .collect(),
+            tag: self.tag.unwrap_or_default(),
+            metadata: self.metadata.into_iter().collect(),
+            message_stream: self.message_stream.unwrap_or_default(),
+        }
+        .parse(settings)
+        .map_err(|e| ApiError::ValidationError(e.to_string()))?;
+        let subject_test = match self.subject_test {
+            Some(_) if !self.translations.is_empty() => Some(Err(SubjectTestError::Translated)),
+            Some(subject_test) => Some(subject_test.parse(&self.title)),
+            None => None,
+        }
+        .transpose()
+        .map_err(|e| ApiError::ValidationError(e.to_string()))?;
+        let default_locale = match self.default_locale {
+            Some(locale) => parse_locale(&locale)?,
+            None => Locale::default(),
+        };
+        let default_variant = IssueVariant {
+            locale: default_locale,
+            title: self.title,
+            text_content: self.text_content,
+            html_content: self.html_content,
+        };
+        let mut variants = vec![default_variant];
+        for translation in self.translations {
+            let locale = parse_locale(&translation.locale)?;
+            if variants.iter().any(|v| v.locale == locale) {
+                return Err(ApiError::ValidationError(format!(
+                    "The issue has more than one variant in `{locale}`."
+                )));
+            }
+            variants.push(IssueVariant {
+                locale,
+                title: translation.title,
+                text_content: translation.text_content,
+                html_content: translation.html_content,
+            });
+        }
+        if variants.iter().any(|v| v.title.trim().is_empty()) {
+            return Err(ApiError::ValidationError(
+                "The issue title cannot be empty.".into(),
+            ));
+        }
+        let default_variant = variants.remove(0);
+        Ok(ParsedIssue {
+            default_variant,
+            translations: variants,
+            subject_test,
+            email_options,
+        })
+    }
+}
+
+fn parse_locale(locale: &str) -> Result<Locale, ApiError> {
+    Locale::parse(locale)
+        .ok_or_else(|| ApiError::ValidationError(format!("`{locale}` is not a supported locale.")))
+}
+
+#[tracing::instrument(name = "List newsletter issues", skip(pool))]
+pub async fn list_newsletter_issues(pool: web::Data<PgPool>) -> Result<HttpResponse, ApiError> {
+    let issues = sqlx::query_as!(
+        NewsletterIssueSummary,
+        r#"
+        SELECT i.newsletter_issue_id, v.title, i.published_at
+        FROM newsletter_issues i
+        JOIN newsletter_issue_variants v
+            ON v.newsletter_issue_id = i.newsletter_issue_id AND v.locale = i.default_locale
+        ORDER BY i.published_at DESC
+        "#,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve newsletter issues.")?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "newsletter_issues": issues })))
+}
+
+#[tracing::instrument(name = "Get newsletter issue", skip(pool))]
+pub async fn get_newsletter_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let issue_id = issue_id.into_inner();
+    let issue = sqlx::query!(
+        r#"
+        SELECT default_locale, published_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve a newsletter issue.")?
+    .ok_or_else(|| issue_not_found(issue_id))?;
+    let variants = sqlx::query_as!(
+        NewsletterIssueVariant,
+        r#"
+        SELECT locale, title, text_content, html_content
+        FROM newsletter_issue_variants
+        WHERE newsletter_issue_id = $1
+        ORDER BY locale
+        "#,
+        issue_id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the variants of a newsletter issue.")?;
+    let default_variant = variants
+        .iter()
+        .find(|v| v.locale == issue.default_locale)
+        .context("A newsletter issue has no variant in its default locale.")?;
+    let email_options = get_email_options(pool.get_ref(), issue_id).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "newsletter_issue_id": issue_id,
+        "title": default_variant.title,
+        "text_content": default_variant.text_content,
+        "html_content": default_variant.html_content,
+        "published_at": issue.published_at,
+        "default_locale": issue.default_locale,
+        "variants": variants,
+        "sender": email_options.sender.as_ref().map(|s| serde_json::json!({
+            "name": s.name(),
+            "email": s.email(),
+        })),
+        "reply_to": email_options.reply_to,
+        "headers": email_options
+            .headers
+            .iter()
+            .map(|h| serde_json::json!({ "name": h.name, "value": h.value }))
+            .collect::<Vec<_>>(),
+        "tag": email_options.tag,
+        "metadata": email_options.metadata,
+        "message_stream": email_options.message_stream,
+    })))
+}
+
+#[tracing::instrument(name = "Get newsletter issue delivery status", skip(pool))]
+pub async fn get_delivery_status(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let issue_id = issue_id.into_inner();
+    let variants = variant_delivery_statuses(&pool, issue_id)
+        .await
+        .context("Failed to retrieve the delivery status of a newsletter issue.")?;
+    // Every issue has at least its default variant.
+    if variants.is_empty() {
+        return Err(issue_not_found(issue_id));
+    }
+    let pending_deliveries: i64 = variants.iter().map(|v| v.pending_deliveries).sum();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "newsletter_issue_id": issue_id,
+        "pending_deliveries": pending_deliveries,
+        "variants": variants,
+    })))
+}
+
+#[tracing::instrument(
+    name = "Create a newsletter issue via the API",
+    skip_all,
+    fields(user_id=%audit.user_id)
+)]
+pub async fn create_newsletter_issue(
+    body: web::Json<NewIssue>,
+    issue_email_settings: web::Data<IssueEmailSettings>,
+    transaction: KeyTransaction,
+    audit: AuditContext,
+) -> Result<HttpResponse, ApiError> {
+    let issue = body.0.parse(&issue_email_settings)?;
+    let mut transaction = transaction.lock().await;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &issue.default_variant,
+        &issue.translations,
+        issue.subject_test.as_ref(),
+        &issue.email_options,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    let target = format!("newsletter_issue:{issue_id}");
+    record_audit_event(
+        transaction.as_mut(),
+        &audit,
+        NEWSLETTER_ISSUE_PUBLISHED,
+        Some(&target),
+    )
+    .await
+    .context("Failed to record the publication of a newsletter issue")?;
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "newsletter_issue_id": issue_id
+    })))
+}
+
+fn issue_not_found(issue_id: Uuid) -> ApiError {
+    ApiError::NotFound(format!("There is no newsletter issue with id {issue_id}."))
+}
@@ -0,0 +1,111 @@
+use super::ApiError;
+use crate::consent::list_consent_events;
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use anyhow::Context;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Serialize)]
+pub struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    locale: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ListParameters {
+    status: Option<String>,
+    #[serde(default = "default_limit")]
+    limit: i64,
+    #[serde(default)]
+    offset: i64,
+}
+
+fn default_limit() -> i64 {
+    100
+}
+
+const MAX_LIMIT: i64 = 1000;
+
+#[tracing::instrument(name = "List subscribers", skip(parameters, pool))]
+pub async fn list_subscribers(
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let ListParameters {
+        status,
+        limit,
+        offset,
+    } = parameters.0;
+    if !(1..=MAX_LIMIT).contains(&limit) || offset < 0 {
+        return Err(ApiError::ValidationError(format!(
+            "`limit` must be between 1 and {MAX_LIMIT} and `offset` cannot be negative."
+        )));
+    }
+    let subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, locale, subscribed_at
+        FROM subscriptions
+        WHERE $1::TEXT IS NULL OR status = $1
+        ORDER BY subscribed_at, id
+        LIMIT $2
+        OFFSET $3
+        "#,
+        status,
+        limit,
+        offset
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve subscribers.")?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "subscribers": subscribers })))
+}
+
+#[tracing::instrument(name = "Get subscriber", skip(pool))]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let subscriber = fetch_subscriber(&pool, subscriber_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+/// Oldest first.
+#[tracing::instrument(name = "List the consent events of a subscriber", skip(pool))]
+pub async fn list_subscriber_consent_events(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let subscriber = fetch_subscriber(&pool, subscriber_id.into_inner()).await?;
+    let consent_events = list_consent_events(&pool, subscriber.id)
+        .await
+        .context("Failed to retrieve consent events.")?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "consent_events": consent_events })))
+}
+
+async fn fetch_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<Subscriber, ApiError> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, locale, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a subscriber.")?
+    .ok_or_else(|| ApiError::NotFound(format!("There is no subscriber with id {subscriber_id}.")))
+}
@@ -1,8 +1,14 @@
+use crate::i18n::Locale;
 use actix_web::http::header::ContentType;
 use actix_web::HttpResponse;
 
-pub async fn home() -> HttpResponse {
+pub async fn home(locale: Locale) -> HttpResponse {
     HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+        .body(format!(
+            include_str!("home.html"),
+            lang = locale,
+            title = locale.t("home-title"),
+            welcome = locale.t("home-welcome"),
+        ))
 }
@@ -1,3 +1,4 @@
+use crate::i18n::Locale;
 use actix_web::{
     http::header::ContentType,
     HttpResponse,
@@ -5,38 +6,44 @@ use actix_web::{
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+pub async fn login_form(flash_messages: IncomingFlashMessages, locale: Locale) -> HttpResponse {
     let mut error_html = String::new();
     for m in flash_messages.iter() {
         writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+    let title = locale.t("login-title");
+    let username = locale.t("login-username");
+    let username_placeholder = locale.t("login-username-placeholder");
+    let password = locale.t("login-password");
+    let password_placeholder = locale.t("login-password-placeholder");
+    let submit = locale.t("login-submit");
     HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
             r#"<!DOCTYPE html>
-        <html lang="en">
+        <html lang="{locale}">
         <head>
             <meta http-equiv="content-type" content="text/html; charset=utf-8">
-            <title>Login</title>
+            <title>{title}</title>
         </head>
         <body>
             {error_html}
         <form action="/login" method="post">
-            <label>Username
+            <label>{username}
                 <input
                     type="text"
-                    placeholder="Enter Username"
+                    placeholder="{username_placeholder}"
                     name="username"
             >
             </label>
-            <label>Password
+            <label>{password}
                     <input
                     type="password"
-                    placeholder="Enter Password"
+                    placeholder="{password_placeholder}"
                     name="password"
             >
             </label>
-            <button type="submit">Login</button>
+            <button type="submit">{submit}</button>
         </form>
     </body>
     </html>"#,
@@ -3,6 +3,7 @@ use crate::authentication::{
     AuthError,
     Credentials,
 };
+use crate::i18n::Locale;
 use crate::routes::error_chain_fmt;
 use crate::session_state::TypedSession;
 use actix_web::error::InternalError;
@@ -36,13 +37,14 @@ impl std::fmt::Debug for LoginError {
 }
 
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(form, pool, session, locale),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    locale: Locale,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
     let credentials = Credentials {
         username: form.0.username,
@@ -50,11 +52,11 @@ pub async fn login(
     };
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
             session.renew(); // to prevent session fixation attacks
             session
                 .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into()), locale))?;
             Ok(HttpResponse::SeeOther()
                 .insert_header((LOCATION, "/admin/dashboard"))
                 .finish())
@@ -64,13 +66,17 @@ pub async fn login(
                 AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
-            Err(login_redirect(e))
+            Err(login_redirect(e, locale))
         }
     }
 }
 
-fn login_redirect(e: LoginError) -> InternalError<LoginError> {
-    FlashMessage::error(e.to_string()).send();
+fn login_redirect(e: LoginError, locale: Locale) -> InternalError<LoginError> {
+    let message = match e {
+        LoginError::AuthError(_) => locale.t("login-failed"),
+        LoginError::UnexpectedError(_) => locale.t("login-unexpected-error"),
+    };
+    FlashMessage::error(message).send();
     let response = HttpResponse::SeeOther()
         .insert_header((LOCATION, "/login"))
         .finish();
@@ -1,13 +1,20 @@
 mod admin;
+pub mod api;
 mod health_check;
 mod home;
 mod login;
+mod newsletter_tracking;
 mod subscriptions;
+mod subscriptions_challenge;
 mod subscriptions_confirm;
+mod subscriptions_data;
 
 pub use admin::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
+pub use newsletter_tracking::*;
 pub use subscriptions::*;
+pub use subscriptions_challenge::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_data::*;
@@ -0,0 +1,60 @@
+use crate::subject_test::{
+    record_click,
+    record_open,
+};
+use crate::utils::{
+    e500,
+    see_other,
+};
+use actix_web::http::header::{
+    CacheControl,
+    CacheDirective,
+};
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A transparent 1x1 GIF.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[derive(serde::Deserialize)]
+pub struct ClickParameters {
+    url: String,
+}
+
+#[tracing::instrument(name = "Track a newsletter open", skip(pool))]
+pub async fn track_open(
+    tracking_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    record_open(&pool, tracking_id.into_inner())
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        // Mail clients must ask again for every open.
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(PIXEL))
+}
+
+#[tracing::instrument(name = "Track a newsletter click", skip(parameters, pool))]
+pub async fn track_click(
+    tracking_id: web::Path<Uuid>,
+    parameters: web::Query<ClickParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !record_click(&pool, tracking_id.into_inner(), &parameters.url)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    Ok(see_other(&parameters.url))
+}
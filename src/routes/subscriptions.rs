@@ -1,6 +1,9 @@
+use actix_web::http::header::ContentType;
 use actix_web::http::StatusCode;
 use actix_web::{
     web,
+    Either,
+    HttpRequest,
     HttpResponse,
     ResponseError,
 };
@@ -18,34 +21,152 @@ use sqlx::{
 };
 use uuid::Uuid;
 
+use crate::consent::{
+    record_consent_event,
+    ConsentContext,
+    ConsentWording,
+    SUBSCRIBED,
+};
 use crate::domain::{
     NewSubscriber,
     SubscriberEmail,
+    SubscriberEmailError,
     SubscriberName,
+    SubscriberNameError,
 };
 use crate::email_client::EmailClient;
+use crate::i18n::Locale;
+use crate::idempotency::KeyTransaction;
+use crate::signup_protection::{
+    ChallengeError,
+    SignupProtection,
+};
 use crate::startup::ApplicationBaseUrl;
+use crate::suppression::is_suppressed;
+use crate::utils::{
+    client_ip,
+    ErrorCode,
+};
 
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
-    #[error("{0}")]
-    ValidationError(String),
+    #[error("{}", .0.message)]
+    ValidationError(FieldError),
+    #[error("Too many signup attempts, please try again later.")]
+    TooManyRequests,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
+
+/// A validation failure tied to the form field that caused it, so that
+/// clients can display it next to the offending input.
+#[derive(Debug, serde::Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    /// Why the field was rejected, for clients that display their own
+    /// messages.
+    pub reason: &'static str,
+    pub message: String,
+    /// A corrected value for the field, when we can guess it.
+    pub suggestion: Option<String>,
+}
+
+impl FieldError {
+    fn new(field: &'static str, reason: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            field,
+            reason,
+            message: message.into(),
+            suggestion: None,
+        }
+    }
+}
+
+fn name_error(e: SubscriberNameError, locale: Locale) -> FieldError {
+    let message = match &e {
+        SubscriberNameError::Empty => locale.t("signup-name-empty"),
+        SubscriberNameError::TooLong { max_length } => locale.t_args(
+            "signup-name-too-long",
+            &[("max_length", (*max_length).into())],
+        ),
+        SubscriberNameError::ForbiddenCharacter(c) => locale.t_args(
+            "signup-name-forbidden-character",
+            &[("character", c.to_string().into())],
+        ),
+    };
+    FieldError::new("name", e.code(), message)
+}
+
+pub(crate) fn email_error(e: SubscriberEmailError, locale: Locale) -> FieldError {
+    let message = match &e {
+        SubscriberEmailError::Invalid(email) => {
+            locale.t_args("signup-email-invalid", &[("email", email.as_str().into())])
+        }
+    };
+    FieldError::new("email", e.code(), message)
+}
+
+fn likely_typo_error(
+    email: &SubscriberEmail,
+    suggestion: SubscriberEmail,
+    locale: Locale,
+) -> FieldError {
+    let message = locale.t_args(
+        "signup-email-likely-typo",
+        &[
+            ("email", email.as_ref().into()),
+            ("suggestion", suggestion.as_ref().into()),
+        ],
+    );
+    FieldError {
+        suggestion: Some(suggestion.to_string()),
+        ..FieldError::new("email", "likely_typo", message)
+    }
+}
+
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    /// Honeypot: the signup form hides this field, so only bots fill it in.
+    #[serde(default)]
+    website: String,
+    challenge: Option<String>,
+    challenge_solution: Option<String>,
+    /// Identifies the form or widget the signup comes from.
+    source: Option<String>,
+    /// The language the subscriber wants to hear from us in. Defaults to
+    /// the one negotiated from the `Accept-Language` header.
+    locale: Option<String>,
+    /// Addresses whose domain looks like a typo of a common one are rejected
+    /// with a suggestion, unless the subscriber confirms them as typed.
+    #[serde(default)]
+    keep_email_as_typed: bool,
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
-
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { email, name })
+impl FormData {
+    fn into_new_subscriber(
+        self,
+        max_name_length: usize,
+        lowercase_email: bool,
+        locale: Locale,
+    ) -> Result<NewSubscriber, FieldError> {
+        let name = SubscriberName::parse_with_max_length(self.name, max_name_length)
+            .map_err(|e| name_error(e, locale))?;
+        let mut email = SubscriberEmail::parse(self.email).map_err(|e| email_error(e, locale))?;
+        if lowercase_email {
+            email = email.lowercase();
+        }
+        if !self.keep_email_as_typed {
+            if let Some(suggestion) = email.suggestion() {
+                return Err(likely_typo_error(&email, suggestion, locale));
+            }
+        }
+        Ok(NewSubscriber {
+            email,
+            name,
+            locale,
+        })
     }
 }
 
@@ -59,43 +180,182 @@ impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
             SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscribeError::ValidationError(e) => {
+                HttpResponse::build(self.status_code()).json(serde_json::json!({
+                    "error": {
+                        "code": "validation_error",
+                        "field": e.field,
+                        "reason": e.reason,
+                        "message": e.message,
+                        "suggestion": e.suggestion
+                    }
+                }))
+            }
+            SubscribeError::TooManyRequests => {
+                HttpResponse::build(self.status_code()).json(serde_json::json!({
+                    "error": {
+                        "code": "rate_limited",
+                        "message": self.to_string()
+                    }
+                }))
+            }
+            SubscribeError::UnexpectedError(_) => HttpResponse::build(self.status_code())
+                .content_type(ContentType::plaintext())
+                .body(self.to_string()),
+        }
+    }
 }
 
 // creates a span
 #[tracing::instrument(
     name = "Adding a new subscriber.",
-    skip(form, pool, email_client, base_url),
+    skip(
+        request,
+        body,
+        pool,
+        email_client,
+        base_url,
+        protection,
+        consent_wording,
+        key_transaction
+    ),
     fields(
-        subscriber_email = %form.email,
-        subscriber_name= %form.name
+        subscriber_email = tracing::field::Empty,
+        subscriber_name = tracing::field::Empty
     )
 )]
-
+// The signup widget on the marketing site posts JSON, while plain HTML forms
+// are url-encoded: both are accepted. Clients may send an idempotency key, so
+// that a double submission does not sign up twice.
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    request: HttpRequest,
+    body: Either<web::Form<FormData>, web::Json<FormData>>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    protection: web::Data<SignupProtection>,
+    consent_wording: web::Data<ConsentWording>,
+    key_transaction: Option<KeyTransaction>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
-    let mut transaction = pool
-        .begin()
-        .await
-        .context("Failed to acquire a Postgres connection from the pool")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
-        .await
-        .context("Failed to insert new subscriber in the database.")?;
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    let form = match body {
+        Either::Left(form) => form.into_inner(),
+        Either::Right(json) => json.into_inner(),
+    };
+    tracing::Span::current()
+        .record("subscriber_email", tracing::field::display(&form.email))
+        .record("subscriber_name", tracing::field::display(&form.name));
+    if !form.website.is_empty() {
+        // Pretend everything went fine: we do not want to teach bots how to
+        // get past the honeypot.
+        tracing::warn!("The honeypot field was filled in, ignoring the signup.");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let ip = client_ip(&request).unwrap_or_default();
+    if !protection.allow_ip(&ip) {
+        tracing::warn!(client_ip = %ip, "Too many signup attempts from the same IP.");
+        return Err(SubscribeError::TooManyRequests);
+    }
+    let challenge = form.challenge.clone();
+    let challenge_solution = form.challenge_solution.clone();
+    let consent_context = ConsentContext::from_request(&request, form.source.clone());
+    let locale = form
+        .locale
+        .as_deref()
+        .and_then(Locale::parse)
+        .unwrap_or_else(|| Locale::from_accept_language(&request));
+    let new_subscriber = form
+        .into_new_subscriber(
+            protection.max_name_length(),
+            protection.lowercase_email_local_parts(),
+            locale,
+        )
+        .map_err(SubscribeError::ValidationError)?;
+    let email_domain = new_subscriber.email.domain();
+    if protection.is_disposable(email_domain) {
+        return Err(SubscribeError::ValidationError(FieldError::new(
+            "email",
+            "disposable",
+            locale.t("signup-email-disposable"),
+        )));
+    }
+    protection
+        .verify_challenge(&pool, challenge.as_deref(), challenge_solution.as_deref())
         .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
-    transaction
-        .commit()
+        .map_err(|e| match e {
+            ChallengeError::UnexpectedError(e) => SubscribeError::UnexpectedError(e),
+            e => {
+                let message = locale.t(&format!("signup-challenge-{}", e.code().replace('_', "-")));
+                SubscribeError::ValidationError(FieldError::new("challenge", "invalid", message))
+            }
+        })?;
+    if !protection.allow_email_domain(email_domain) {
+        tracing::warn!(%email_domain, "Too many signup attempts for the same email domain.");
+        return Err(SubscribeError::TooManyRequests);
+    }
+    if !protection.has_mail_servers(email_domain).await {
+        return Err(SubscribeError::ValidationError(FieldError::new(
+            "email",
+            "no_mail_servers",
+            locale.t_args(
+                "signup-email-no-mail-servers",
+                &[("domain", email_domain.into())],
+            ),
+        )));
+    }
+    if is_suppressed(pool.get_ref(), new_subscriber.email.as_ref())
         .await
-        .context("Failed to commit SQL transaction to store a new subscriber.")?;
+        .context("Failed to check the suppression list.")?
+    {
+        // Answer as if the signup went through: whether an address is
+        // suppressed is nobody's business.
+        tracing::info!("The email address is suppressed, ignoring the signup.");
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let subscription_token = match key_transaction {
+        // Committed along with the response, once the email is sent
+        Some(key_transaction) => {
+            let mut transaction = key_transaction.lock().await;
+            store_new_subscriber(
+                &mut transaction,
+                &new_subscriber,
+                &consent_context,
+                &consent_wording,
+            )
+            .await?
+        }
+        None => {
+            let mut transaction = pool
+                .begin()
+                .await
+                .context("Failed to acquire a Postgres connection from the pool")?;
+            let subscription_token = store_new_subscriber(
+                &mut transaction,
+                &new_subscriber,
+                &consent_context,
+                &consent_wording,
+            )
+            .await?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to store a new subscriber.")?;
+            subscription_token
+        }
+    };
+    let Some(subscription_token) = subscription_token else {
+        // Same answer as for a new address: signing up must not tell who is
+        // subscribed already.
+        tracing::info!("The email address is confirmed already, ignoring the signup.");
+        return Ok(HttpResponse::Ok().finish());
+    };
     send_confirmation_email(
         &email_client,
         new_subscriber,
@@ -107,7 +367,48 @@ pub async fn subscribe(
     Ok(HttpResponse::Ok().finish())
 }
 
-fn generate_subscription_token() -> String {
+/// Returns the token confirming the subscription, or `None` if the address is
+/// confirmed already. Signing up again while the subscription is pending gets
+/// a new token, and records the consent again.
+async fn store_new_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    consent_context: &ConsentContext,
+    consent_wording: &ConsentWording,
+) -> Result<Option<String>, anyhow::Error> {
+    let subscriber_id = match insert_subscriber(transaction, new_subscriber)
+        .await
+        .context("Failed to insert new subscriber in the database.")?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            let subscriber = get_subscriber_by_email(transaction, &new_subscriber.email)
+                .await
+                .context("Failed to look up an existing subscriber.")?
+                .context("The subscriber conflicting with a new one does not exist.")?;
+            if subscriber.status == "confirmed" {
+                return Ok(None);
+            }
+            subscriber.id
+        }
+    };
+    record_consent_event(
+        transaction,
+        subscriber_id,
+        SUBSCRIBED,
+        consent_context,
+        &consent_wording.0,
+    )
+    .await
+    .context("Failed to record the consent of a new subscriber.")?;
+    let subscription_token = generate_subscription_token();
+    store_token(transaction, subscriber_id, &subscription_token)
+        .await
+        .context("Failed to store the confirmation token for a new subscriber.")?;
+    Ok(Some(subscription_token))
+}
+
+pub(crate) fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -124,25 +425,26 @@ pub async fn send_confirmation_email(
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token
     );
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
-    let html_body = format!(
-        "Welcome to our newsletter!<br />Click <a href=\"{}\">here</a> to confirm your \
-         subscription.",
-        confirmation_link
-    );
+    let locale = new_subscriber.locale;
+    let args = [("confirmation_link", confirmation_link.as_str().into())];
+    let plain_body = locale.t_args("confirmation-email-text", &args);
+    let html_body = locale.t_args("confirmation-email-html", &args);
     email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
+        .send_email(
+            &new_subscriber.email,
+            &locale.t("confirmation-email-subject"),
+            &html_body,
+            &plain_body,
+        )
         .await
 }
 
+/// Returns `None`, and inserts nothing, if the address is taken already.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(new_subscriber, transaction)
@@ -150,22 +452,38 @@ pub async fn send_confirmation_email(
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
-        r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')"#,
-        subscriber_id,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status, locale)
+        VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
+        ON CONFLICT DO NOTHING
+        RETURNING id"#,
+        Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        new_subscriber.locale.as_str()
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+}
+
+struct ExistingSubscriber {
+    id: Uuid,
+    status: String,
+}
+
+async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        ExistingSubscriber,
+        "SELECT id, status FROM subscriptions WHERE lower(email) = lower($1)",
+        email.as_ref()
     )
-    .execute(transaction.as_mut())
+    .fetch_optional(transaction.as_mut())
     .await
-    .map_err(|e| e)?;
-    // Using the `?` operator to return early
-    // if the function failed, returning a sqlx::Error
-    Ok(subscriber_id)
 }
 
 #[tracing::instrument(
@@ -185,14 +503,22 @@ pub async fn store_token(
     )
     .execute(transaction.as_mut()) // Use `transaction.as_mut()` to access the underlying connection
     .await
-    .map_err(|e| StoreTokenError(e))?;
+    .map_err(StoreTokenError)?;
     Ok(())
 }
 
-#[derive(Debug)]
 pub struct StoreTokenError(sqlx::Error);
 
 impl std::fmt::Display for StoreTokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "A database error was encountered while trying to store a subscription token."
+        )
+    }
+}
+
+impl std::fmt::Debug for StoreTokenError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
     }
@@ -0,0 +1,10 @@
+use crate::signup_protection::SignupProtection;
+use actix_web::{
+    web,
+    HttpResponse,
+};
+
+/// Hand out a proof-of-work challenge for the signup form to solve.
+pub async fn signup_challenge(protection: web::Data<SignupProtection>) -> HttpResponse {
+    HttpResponse::Ok().json(protection.issue_challenge())
+}
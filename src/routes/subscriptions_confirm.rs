@@ -1,8 +1,20 @@
+use crate::consent::{
+    get_signup_wording,
+    record_consent_event,
+    ConsentContext,
+    ConsentWording,
+    CONFIRMED,
+};
 use actix_web::{
     web,
+    HttpRequest,
     HttpResponse,
 };
-use sqlx::PgPool;
+use sqlx::{
+    PgPool,
+    Postgres,
+    Transaction,
+};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -10,8 +22,16 @@ pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
-pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(request, parameters, pool, consent_wording)
+)]
+pub async fn confirm(
+    request: HttpRequest,
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    consent_wording: web::Data<ConsentWording>,
+) -> HttpResponse {
     let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
         Ok(id) => id,
         Err(_) => return HttpResponse::InternalServerError().finish(),
@@ -19,7 +39,22 @@ pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>
     match id {
         None => HttpResponse::Unauthorized().finish(),
         Some(subscriber_id) => {
-            if confirm_subscriber(&pool, subscriber_id).await.is_err() {
+            let consent_context = ConsentContext::from_request(&request, None);
+            let Ok(mut transaction) = pool.begin().await else {
+                return HttpResponse::InternalServerError().finish();
+            };
+            if confirm_subscriber_and_record_consent(
+                &mut transaction,
+                subscriber_id,
+                &consent_context,
+                &consent_wording.0,
+            )
+            .await
+            .is_err()
+            {
+                return HttpResponse::InternalServerError().finish();
+            }
+            if transaction.commit().await.is_err() {
                 return HttpResponse::InternalServerError().finish();
             }
             HttpResponse::Ok().finish()
@@ -27,19 +62,51 @@ pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>
     }
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+/// In the transaction of the caller, which commits it.
+pub async fn confirm_subscriber_and_record_consent(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    consent_context: &ConsentContext,
+    default_wording: &str,
+) -> Result<(), sqlx::Error> {
+    // Following the link again does not count as a new confirmation.
+    if confirm_subscriber(transaction, subscriber_id).await? {
+        let wording = get_signup_wording(transaction, subscriber_id)
+            .await?
+            .unwrap_or_else(|| default_wording.to_owned());
+        record_consent_event(
+            transaction,
+            subscriber_id,
+            CONFIRMED,
+            consent_context,
+            &wording,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Returns `false` if the subscriber had already been confirmed.
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, transaction)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_updated = sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1 AND status != 'confirmed'"#,
         subscriber_id,
     )
-    .execute(pool)
+    .execute(transaction.as_mut())
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
-    })?;
-    Ok(())
+    })?
+    .rows_affected();
+    Ok(n_updated > 0)
 }
 
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
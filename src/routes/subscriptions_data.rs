@@ -0,0 +1,208 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::i18n::Locale;
+use crate::routes::generate_subscription_token;
+use crate::startup::ApplicationBaseUrl;
+use crate::subscriber_data::{
+    get_subscriber_id_from_data_request_token,
+    store_data_request_token,
+};
+use crate::utils::{
+    e400,
+    e500,
+};
+use actix_web::http::header::ContentType;
+use actix_web::{
+    web,
+    HttpResponse,
+};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestFormData {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestToken {
+    data_request_token: String,
+}
+
+/// Emails a link giving access to the subscriber's data, so that only the
+/// owner of the address can see or erase it.
+#[tracing::instrument(
+    name = "Request access to subscriber data",
+    skip(form, pool, email_client, base_url, locale)
+)]
+pub async fn request_subscriber_data(
+    form: web::Form<DataRequestFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = SubscriberEmail::parse(form.0.email).map_err(e400)?;
+    let subscriber = sqlx::query!(
+        r#"SELECT id, locale FROM subscriptions WHERE lower(email) = lower($1)"#,
+        email.as_ref()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to look up a subscriber by email.")
+    .map_err(e500)?;
+    // The response is the same whether we know the address or not: it must
+    // not reveal who is subscribed.
+    if let Some(subscriber) = subscriber {
+        let data_request_token = generate_subscription_token();
+        let stored = store_data_request_token(&pool, subscriber.id, &data_request_token)
+            .await
+            .context("Failed to store a data request token.")
+            .map_err(e500)?;
+        if !stored {
+            tracing::info!("A data request link was sent recently, not sending another.");
+            return Ok(data_request_sent(locale));
+        }
+        // The email is written in the language the subscriber signed up in,
+        // not the one of whoever asked for it.
+        let subscriber_locale = Locale::parse(&subscriber.locale).unwrap_or_default();
+        send_data_request_email(
+            &email_client,
+            &email,
+            subscriber_locale,
+            &base_url.0,
+            &data_request_token,
+        )
+        .await
+        .context("Failed to send a data request email.")
+        .map_err(e500)?;
+    }
+    Ok(data_request_sent(locale))
+}
+
+fn data_request_sent(locale: Locale) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(locale.t("data-request-sent"))
+}
+
+#[tracing::instrument(
+    name = "Send a data request email",
+    skip(email_client, email, base_url, data_request_token)
+)]
+async fn send_data_request_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    locale: Locale,
+    base_url: &str,
+    data_request_token: &str,
+) -> Result<(), anyhow::Error> {
+    let export_link = format!(
+        "{}/subscriptions/data?data_request_token={}",
+        base_url, data_request_token
+    );
+    let erasure_link = format!(
+        "{}/subscriptions/erase?data_request_token={}",
+        base_url, data_request_token
+    );
+    let args = [
+        ("export_link", export_link.as_str().into()),
+        ("erasure_link", erasure_link.as_str().into()),
+    ];
+    let plain_body = locale.t_args("data-request-email-text", &args);
+    let html_body = locale.t_args("data-request-email-html", &args);
+    email_client
+        .send_email(
+            email,
+            &locale.t("data-request-email-subject"),
+            &html_body,
+            &plain_body,
+        )
+        .await
+}
+
+#[tracing::instrument(name = "Download subscriber data", skip(parameters, pool))]
+pub async fn download_subscriber_data(
+    parameters: web::Query<DataRequestToken>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(subscriber_id) = subscriber_id(&pool, &parameters.data_request_token).await? else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+    match crate::subscriber_data::export_subscriber_data(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(export) => Ok(HttpResponse::Ok().json(export)),
+        None => Ok(HttpResponse::Unauthorized().finish()),
+    }
+}
+
+pub async fn erase_subscriber_data_form(
+    parameters: web::Query<DataRequestToken>,
+    locale: Locale,
+) -> HttpResponse {
+    let data_request_token = htmlescape::encode_attribute(&parameters.data_request_token);
+    let title = locale.t("erasure-title");
+    let explanation = locale.t("erasure-explanation");
+    let submit = locale.t("erasure-submit");
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{locale}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <p>{explanation}</p>
+    <form action="/subscriptions/erase" method="post">
+        <input hidden type="text" name="data_request_token" value="{data_request_token}">
+        <button type="submit">{submit}</button>
+    </form>
+</body>
+</html>"#,
+        ))
+}
+
+#[tracing::instrument(name = "Erase subscriber data on request", skip(form, pool, locale))]
+pub async fn erase_subscriber_data(
+    form: web::Form<DataRequestToken>,
+    pool: web::Data<PgPool>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(subscriber_id) = subscriber_id(&pool, &form.data_request_token).await? else {
+        return Ok(HttpResponse::Unauthorized().finish());
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    if !crate::subscriber_data::erase_subscriber_data(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(locale.t("erasure-done")))
+}
+
+async fn subscriber_id(
+    pool: &PgPool,
+    data_request_token: &str,
+) -> Result<Option<Uuid>, actix_web::Error> {
+    get_subscriber_id_from_data_request_token(pool, data_request_token)
+        .await
+        .context("Failed to validate a data request token.")
+        .map_err(e500)
+}
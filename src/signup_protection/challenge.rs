@@ -0,0 +1,219 @@
+use crate::utils::ErrorCode;
+use anyhow::Context;
+use chrono::Utc;
+use hmac::{
+    Hmac,
+    Mac,
+};
+use rand::RngCore;
+use secrecy::{
+    ExposeSecret,
+    Secret,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use sqlx::PgExecutor;
+use std::time::Duration;
+
+/// A self-hosted proof-of-work challenge.
+///
+/// Tokens are stateless - `<issued at>.<nonce>.<hmac>` - so any replica can
+/// verify them. Solving one requires finding a `solution` such that
+/// `sha256(token + solution)` starts with `difficulty` zero bits. Solved
+/// tokens are recorded in the database until they expire, see
+/// [`mark_as_used`], so that they cannot be replayed against any replica.
+pub struct ProofOfWork {
+    secret: Secret<String>,
+    difficulty: u8,
+    ttl: Duration,
+}
+
+#[derive(serde::Serialize)]
+pub struct Challenge {
+    pub challenge: String,
+    pub difficulty: u8,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChallengeError {
+    #[error("The challenge is missing.")]
+    Missing,
+    #[error("The challenge is malformed or was not issued by us.")]
+    Invalid,
+    #[error("The challenge has expired.")]
+    Expired,
+    #[error("The challenge solution is incorrect.")]
+    WrongSolution,
+    #[error("The challenge has already been used.")]
+    AlreadyUsed,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// Also picks the `signup-challenge-*` message shown on the form.
+impl ErrorCode for ChallengeError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Missing => "missing",
+            Self::Invalid => "invalid",
+            Self::Expired => "expired",
+            Self::WrongSolution => "wrong_solution",
+            Self::AlreadyUsed => "already_used",
+            Self::UnexpectedError(_) => "unexpected_error",
+        }
+    }
+}
+
+impl ProofOfWork {
+    pub fn new(secret: Secret<String>, difficulty: u8, ttl: Duration) -> Self {
+        Self {
+            secret,
+            difficulty,
+            ttl,
+        }
+    }
+
+    pub fn issue(&self) -> Challenge {
+        let mut nonce = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let payload = format!("{}.{}", Utc::now().timestamp(), hex::encode(nonce));
+        let signature = hex::encode(self.mac(&payload).finalize().into_bytes());
+        Challenge {
+            challenge: format!("{payload}.{signature}"),
+            difficulty: self.difficulty,
+        }
+    }
+
+    /// Returns when the challenge expires. Whether it was already used is
+    /// left to [`mark_as_used`].
+    pub fn verify(&self, challenge: &str, solution: &str) -> Result<i64, ChallengeError> {
+        let (payload, signature) = challenge.rsplit_once('.').ok_or(ChallengeError::Invalid)?;
+        let signature = hex::decode(signature).map_err(|_| ChallengeError::Invalid)?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| ChallengeError::Invalid)?;
+
+        let issued_at: i64 = payload
+            .split_once('.')
+            .and_then(|(issued_at, _)| issued_at.parse().ok())
+            .ok_or(ChallengeError::Invalid)?;
+        let expires_at = issued_at + self.ttl.as_secs() as i64;
+        let now = Utc::now().timestamp();
+        if now > expires_at {
+            return Err(ChallengeError::Expired);
+        }
+
+        let hash = Sha256::digest(format!("{challenge}{solution}").as_bytes());
+        if leading_zero_bits(&hash) < u32::from(self.difficulty) {
+            return Err(ChallengeError::WrongSolution);
+        }
+
+        Ok(expires_at)
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// Fails if the challenge was already used. Expired challenges are forgotten
+/// along the way: they are rejected before getting here.
+#[tracing::instrument(name = "Mark a challenge as used", skip(executor, challenge))]
+pub async fn mark_as_used(
+    executor: impl PgExecutor<'_>,
+    challenge: &str,
+    expires_at: i64,
+) -> Result<(), ChallengeError> {
+    let n_inserted = sqlx::query!(
+        r#"
+        WITH expired AS (
+            DELETE FROM used_challenges WHERE expires_at < now()
+        )
+        INSERT INTO used_challenges (challenge, expires_at)
+        VALUES ($1, to_timestamp($2))
+        ON CONFLICT DO NOTHING
+        "#,
+        challenge,
+        expires_at as f64
+    )
+    .execute(executor)
+    .await
+    .context("Failed to record a used challenge.")?
+    .rows_affected();
+    if n_inserted == 0 {
+        return Err(ChallengeError::AlreadyUsed);
+    }
+    Ok(())
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut n_zeros = 0;
+    for byte in hash {
+        n_zeros += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    n_zeros
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        leading_zero_bits,
+        ChallengeError,
+        ProofOfWork,
+    };
+    use secrecy::Secret;
+    use sha2::{
+        Digest,
+        Sha256,
+    };
+    use std::time::Duration;
+
+    fn proof_of_work() -> ProofOfWork {
+        ProofOfWork::new(Secret::new("a-secret".into()), 8, Duration::from_secs(300))
+    }
+
+    fn solve(challenge: &str, difficulty: u8) -> String {
+        (0u64..)
+            .map(|n| n.to_string())
+            .find(|n| {
+                let hash = Sha256::digest(format!("{challenge}{n}").as_bytes());
+                leading_zero_bits(&hash) >= u32::from(difficulty)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn leading_zero_bits_spans_multiple_bytes() {
+        assert_eq!(leading_zero_bits(&[0x00, 0x0f, 0xff]), 12);
+        assert_eq!(leading_zero_bits(&[0x80]), 0);
+    }
+
+    #[test]
+    fn a_solved_challenge_is_accepted() {
+        let pow = proof_of_work();
+        let challenge = pow.issue();
+        let solution = solve(&challenge.challenge, challenge.difficulty);
+
+        assert!(pow.verify(&challenge.challenge, &solution).is_ok());
+    }
+
+    #[test]
+    fn a_tampered_challenge_is_rejected() {
+        let pow = proof_of_work();
+        let challenge = pow.issue().challenge.replacen('1', "2", 1);
+        let solution = solve(&challenge, 8);
+
+        assert!(matches!(
+            pow.verify(&challenge, &solution),
+            Err(ChallengeError::Invalid)
+        ));
+    }
+}
@@ -0,0 +1,136 @@
+//! Defences against bots abusing the public signup form: every signup sends
+//! a confirmation email, which makes `/subscriptions` an email-bombing vector.
+mod challenge;
+mod rate_limiter;
+
+pub use challenge::{
+    Challenge,
+    ChallengeError,
+    ProofOfWork,
+};
+pub use rate_limiter::FixedWindowRateLimiter;
+
+use challenge::mark_as_used;
+
+use crate::configuration::SignupSettings;
+use crate::deliverability::MxResolver;
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub struct SignupProtection {
+    per_ip: FixedWindowRateLimiter,
+    per_email_domain: FixedWindowRateLimiter,
+    disposable_email_domains: HashSet<String>,
+    proof_of_work: ProofOfWork,
+    challenge_required: bool,
+    mx_resolver: Option<Arc<dyn MxResolver>>,
+    max_name_length: usize,
+    lowercase_email_local_parts: bool,
+}
+
+impl SignupProtection {
+    /// Domains are only checked for mail servers when given an `mx_resolver`.
+    pub fn new(
+        settings: &SignupSettings,
+        hmac_secret: Secret<String>,
+        mx_resolver: Option<Arc<dyn MxResolver>>,
+    ) -> Self {
+        let window = Duration::from_secs(settings.rate_limit_window_seconds);
+        Self {
+            per_ip: FixedWindowRateLimiter::new(settings.max_requests_per_ip, window),
+            per_email_domain: FixedWindowRateLimiter::new(
+                settings.max_requests_per_email_domain,
+                window,
+            ),
+            disposable_email_domains: settings
+                .disposable_email_domains
+                .iter()
+                .map(|d| d.to_lowercase())
+                .collect(),
+            proof_of_work: ProofOfWork::new(
+                hmac_secret,
+                settings.proof_of_work_difficulty.unwrap_or(0),
+                Duration::from_secs(settings.challenge_ttl_seconds),
+            ),
+            challenge_required: settings.proof_of_work_difficulty.is_some(),
+            mx_resolver,
+            max_name_length: settings.max_name_length,
+            lowercase_email_local_parts: settings.lowercase_email_local_parts,
+        }
+    }
+
+    pub fn allow_ip(&self, ip: &str) -> bool {
+        self.per_ip.check(ip)
+    }
+
+    pub fn allow_email_domain(&self, domain: &str) -> bool {
+        self.per_email_domain.check(&domain.to_lowercase())
+    }
+
+    /// Subdomains of a blocked domain are blocked as well.
+    pub fn is_disposable(&self, domain: &str) -> bool {
+        let domain = domain.to_lowercase();
+        let mut candidate = domain.as_str();
+        loop {
+            if self.disposable_email_domains.contains(candidate) {
+                return true;
+            }
+            match candidate.split_once('.') {
+                Some((_, parent)) => candidate = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Fails open: a DNS outage should not stop people from signing up.
+    pub async fn has_mail_servers(&self, domain: &str) -> bool {
+        let Some(mx_resolver) = &self.mx_resolver else {
+            return true;
+        };
+        match mx_resolver.has_mx_records(domain).await {
+            Ok(has_mx_records) => has_mx_records,
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    %domain,
+                    "Failed to look up MX records, skipping the check."
+                );
+                true
+            }
+        }
+    }
+
+    /// Names end up in every email we send, we do not let them grow unbounded.
+    pub fn max_name_length(&self) -> usize {
+        self.max_name_length
+    }
+
+    /// Whether new addresses are stored in lowercase, mailbox name included.
+    pub fn lowercase_email_local_parts(&self) -> bool {
+        self.lowercase_email_local_parts
+    }
+
+    pub fn issue_challenge(&self) -> Challenge {
+        self.proof_of_work.issue()
+    }
+
+    /// A no-op unless a proof-of-work difficulty has been configured.
+    pub async fn verify_challenge(
+        &self,
+        pool: &PgPool,
+        challenge: Option<&str>,
+        solution: Option<&str>,
+    ) -> Result<(), ChallengeError> {
+        if !self.challenge_required {
+            return Ok(());
+        }
+        let (Some(challenge), Some(solution)) = (challenge, solution) else {
+            return Err(ChallengeError::Missing);
+        };
+        let expires_at = self.proof_of_work.verify(challenge, solution)?;
+        mark_as_used(pool, challenge, expires_at).await
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+/// Counts requests per key (an IP address, an email domain...) over a fixed
+/// time window, in memory.
+pub struct FixedWindowRateLimiter {
+    max_requests: u32,
+    window: Duration,
+    counters: Mutex<HashMap<String, Counter>>,
+}
+
+struct Counter {
+    window_start: Instant,
+    n_requests: u32,
+}
+
+// Past this many tracked keys we drop the counters whose window has expired.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+impl FixedWindowRateLimiter {
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+            counters: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a request for `key`, returning `false` if it goes over the limit.
+    pub fn check(&self, key: &str) -> bool {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> bool {
+        let mut counters = self.counters.lock().unwrap();
+        if counters.len() > PRUNE_THRESHOLD {
+            counters.retain(|_, c| now.duration_since(c.window_start) < self.window);
+        }
+        let counter = counters.entry(key.to_owned()).or_insert(Counter {
+            window_start: now,
+            n_requests: 0,
+        });
+        if now.duration_since(counter.window_start) >= self.window {
+            counter.window_start = now;
+            counter.n_requests = 0;
+        }
+        counter.n_requests += 1;
+        counter.n_requests <= self.max_requests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FixedWindowRateLimiter;
+    use std::time::{
+        Duration,
+        Instant,
+    };
+
+    #[test]
+    fn requests_over_the_limit_are_rejected() {
+        let limiter = FixedWindowRateLimiter::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+
+        assert!(limiter.check_at("127.0.0.1", now));
+        assert!(limiter.check_at("127.0.0.1", now));
+        assert!(!limiter.check_at("127.0.0.1", now));
+        // Other keys have their own budget
+        assert!(limiter.check_at("10.0.0.1", now));
+    }
+
+    #[test]
+    fn the_budget_is_restored_when_the_window_expires() {
+        let limiter = FixedWindowRateLimiter::new(1, Duration::from_secs(60));
+        let now = Instant::now();
+
+        assert!(limiter.check_at("127.0.0.1", now));
+        assert!(!limiter.check_at("127.0.0.1", now));
+        assert!(limiter.check_at("127.0.0.1", now + Duration::from_secs(60)));
+    }
+}
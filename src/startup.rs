@@ -1,25 +1,72 @@
-use crate::authentication::reject_anonymous_users;
+use crate::attachment::BlobStore;
+use crate::authentication::{
+    reject_anonymous_users,
+    reject_invalid_api_tokens,
+};
 use crate::configuration::DatabaseSettings;
 use crate::configuration::Settings;
+use crate::consent::ConsentWording;
+use crate::deliverability::{
+    DnsMxResolver,
+    MxResolver,
+};
 use crate::email_client::EmailClient;
+use crate::idempotency::{
+    idempotent,
+    IDEMPOTENCY_KEY_HEADER,
+    IDEMPOTENT_REPLAYED_HEADER,
+};
+use crate::migrations::{
+    check_schema,
+    migrate_database,
+};
+use crate::routes::api::v1;
 use crate::routes::{
+    add_suppression,
     admin_dashboard,
+    api_tokens_form,
+    audit_log,
     change_password,
     change_password_form,
     confirm,
+    create_api_token,
+    download_subscriber_data,
+    erase_subscriber,
+    erase_subscriber_data,
+    erase_subscriber_data_form,
+    export_subscriber,
     health_check,
     home,
+    import_suppressions,
     log_out,
     login,
     login_form,
+    newsletter_issue_details,
     publish_newsletter,
     publish_newsletter_form,
+    remove_suppression,
+    request_subscriber_data,
+    resend_success_message,
+    revoke_api_token,
+    signup_challenge,
     subscribe,
+    subscriber_details,
+    subscribers_list,
+    suppressions_form,
+    track_click,
+    track_open,
 };
+use crate::signup_protection::SignupProtection;
+use crate::utils::TrustedProxies;
+use actix_cors::Cors;
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
 use actix_web::dev::Server;
+use actix_web::http::header::{
+    ACCEPT,
+    CONTENT_TYPE,
+};
 use actix_web::web::Data;
 use actix_web::{
     web,
@@ -29,6 +76,7 @@ use actix_web::{
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_lab::middleware::from_fn;
+use anyhow::Context;
 use secrecy::{
     ExposeSecret,
     Secret,
@@ -36,6 +84,8 @@ use secrecy::{
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::TcpListener;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 use tracing_actix_web::TracingLogger;
 
 pub struct Application {
@@ -45,8 +95,29 @@ pub struct Application {
 
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
+        let mx_resolver: Option<Arc<dyn MxResolver>> = if configuration.signup.check_mx_records {
+            Some(Arc::new(DnsMxResolver::from_system_conf()?))
+        } else {
+            None
+        };
+        Self::build_with_mx_resolver(configuration, mx_resolver).await
+    }
+
+    /// Lets tests stub out DNS lookups.
+    pub async fn build_with_mx_resolver(
+        configuration: Settings,
+        mx_resolver: Option<Arc<dyn MxResolver>>,
+    ) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
-        let email_client = configuration.email_client.client();
+        if configuration.application.migrate_on_startup {
+            migrate_database(&connection_pool)
+                .await
+                .context("Failed to migrate the database.")?;
+        }
+        check_schema(&connection_pool)
+            .await
+            .context("Refusing to serve with a database schema this binary does not expect.")?;
+        let email_client = configuration.email_client.clone().client();
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
@@ -57,9 +128,8 @@ impl Application {
             listener,
             connection_pool,
             email_client,
-            configuration.application.base_url,
-            HmacSecret(configuration.application.hmac_secret.clone()),
-            configuration.redis_uri,
+            configuration,
+            mx_resolver,
         )
         .await?;
 
@@ -73,6 +143,21 @@ impl Application {
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
         self.server.await
     }
+
+    /// Stops accepting connections on shutdown, and lets the open ones
+    /// complete within the configured timeout.
+    pub async fn run_until_shutdown(
+        self,
+        shutdown: CancellationToken,
+    ) -> Result<(), std::io::Error> {
+        let handle = self.server.handle();
+        tokio::spawn(async move {
+            shutdown.cancelled().await;
+            tracing::info!("Draining open connections.");
+            handle.stop(true).await;
+        });
+        self.server.await
+    }
 }
 
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
@@ -81,21 +166,47 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 
 pub struct ApplicationBaseUrl(pub String);
 
+/// The default limits of `web::Form` and `web::Json`. Idempotent routes read
+/// requests up to them before their handlers do.
+const FORM_PAYLOAD_LIMIT: usize = 16 * 1024;
+const JSON_PAYLOAD_LIMIT: usize = 2 * 1024 * 1024;
+
 async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
-    base_url: String,
-    hmac_secret: HmacSecret,
-    redis_uri: Secret<String>,
+    configuration: Settings,
+    mx_resolver: Option<Arc<dyn MxResolver>>,
 ) -> Result<Server, anyhow::Error> {
     let db_pool = Data::new(db_pool);
     let email_client = Data::new(email_client);
-    let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let base_url = Data::new(ApplicationBaseUrl(configuration.application.base_url));
+    let hmac_secret = HmacSecret(configuration.application.hmac_secret);
+    let cors_allowed_origins = configuration.application.cors_allowed_origins;
+    let trusted_proxies = Data::new(TrustedProxies(configuration.application.trusted_proxies));
+    let shutdown_timeout = configuration.application.shutdown_timeout_seconds;
+    let signup_protection = Data::new(SignupProtection::new(
+        &configuration.signup,
+        hmac_secret.0.clone(),
+        mx_resolver,
+    ));
+    let consent_wording = Data::new(ConsentWording(configuration.signup.consent_wording));
+    let blob_store: Data<dyn BlobStore> =
+        Data::from(Arc::new(configuration.attachments.store()) as Arc<dyn BlobStore>);
+    let attachment_policy = Data::new(configuration.attachments.policy());
+    // The attachments, and some room for the rest of the form.
+    let publish_payload_limit = configuration.attachments.max_total_size_bytes + FORM_PAYLOAD_LIMIT;
+    let idempotency_policy = Data::new(configuration.idempotency.policy());
+    let issue_email_settings = Data::new(
+        configuration
+            .email_client
+            .issue_email_settings()
+            .context("Invalid sender identity.")?,
+    );
     let secret_key = Key::from(hmac_secret.0.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
-    let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let redis_store = RedisSessionStore::new(configuration.redis_uri.expose_secret()).await?;
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
@@ -108,23 +219,134 @@ async fn run(
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
             .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
+            .service(
+                web::resource("/subscriptions")
+                    .wrap(signup_cors(&cors_allowed_origins))
+                    .route(
+                        web::post()
+                            .to(subscribe)
+                            .wrap(from_fn(idempotent(FORM_PAYLOAD_LIMIT))),
+                    ),
+            )
+            .route("/subscriptions/challenge", web::get().to(signup_challenge))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/data_requests",
+                web::post().to(request_subscriber_data),
+            )
+            .route(
+                "/subscriptions/data",
+                web::get().to(download_subscriber_data),
+            )
+            .route(
+                "/subscriptions/erase",
+                web::get().to(erase_subscriber_data_form),
+            )
+            .route(
+                "/subscriptions/erase",
+                web::post().to(erase_subscriber_data),
+            )
+            .route(
+                "/newsletters/opened/{tracking_id}",
+                web::get().to(track_open),
+            )
+            .route(
+                "/newsletters/clicked/{tracking_id}",
+                web::get().to(track_click),
+            )
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/newsletters", web::get().to(publish_newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter))
+                    .route(
+                        "/newsletters",
+                        web::post()
+                            .to(publish_newsletter)
+                            .wrap(from_fn(idempotent(publish_payload_limit)))
+                            .wrap(from_fn(resend_success_message)),
+                    )
+                    .route(
+                        "/newsletters/{newsletter_issue_id}",
+                        web::get().to(newsletter_issue_details),
+                    )
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
-                    .route("/logout", web::post().to(log_out)),
+                    .route("/logout", web::post().to(log_out))
+                    .route("/subscribers", web::get().to(subscribers_list))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(subscriber_details),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/export",
+                        web::get().to(export_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/erase",
+                        web::post().to(erase_subscriber),
+                    )
+                    .route("/suppressions", web::get().to(suppressions_form))
+                    .route("/suppressions", web::post().to(add_suppression))
+                    .route("/suppressions/import", web::post().to(import_suppressions))
+                    .route(
+                        "/suppressions/{email_hash}/remove",
+                        web::post().to(remove_suppression),
+                    )
+                    .route("/api_tokens", web::get().to(api_tokens_form))
+                    .route("/api_tokens", web::post().to(create_api_token))
+                    .route(
+                        "/api_tokens/{api_token_id}/revoke",
+                        web::post().to(revoke_api_token),
+                    )
+                    .route("/audit_log", web::get().to(audit_log)),
+            )
+            .service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(reject_invalid_api_tokens))
+                    .route("/subscribers", web::get().to(v1::list_subscribers))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(v1::get_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/consent_events",
+                        web::get().to(v1::list_subscriber_consent_events),
+                    )
+                    .route(
+                        "/newsletter_issues",
+                        web::get().to(v1::list_newsletter_issues),
+                    )
+                    .route(
+                        "/newsletter_issues",
+                        web::post()
+                            .to(v1::create_newsletter_issue)
+                            .wrap(from_fn(idempotent(JSON_PAYLOAD_LIMIT))),
+                    )
+                    .route(
+                        "/newsletter_issues/{newsletter_issue_id}",
+                        web::get().to(v1::get_newsletter_issue),
+                    )
+                    .route(
+                        "/newsletter_issues/{newsletter_issue_id}/delivery_status",
+                        web::get().to(v1::get_delivery_status),
+                    ),
             )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(signup_protection.clone())
+            .app_data(consent_wording.clone())
+            .app_data(blob_store.clone())
+            .app_data(attachment_policy.clone())
+            .app_data(issue_email_settings.clone())
+            .app_data(idempotency_policy.clone())
+            .app_data(trusted_proxies.clone())
             .app_data(Data::new(hmac_secret.0.clone()))
     })
+    // Shutdown is driven by the caller, see `Application::run_until_shutdown`
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout)
     .listen(listener)?
     .run();
     Ok(server)
@@ -132,3 +354,21 @@ async fn run(
 
 #[derive(Clone, Debug)]
 pub struct HmacSecret(pub Secret<String>);
+
+/// CORS policy for the public signup endpoint, so that it can be called from
+/// the signup widget embedded in other sites. `*` allows any origin.
+fn signup_cors(allowed_origins: &[String]) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods(vec!["POST"])
+        .allowed_headers(vec![ACCEPT, CONTENT_TYPE])
+        .allowed_header(IDEMPOTENCY_KEY_HEADER)
+        .expose_headers(vec![IDEMPOTENT_REPLAYED_HEADER])
+        .max_age(3600);
+    allowed_origins.iter().fold(cors, |cors, origin| {
+        if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        }
+    })
+}
@@ -0,0 +1,564 @@
+//! A/B tests of the subject line of a newsletter issue.
+//!
+//! The issue first goes out to a random sample of the confirmed subscribers,
+//! split evenly between the candidate subject lines. Once the wait is over,
+//! the subject line with the best open or click rate wins and the rest of the
+//! list gets the issue with it, see [`decide_due_subject_tests`].
+//!
+//! Opens are tracked with an image and clicks by routing the links of the
+//! email through us, for the sample only.
+use crate::issue_delivery_worker::notify_new_tasks;
+use crate::utils::ErrorCode;
+use anyhow::Context;
+use chrono::{
+    DateTime,
+    Duration,
+    Utc,
+};
+use sqlx::{
+    PgExecutor,
+    PgPool,
+    Postgres,
+    Transaction,
+};
+use uuid::Uuid;
+
+pub const DEFAULT_SAMPLE_PERCENT: i32 = 20;
+pub const DEFAULT_WAIT_MINUTES: i64 = 240;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Metric {
+    #[default]
+    Opens,
+    Clicks,
+}
+
+impl Metric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Opens => "opens",
+            Self::Clicks => "clicks",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, SubjectTestError> {
+        match s {
+            "opens" => Ok(Self::Opens),
+            "clicks" => Ok(Self::Clicks),
+            _ => Err(SubjectTestError::UnknownMetric(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubjectTestError {
+    #[error("An A/B test needs at least two different subject lines.")]
+    TooFewSubjectLines,
+    #[error("The test sample must be between 1% and 100% of the subscribers.")]
+    InvalidSamplePercent,
+    #[error("The wait before sending the winner must be a positive number of minutes.")]
+    InvalidWait,
+    #[error("{0} is not a known A/B test metric.")]
+    UnknownMetric(String),
+    #[error("Subject lines cannot be A/B tested on translated issues yet.")]
+    Translated,
+}
+
+/// Also picks the `subject-test-*` message flashed to admins.
+impl ErrorCode for SubjectTestError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::TooFewSubjectLines => "too_few_subject_lines",
+            Self::InvalidSamplePercent => "invalid_sample_percent",
+            Self::InvalidWait => "invalid_wait",
+            Self::UnknownMetric(_) => "unknown_metric",
+            Self::Translated => "translated",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SubjectTest {
+    /// The first one is the title of the issue.
+    subject_lines: Vec<String>,
+    metric: Metric,
+    sample_percent: i32,
+    wait: Duration,
+}
+
+impl SubjectTest {
+    /// Blank and duplicate alternatives are ignored.
+    pub fn new(
+        title: &str,
+        alternatives: impl IntoIterator<Item = String>,
+        metric: Metric,
+        sample_percent: i32,
+        wait_minutes: i64,
+    ) -> Result<Self, SubjectTestError> {
+        let mut subject_lines = vec![title.trim().to_owned()];
+        for subject in alternatives {
+            let subject = subject.trim();
+            if !subject.is_empty() && !subject_lines.iter().any(|s| s == subject) {
+                subject_lines.push(subject.to_owned());
+            }
+        }
+        if subject_lines.len() < 2 {
+            return Err(SubjectTestError::TooFewSubjectLines);
+        }
+        if !(1..=100).contains(&sample_percent) {
+            return Err(SubjectTestError::InvalidSamplePercent);
+        }
+        if wait_minutes < 1 {
+            return Err(SubjectTestError::InvalidWait);
+        }
+        Ok(Self {
+            subject_lines,
+            metric,
+            sample_percent,
+            wait: Duration::minutes(wait_minutes),
+        })
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn insert_subject_test(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    subject_test: &SubjectTest,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subject_tests (newsletter_issue_id, metric, sample_percent, decide_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        newsletter_issue_id,
+        subject_test.metric.as_str(),
+        subject_test.sample_percent,
+        Utc::now() + subject_test.wait
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    for (variant_index, subject) in subject_test.subject_lines.iter().enumerate() {
+        sqlx::query!(
+            r#"
+            INSERT INTO subject_test_variants (newsletter_issue_id, variant_index, subject)
+            VALUES ($1, $2, $3)
+            "#,
+            newsletter_issue_id,
+            variant_index as i32,
+            subject
+        )
+        .execute(transaction.as_mut())
+        .await?;
+    }
+    Ok(())
+}
+
+/// The subject line to send, for deliveries to the sample (`variant_index` is
+/// set) or to the rest of the list once there is a winner. `None` if the issue
+/// is not being tested: its title is the subject.
+pub async fn subject_line(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+    variant_index: Option<i32>,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT v.subject
+        FROM subject_test_variants v
+        JOIN subject_tests t USING (newsletter_issue_id)
+        WHERE
+            v.newsletter_issue_id = $1 AND
+            v.variant_index = COALESCE($2, t.winner)
+        "#,
+        newsletter_issue_id,
+        variant_index
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+pub struct SubjectTestSummary {
+    pub metric: String,
+    pub sample_percent: i32,
+    pub decide_at: DateTime<Utc>,
+    pub winner: Option<i32>,
+    pub decided_at: Option<DateTime<Utc>>,
+}
+
+pub async fn get_subject_test(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<SubjectTestSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        SubjectTestSummary,
+        r#"
+        SELECT metric, sample_percent, decide_at, winner, decided_at
+        FROM subject_tests
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+pub struct SubjectLineResult {
+    pub variant_index: i32,
+    pub subject: String,
+    pub n_sent: i64,
+    /// A click counts as an open: images may be blocked.
+    pub n_opened: i64,
+    pub n_clicked: i64,
+}
+
+impl SubjectLineResult {
+    pub fn open_rate(&self) -> f64 {
+        rate(self.n_opened, self.n_sent)
+    }
+
+    pub fn click_rate(&self) -> f64 {
+        rate(self.n_clicked, self.n_sent)
+    }
+
+    fn rate_by(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::Opens => self.open_rate(),
+            Metric::Clicks => self.click_rate(),
+        }
+    }
+}
+
+fn rate(n: i64, n_sent: i64) -> f64 {
+    if n_sent == 0 {
+        0.0
+    } else {
+        n as f64 / n_sent as f64
+    }
+}
+
+pub async fn subject_test_results(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<SubjectLineResult>, sqlx::Error> {
+    sqlx::query_as!(
+        SubjectLineResult,
+        r#"
+        SELECT
+            v.variant_index,
+            v.subject,
+            COUNT(d.tracking_id) AS "n_sent!",
+            COUNT(*) FILTER (
+                WHERE d.opened_at IS NOT NULL OR d.clicked_at IS NOT NULL
+            ) AS "n_opened!",
+            COUNT(d.clicked_at) AS "n_clicked!"
+        FROM subject_test_variants v
+        LEFT JOIN subject_test_deliveries d
+            ON d.newsletter_issue_id = v.newsletter_issue_id AND
+               d.variant_index = v.variant_index
+        WHERE v.newsletter_issue_id = $1
+        GROUP BY v.variant_index, v.subject
+        ORDER BY v.variant_index
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Ties go to the subject line listed first, the title of the issue when
+/// nothing was opened or clicked at all.
+fn pick_winner(results: &[SubjectLineResult], metric: Metric) -> i32 {
+    let mut winner: Option<&SubjectLineResult> = None;
+    for result in results {
+        if winner.is_none_or(|w| result.rate_by(metric) > w.rate_by(metric)) {
+            winner = Some(result);
+        }
+    }
+    winner.map_or(0, |w| w.variant_index)
+}
+
+/// Picks the winner of every test whose wait is over and releases the
+/// deliveries that were held back for it.
+#[tracing::instrument(skip_all, err)]
+pub async fn decide_due_subject_tests(pool: &PgPool) -> Result<(), anyhow::Error> {
+    loop {
+        let mut transaction = pool.begin().await?;
+        let test = sqlx::query!(
+            r#"
+            SELECT newsletter_issue_id, metric
+            FROM subject_tests
+            WHERE winner IS NULL AND decide_at <= now()
+            FOR UPDATE
+            SKIP LOCKED
+            LIMIT 1
+            "#,
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let Some(test) = test else {
+            return Ok(());
+        };
+        let metric = Metric::parse(&test.metric)?;
+        let results = subject_test_results(&mut *transaction, test.newsletter_issue_id).await?;
+        let winner = pick_winner(&results, metric);
+        tracing::info!(
+            newsletter_issue_id = %test.newsletter_issue_id,
+            winner,
+            "Picked the winner of a subject line test."
+        );
+        sqlx::query!(
+            r#"
+            UPDATE subject_tests
+            SET winner = $2, decided_at = now()
+            WHERE newsletter_issue_id = $1
+            "#,
+            test.newsletter_issue_id,
+            winner
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            UPDATE issue_delivery_queue
+            SET held = false
+            WHERE newsletter_issue_id = $1 AND held
+            "#,
+            test.newsletter_issue_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+        notify_new_tasks(&mut transaction).await?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit the winner of a subject line test.")?;
+    }
+}
+
+/// Adds open and click tracking to the HTML body of an email sent to the
+/// sample of a test.
+pub fn track_email(html: &str, base_url: &str, tracking_id: Uuid) -> String {
+    let mut tracked = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some((start, end)) = next_link(rest) {
+        let link = htmlescape::decode_html(&rest[start..end]).unwrap_or_default();
+        tracked.push_str(&rest[..start]);
+        tracked.push_str(&format!(
+            "{}/newsletters/clicked/{}?url={}",
+            base_url,
+            tracking_id,
+            urlencoding::encode(&link)
+        ));
+        rest = &rest[end..];
+    }
+    tracked.push_str(rest);
+    tracked.push_str(&format!(
+        r#"<img src="{}/newsletters/opened/{}" width="1" height="1" alt="">"#,
+        base_url, tracking_id
+    ));
+    tracked
+}
+
+/// The byte range of the next `http(s)` link in `html`.
+fn next_link(html: &str) -> Option<(usize, usize)> {
+    let mut offset = 0;
+    loop {
+        let start = offset + html[offset..].find(r#"href=""#)? + r#"href=""#.len();
+        let end = start + html[start..].find('"')?;
+        if html[start..end].starts_with("http://") || html[start..end].starts_with("https://") {
+            return Some((start, end));
+        }
+        offset = end;
+    }
+}
+
+fn links(html: &str) -> Vec<String> {
+    let mut links = vec![];
+    let mut rest = html;
+    while let Some((start, end)) = next_link(rest) {
+        links.extend(htmlescape::decode_html(&rest[start..end]).ok());
+        rest = &rest[end..];
+    }
+    links
+}
+
+#[tracing::instrument(skip(transaction))]
+pub async fn record_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    tracking_id: Uuid,
+    newsletter_issue_id: Uuid,
+    variant_index: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subject_test_deliveries (
+            tracking_id, newsletter_issue_id, variant_index, sent_at
+        )
+        VALUES ($1, $2, $3, now())
+        "#,
+        tracking_id,
+        newsletter_issue_id,
+        variant_index
+    )
+    .execute(transaction.as_mut())
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn record_open(pool: &PgPool, tracking_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subject_test_deliveries
+        SET opened_at = COALESCE(opened_at, now())
+        WHERE tracking_id = $1
+        "#,
+        tracking_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Returns `false` if `url` is not one of the links of the issue the email
+/// was for: we are not an open redirect.
+#[tracing::instrument(skip(pool))]
+pub async fn record_click(
+    pool: &PgPool,
+    tracking_id: Uuid,
+    url: &str,
+) -> Result<bool, sqlx::Error> {
+    let html_contents = sqlx::query_scalar!(
+        r#"
+        SELECT v.html_content
+        FROM subject_test_deliveries d
+        JOIN newsletter_issue_variants v USING (newsletter_issue_id)
+        WHERE d.tracking_id = $1
+        "#,
+        tracking_id
+    )
+    .fetch_all(pool)
+    .await?;
+    if !html_contents
+        .iter()
+        .any(|html| links(html).iter().any(|l| l == url))
+    {
+        return Ok(false);
+    }
+    sqlx::query!(
+        r#"
+        UPDATE subject_test_deliveries
+        SET clicked_at = COALESCE(clicked_at, now())
+        WHERE tracking_id = $1
+        "#,
+        tracking_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        pick_winner,
+        track_email,
+        Metric,
+        SubjectLineResult,
+        SubjectTest,
+        SubjectTestError,
+    };
+    use claim::{
+        assert_err,
+        assert_ok,
+    };
+    use uuid::Uuid;
+
+    fn result(variant_index: i32, n_sent: i64, n_opened: i64, n_clicked: i64) -> SubjectLineResult {
+        SubjectLineResult {
+            variant_index,
+            subject: format!("Subject {variant_index}"),
+            n_sent,
+            n_opened,
+            n_clicked,
+        }
+    }
+
+    #[test]
+    fn the_best_rate_wins() {
+        let results = [result(0, 10, 2, 1), result(1, 10, 5, 0), result(2, 5, 2, 2)];
+        assert_eq!(pick_winner(&results, Metric::Opens), 1);
+        assert_eq!(pick_winner(&results, Metric::Clicks), 2);
+    }
+
+    #[test]
+    fn ties_go_to_the_first_subject_line() {
+        let results = [result(0, 10, 0, 0), result(1, 0, 0, 0), result(2, 10, 0, 0)];
+        assert_eq!(pick_winner(&results, Metric::Opens), 0);
+    }
+
+    #[test]
+    fn a_test_needs_two_different_subject_lines() {
+        let err = SubjectTest::new(
+            "Title",
+            ["  ".into(), "Title".into()],
+            Metric::Opens,
+            20,
+            60,
+        );
+        assert!(matches!(err, Err(SubjectTestError::TooFewSubjectLines)));
+        assert_ok!(SubjectTest::new(
+            "Title",
+            ["Other title".into()],
+            Metric::Opens,
+            20,
+            60
+        ));
+    }
+
+    #[test]
+    fn the_sample_must_be_a_percentage() {
+        for sample_percent in [0, 101, -5] {
+            assert_err!(SubjectTest::new(
+                "Title",
+                ["Other title".into()],
+                Metric::Opens,
+                sample_percent,
+                60
+            ));
+        }
+    }
+
+    #[test]
+    fn the_wait_must_be_positive() {
+        for wait_minutes in [0, -5] {
+            let err = SubjectTest::new(
+                "Title",
+                ["Other title".into()],
+                Metric::Opens,
+                20,
+                wait_minutes,
+            );
+            assert!(matches!(err, Err(SubjectTestError::InvalidWait)));
+        }
+    }
+
+    #[test]
+    fn links_are_routed_through_click_tracking() {
+        let tracking_id = Uuid::nil();
+        let html = r#"<a href="https://example.com/a?b=1&amp;c=2">A</a> <a href="/relative">B</a>"#;
+
+        let tracked = track_email(html, "http://127.0.0.1", tracking_id);
+
+        assert_eq!(
+            tracked,
+            format!(
+                "<a href=\"http://127.0.0.1/newsletters/clicked/{tracking_id}?url=https%3A%2F%2Fexample.\
+                 com%2Fa%3Fb%3D1%26c%3D2\">A</a> <a href=\"/relative\">B</a><img \
+                 src=\"http://127.0.0.1/newsletters/opened/{tracking_id}\" width=\"1\" height=\"1\" \
+                 alt=\"\">"
+            )
+        );
+    }
+}
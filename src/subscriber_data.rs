@@ -0,0 +1,210 @@
+//! Everything we know about a subscriber, for data export and erasure
+//! requests.
+//!
+//! Tables that store subscriber data must be covered by both
+//! [`export_subscriber_data`] and [`erase_subscriber_data`].
+use crate::consent::{
+    list_consent_events,
+    ConsentEvent,
+};
+use crate::suppression::{
+    suppress_email,
+    ERASED,
+};
+use anyhow::Context;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use sqlx::{
+    PgPool,
+    Postgres,
+    Transaction,
+};
+use uuid::Uuid;
+
+/// How long the link emailed to a subscriber gives access to their data.
+const DATA_REQUEST_TOKEN_TTL_HOURS: i32 = 24;
+/// How long a subscriber waits between two links, so that the data request
+/// form cannot be used to flood their inbox.
+const DATA_REQUEST_COOLDOWN_MINUTES: i32 = 15;
+
+#[derive(serde::Serialize)]
+pub struct SubscriberDataExport {
+    pub exported_at: DateTime<Utc>,
+    pub subscriber: SubscriberData,
+    pub consent_events: Vec<ConsentEvent>,
+    /// Newsletter issues that are still waiting to be delivered to them.
+    pub pending_deliveries: Vec<Uuid>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberData {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub locale: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Export subscriber data", skip(pool))]
+pub async fn export_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberDataExport>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberData,
+        r#"
+        SELECT id, email, name, status, locale, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a subscriber.")?;
+    let Some(subscriber) = subscriber else {
+        return Ok(None);
+    };
+    let consent_events = list_consent_events(pool, subscriber_id)
+        .await
+        .context("Failed to retrieve consent events.")?;
+    let pending_deliveries = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM issue_delivery_queue
+        WHERE subscriber_email = $1
+        "#,
+        subscriber.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve pending deliveries.")?
+    .into_iter()
+    .map(|r| r.newsletter_issue_id)
+    .collect();
+    Ok(Some(SubscriberDataExport {
+        exported_at: Utc::now(),
+        subscriber,
+        consent_events,
+        pending_deliveries,
+    }))
+}
+
+/// Deletes every trace of the subscriber, leaving behind only a suppression
+/// entry so that the address is never emailed again.
+/// Returns `false` if there was no subscriber with the given id.
+#[tracing::instrument(name = "Erase subscriber data", skip(transaction))]
+pub async fn erase_subscriber_data(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("Failed to retrieve a subscriber.")?;
+    let Some(subscriber) = subscriber else {
+        return Ok(false);
+    };
+    suppress_email(transaction.as_mut(), &subscriber.email, ERASED)
+        .await
+        .context("Failed to suppress the email address of an erased subscriber.")?;
+    sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE subscriber_email = $1"#,
+        subscriber.email
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("Failed to delete pending deliveries.")?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("Failed to delete subscription tokens.")?;
+    sqlx::query!(
+        r#"DELETE FROM data_request_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("Failed to delete data request tokens.")?;
+    // Consent events are append-only, erasure is the one exception.
+    sqlx::query!("SET LOCAL prod_craft.erasure = 'on'")
+        .execute(transaction.as_mut())
+        .await
+        .context("Failed to allow the erasure of consent events.")?;
+    sqlx::query!(
+        r#"DELETE FROM consent_events WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("Failed to delete consent events.")?;
+    sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(transaction.as_mut())
+        .await
+        .context("Failed to delete a subscriber.")?;
+    Ok(true)
+}
+
+/// Returns `false`, and stores nothing, if the subscriber was already issued
+/// a token less than [`DATA_REQUEST_COOLDOWN_MINUTES`] ago.
+#[tracing::instrument(name = "Store data request token", skip(pool, data_request_token))]
+pub async fn store_data_request_token(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    data_request_token: &str,
+) -> Result<bool, sqlx::Error> {
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO data_request_tokens (data_request_token, subscriber_id, created_at)
+        SELECT $1, $2, now()
+        WHERE NOT EXISTS (
+            SELECT 1
+            FROM data_request_tokens
+            WHERE
+                subscriber_id = $2 AND
+                created_at > now() - make_interval(mins => $3)
+        )
+        "#,
+        data_request_token,
+        subscriber_id,
+        DATA_REQUEST_COOLDOWN_MINUTES
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_inserted == 1)
+}
+
+/// Returns `None` for unknown and expired tokens.
+#[tracing::instrument(
+    name = "Get subscriber_id from data request token",
+    skip(pool, data_request_token)
+)]
+pub async fn get_subscriber_id_from_data_request_token(
+    pool: &PgPool,
+    data_request_token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT subscriber_id
+        FROM data_request_tokens
+        WHERE
+            data_request_token = $1 AND
+            created_at > now() - make_interval(hours => $2)
+        "#,
+        data_request_token,
+        DATA_REQUEST_TOKEN_TTL_HOURS
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|r| r.subscriber_id))
+}
@@ -0,0 +1,152 @@
+//! Addresses we must never email again.
+//!
+//! Only a hash of the normalized address is stored: the list has to outlive
+//! erasure requests, so it cannot hold the addresses themselves. Every send
+//! path must check it, see [`is_suppressed`].
+use chrono::{
+    DateTime,
+    Utc,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use sqlx::{
+    PgExecutor,
+    PgPool,
+};
+
+/// Delivery to the address failed permanently.
+pub const BOUNCED: &str = "bounced";
+/// The recipient flagged one of our emails as spam.
+pub const COMPLAINED: &str = "complained";
+/// The subscriber asked for their data to be erased.
+pub const ERASED: &str = "erased";
+/// Added by an administrator.
+pub const MANUAL: &str = "manual";
+
+pub const REASONS: [&str; 4] = [BOUNCED, COMPLAINED, ERASED, MANUAL];
+
+/// The only definition of the normalization: queries never hash addresses
+/// themselves, they are handed hashes or the output of
+/// [`suppressed_emails`].
+pub fn email_hash(email: &str) -> String {
+    hex::encode(Sha256::digest(email.trim().to_lowercase().as_bytes()))
+}
+
+pub struct Suppression {
+    pub email_hash: String,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Returns `false` if the address was already suppressed.
+#[tracing::instrument(name = "Suppress an email address", skip(executor, email))]
+pub async fn suppress_email(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    reason: &str,
+) -> Result<bool, sqlx::Error> {
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email_hash, reason, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        email_hash(email),
+        reason
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_inserted > 0)
+}
+
+/// Returns `false` if there was no suppression with the given hash.
+#[tracing::instrument(name = "Remove a suppression", skip(executor))]
+pub async fn remove_suppression(
+    executor: impl PgExecutor<'_>,
+    email_hash: &str,
+) -> Result<bool, sqlx::Error> {
+    let n_deleted = sqlx::query!(
+        r#"DELETE FROM suppressions WHERE email_hash = $1"#,
+        email_hash
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_deleted > 0)
+}
+
+#[tracing::instrument(
+    name = "Check if an email address is suppressed",
+    skip(executor, email)
+)]
+pub async fn is_suppressed(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM suppressions WHERE email_hash = $1) as "suppressed!""#,
+        email_hash(email)
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(row.suppressed)
+}
+
+/// The addresses among `emails` that are suppressed.
+#[tracing::instrument(name = "Find suppressed email addresses", skip_all)]
+pub async fn suppressed_emails(
+    executor: impl PgExecutor<'_>,
+    emails: Vec<String>,
+) -> Result<Vec<String>, sqlx::Error> {
+    let hashes: Vec<String> = emails.iter().map(|email| email_hash(email)).collect();
+    let suppressed_hashes = sqlx::query_scalar!(
+        r#"SELECT email_hash FROM suppressions WHERE email_hash = ANY($1)"#,
+        &hashes
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(emails
+        .into_iter()
+        .zip(hashes)
+        .filter(|(_, hash)| suppressed_hashes.contains(hash))
+        .map(|(email, _)| email)
+        .collect())
+}
+
+#[tracing::instrument(name = "List suppressions", skip(pool))]
+pub async fn list_suppressions(pool: &PgPool) -> Result<Vec<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT email_hash, reason, created_at
+        FROM suppressions
+        ORDER BY created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::email_hash;
+
+    #[test]
+    fn email_hash_ignores_case_and_surrounding_whitespace() {
+        assert_eq!(
+            email_hash("ursula@domain.com"),
+            email_hash(" Ursula@DOMAIN.com ")
+        );
+    }
+
+    #[test]
+    fn different_addresses_have_different_hashes() {
+        assert_ne!(
+            email_hash("ursula@domain.com"),
+            email_hash("le.guin@domain.com")
+        );
+    }
+}
@@ -19,7 +19,6 @@ use tracing_subscriber::{
 ///
 /// We are using `impl Subscriber` as return type to avoid having to spell out
 /// the actual type of the returned subscriber, which is indeed quite complex.
-
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
@@ -0,0 +1,401 @@
+//! Keeps the delivery worker under the send rate of the email provider and
+//! under the rates receiving domains tolerate before greylisting us.
+//!
+//! Rates are enforced with token buckets: a global one, and one per recipient
+//! domain. Bursts are capped at one second worth of sends, or a single send
+//! for rates under one per second. A rate of zero pauses sends.
+use serde::de::Error;
+use serde::{
+    Deserialize,
+    Deserializer,
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+/// How often to look again at a domain that is at its concurrency cap: there
+/// is no telling when a send will complete.
+const CONCURRENCY_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Above this many tracked domains, idle ones are forgotten.
+const MAX_IDLE_DOMAINS: usize = 10_000;
+
+/// How often to look again at a paused bucket, or at one that takes longer
+/// than this to refill: the configuration may have changed.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// For rates in the configuration, which cannot be negative.
+pub fn deserialize_rate<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<f64>::deserialize(deserializer)? {
+        Some(rate) if rate.is_nan() || rate < 0.0 => Err(D::Error::custom(format!(
+            "{rate} is not a valid rate, set it to 0 to pause sends"
+        ))),
+        rate => Ok(rate),
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct DomainLimits {
+    /// Unlimited if unset.
+    #[serde(default, deserialize_with = "deserialize_rate")]
+    pub max_sends_per_minute: Option<f64>,
+    /// Unlimited if unset.
+    pub max_concurrent_sends: Option<usize>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Starts full, unless paused.
+    fn new(refill_per_second: f64, now: Instant) -> Self {
+        let capacity = refill_per_second.max(1.0);
+        let paused = refill_per_second <= 0.0;
+        Self {
+            capacity,
+            tokens: if paused { 0.0 } else { capacity },
+            refill_per_second,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * self.refill_per_second).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// How long until a token is available, zero if one is. Capped at
+    /// [`MAX_WAIT`], which is also the answer for a paused bucket.
+    fn wait_time(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            return Duration::ZERO;
+        }
+        Duration::try_from_secs_f64((1.0 - self.tokens) / self.refill_per_second)
+            .map_or(MAX_WAIT, |wait| wait.min(MAX_WAIT))
+    }
+
+    /// Takes a token if one is available, otherwise returns how long until
+    /// one is.
+    fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        match self.wait_time(now) {
+            Duration::ZERO => {
+                self.tokens -= 1.0;
+                Ok(())
+            }
+            wait => Err(wait),
+        }
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.capacity
+    }
+}
+
+#[derive(Debug)]
+struct DomainState {
+    limits: DomainLimits,
+    bucket: Option<TokenBucket>,
+    in_flight: usize,
+}
+
+impl DomainState {
+    fn new(limits: DomainLimits, now: Instant) -> Self {
+        Self {
+            limits,
+            bucket: limits
+                .max_sends_per_minute
+                .map(|rate| TokenBucket::new(rate / 60.0, now)),
+            in_flight: 0,
+        }
+    }
+
+    fn wait_time(&mut self, now: Instant) -> Duration {
+        if self
+            .limits
+            .max_concurrent_sends
+            .is_some_and(|max| self.in_flight >= max)
+        {
+            return CONCURRENCY_RETRY_INTERVAL;
+        }
+        match &mut self.bucket {
+            Some(bucket) => bucket.wait_time(now),
+            None => Duration::ZERO,
+        }
+    }
+
+    fn is_idle(&mut self, now: Instant) -> bool {
+        self.in_flight == 0 && self.bucket.as_mut().is_none_or(|b| b.is_full(now))
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    global: Option<TokenBucket>,
+    domains: HashMap<String, DomainState>,
+}
+
+/// Shared by the tasks of the delivery worker.
+#[derive(Debug)]
+pub struct Throttle {
+    default_limits: DomainLimits,
+    /// By lowercase domain.
+    domain_overrides: HashMap<String, DomainLimits>,
+    state: Mutex<State>,
+}
+
+/// A send in progress to a domain, counted against its concurrency cap until
+/// dropped.
+#[derive(Debug)]
+#[must_use]
+pub struct DomainPermit<'a> {
+    throttle: &'a Throttle,
+    domain: String,
+}
+
+impl Drop for DomainPermit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.throttle.state.lock().unwrap();
+        if let Some(domain) = state.domains.get_mut(&self.domain) {
+            domain.in_flight = domain.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+impl Throttle {
+    pub fn new(
+        max_sends_per_second: Option<f64>,
+        default_limits: DomainLimits,
+        domain_overrides: impl IntoIterator<Item = (String, DomainLimits)>,
+    ) -> Self {
+        let now = Instant::now();
+        Self {
+            default_limits,
+            domain_overrides: domain_overrides
+                .into_iter()
+                .map(|(domain, limits)| (domain.to_lowercase(), limits))
+                .collect(),
+            state: Mutex::new(State {
+                global: max_sends_per_second.map(|rate| TokenBucket::new(rate, now)),
+                domains: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Waits for the global send rate to allow one more send. Returns how
+    /// long it waited.
+    pub async fn until_ready(&self) -> Duration {
+        let mut waited = Duration::ZERO;
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                match &mut state.global {
+                    Some(bucket) => bucket.try_take(Instant::now()),
+                    None => Ok(()),
+                }
+            };
+            match wait {
+                Ok(()) => return waited,
+                Err(wait) => {
+                    tokio::time::sleep(wait).await;
+                    waited += wait;
+                }
+            }
+        }
+    }
+
+    /// The domains that cannot be sent to right now, and how long until the
+    /// first of them can.
+    pub fn throttled_domains(&self) -> (Vec<String>, Option<Duration>) {
+        self.throttled_domains_at(Instant::now())
+    }
+
+    fn throttled_domains_at(&self, now: Instant) -> (Vec<String>, Option<Duration>) {
+        let mut state = self.state.lock().unwrap();
+        if state.domains.len() > MAX_IDLE_DOMAINS {
+            state.domains.retain(|_, domain| !domain.is_idle(now));
+        }
+        let mut throttled = Vec::new();
+        let mut next_ready: Option<Duration> = None;
+        for (name, domain) in state.domains.iter_mut() {
+            let wait = domain.wait_time(now);
+            if wait > Duration::ZERO {
+                throttled.push(name.clone());
+                next_ready = Some(next_ready.map_or(wait, |w| w.min(wait)));
+            }
+        }
+        (throttled, next_ready)
+    }
+
+    /// Takes a send out of the allowance of a domain, unless it is throttled.
+    pub fn try_acquire(&self, domain: &str) -> Result<DomainPermit<'_>, Duration> {
+        self.try_acquire_at(domain, Instant::now())
+    }
+
+    fn try_acquire_at(&self, domain: &str, now: Instant) -> Result<DomainPermit<'_>, Duration> {
+        let domain = domain.to_lowercase();
+        let limits = self
+            .domain_overrides
+            .get(&domain)
+            .copied()
+            .unwrap_or(self.default_limits);
+        let mut state = self.state.lock().unwrap();
+        let state = state
+            .domains
+            .entry(domain.clone())
+            .or_insert_with(|| DomainState::new(limits, now));
+        match state.wait_time(now) {
+            Duration::ZERO => {
+                if let Some(bucket) = &mut state.bucket {
+                    bucket.tokens -= 1.0;
+                }
+                state.in_flight += 1;
+                Ok(DomainPermit {
+                    throttle: self,
+                    domain,
+                })
+            }
+            wait => Err(wait),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DomainLimits,
+        Throttle,
+        TokenBucket,
+        MAX_WAIT,
+    };
+    use claim::{
+        assert_err,
+        assert_ok,
+    };
+    use std::time::{
+        Duration,
+        Instant,
+    };
+
+    #[test]
+    fn a_bucket_allows_a_burst_of_one_second_worth_of_sends() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(3.0, now);
+        for _ in 0..3 {
+            assert_ok!(bucket.try_take(now));
+        }
+        let wait = bucket.try_take(now).unwrap_err();
+        assert!(wait > Duration::from_millis(330) && wait <= Duration::from_millis(334));
+        assert_ok!(bucket.try_take(now + wait));
+    }
+
+    #[test]
+    fn slow_rates_allow_a_single_send_at_a_time() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(0.5, now);
+        assert_ok!(bucket.try_take(now));
+        assert_eq!(bucket.try_take(now), Err(Duration::from_secs(2)));
+        assert_err!(bucket.try_take(now + Duration::from_secs(1)));
+        assert_ok!(bucket.try_take(now + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn a_rate_of_zero_pauses_sends() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(0.0, now);
+        assert_eq!(bucket.try_take(now), Err(MAX_WAIT));
+        assert_eq!(
+            bucket.try_take(now + Duration::from_secs(3600)),
+            Err(MAX_WAIT)
+        );
+
+        let throttle = Throttle::new(
+            Some(10.0),
+            DomainLimits::default(),
+            [(
+                "example.com".to_owned(),
+                DomainLimits {
+                    max_sends_per_minute: Some(0.0),
+                    max_concurrent_sends: None,
+                },
+            )],
+        );
+        assert_eq!(
+            throttle.try_acquire_at("example.com", now).err(),
+            Some(MAX_WAIT)
+        );
+        let (throttled, _) = throttle.throttled_domains_at(now);
+        assert_eq!(throttled, ["example.com"]);
+        // The throttle is still usable.
+        let _permit = throttle.try_acquire_at("example.org", now).unwrap();
+    }
+
+    #[test]
+    fn negative_rates_are_rejected() {
+        let limits: Result<DomainLimits, _> =
+            serde_json::from_value(serde_json::json!({ "max_sends_per_minute": -1.0 }));
+        assert_err!(limits);
+        let limits: DomainLimits =
+            serde_json::from_value(serde_json::json!({ "max_sends_per_minute": 0.0 })).unwrap();
+        assert_eq!(limits.max_sends_per_minute, Some(0.0));
+        let limits: DomainLimits = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(limits, DomainLimits::default());
+    }
+
+    #[test]
+    fn domains_are_limited_independently() {
+        let throttle = Throttle::new(
+            None,
+            DomainLimits {
+                max_sends_per_minute: Some(60.0),
+                max_concurrent_sends: None,
+            },
+            [],
+        );
+        let now = Instant::now();
+        let _permit = throttle.try_acquire_at("example.com", now).unwrap();
+        assert_eq!(
+            throttle.try_acquire_at("EXAMPLE.com", now).err(),
+            Some(Duration::from_secs(1))
+        );
+        let (throttled, next_ready) = throttle.throttled_domains_at(now);
+        assert_eq!(throttled, ["example.com"]);
+        assert_eq!(next_ready, Some(Duration::from_secs(1)));
+        let _other_permit = throttle.try_acquire_at("example.org", now).unwrap();
+    }
+
+    #[test]
+    fn concurrent_sends_are_capped_until_permits_are_dropped() {
+        let throttle = Throttle::new(
+            None,
+            DomainLimits::default(),
+            [(
+                "Example.com".to_owned(),
+                DomainLimits {
+                    max_sends_per_minute: None,
+                    max_concurrent_sends: Some(1),
+                },
+            )],
+        );
+        let now = Instant::now();
+        let permit = throttle.try_acquire_at("example.com", now).unwrap();
+        assert_err!(throttle.try_acquire_at("example.com", now));
+        let _other_permit = throttle.try_acquire_at("example.org", now).unwrap();
+        drop(permit);
+        let _permit = throttle.try_acquire_at("example.com", now).unwrap();
+    }
+}
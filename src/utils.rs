@@ -1,5 +1,11 @@
 use actix_web::http::header::LOCATION;
-use actix_web::HttpResponse;
+use actix_web::{
+    web,
+    HttpRequest,
+    HttpResponse,
+};
+use ipnet::IpNet;
+use std::net::IpAddr;
 
 // Return an opaque 500 while preserving the error root's cause for logging.
 pub fn e500<T>(e: T) -> actix_web::Error
@@ -11,7 +17,7 @@ where
 
 // Return a 400 with the user-representation of the validation error as body.
 // The error root cause is preserved for logging purposes.
-pub fn e400<T: std::fmt::Debug + std::fmt::Display>(e: T) -> actix_web::Error
+pub fn e400<T>(e: T) -> actix_web::Error
 where
     T: std::fmt::Debug + std::fmt::Display + 'static,
 {
@@ -23,3 +29,45 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+/// The reverse proxies allowed to tell us who their clients are.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(pub Vec<IpNet>);
+
+impl TrustedProxies {
+    fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|proxy| proxy.contains(ip))
+    }
+}
+
+// The IP address of the client. `X-Forwarded-For` can be set by anyone, so
+// it is only believed when the request comes from a trusted proxy: each one
+// appends the address it got the request from, we walk the list back until
+// an address we do not trust.
+pub fn client_ip(request: &HttpRequest) -> Option<String> {
+    let mut ip = request.peer_addr()?.ip();
+    if let Some(trusted_proxies) = request.app_data::<web::Data<TrustedProxies>>() {
+        let mut forwarded_for = request
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev();
+        while trusted_proxies.contains(&ip) {
+            match forwarded_for.next().and_then(|hop| hop.parse().ok()) {
+                Some(hop) => ip = hop,
+                None => break,
+            }
+        }
+    }
+    Some(ip.to_string())
+}
+
+/// Errors shown to users carry a stable code next to their message, for
+/// clients that display their own messages.
+pub trait ErrorCode {
+    fn code(&self) -> &'static str;
+}
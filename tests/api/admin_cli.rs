@@ -0,0 +1,325 @@
+use crate::helpers::{
+    assert_is_redirect_to,
+    create_confirmed_subscribers,
+    spawn_app,
+    TestApp,
+};
+use prod_craft::admin::{
+    execute,
+    AdminCommand,
+    Report,
+};
+use prod_craft::idempotency::IdempotencyScope;
+use uuid::Uuid;
+
+async fn run(app: &TestApp, command: AdminCommand, input: &str) -> Result<Report, anyhow::Error> {
+    execute(
+        command,
+        "on-call",
+        &app.configuration,
+        &mut input.as_bytes(),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn created_users_can_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let report = run(
+        &app,
+        AdminCommand::CreateUser {
+            username: "ursula".into(),
+        },
+        "a-long-enough-password\n",
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(report.get("username").unwrap(), "ursula");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "ursula",
+            "password": "a-long-enough-password"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn usernames_cannot_be_taken_twice() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let outcome = run(
+        &app,
+        AdminCommand::CreateUser {
+            username: app.test_user.username.clone(),
+        },
+        "a-long-enough-password\n",
+    )
+    .await;
+
+    // Assert
+    assert!(outcome.unwrap_err().to_string().contains("already taken"));
+}
+
+#[tokio::test]
+async fn reset_passwords_can_be_used_to_log_in() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    run(
+        &app,
+        AdminCommand::ResetPassword {
+            username: app.test_user.username.clone(),
+        },
+        "a-brand-new-password\n",
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "a-brand-new-password"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn confirming_a_subscriber_records_their_consent() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status, locale)
+        VALUES ($1, 'ursula@example.com', 'Ursula', now(), 'pending_confirmation', 'en')"#,
+        subscriber_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let report = run(
+        &app,
+        AdminCommand::ConfirmSubscriber {
+            email: "ursula@example.com".into(),
+        },
+        "",
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(
+        report.get("previous_status").unwrap(),
+        "pending_confirmation"
+    );
+    let status = sqlx::query_scalar!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(status, "confirmed");
+    let source = sqlx::query_scalar!(
+        "SELECT source FROM consent_events WHERE subscriber_id = $1 AND event_type = 'confirmed'",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(source, "admin-cli");
+}
+
+#[tokio::test]
+async fn requeued_issues_are_queued_once_per_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 3).await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    let issue_id = sqlx::query_scalar!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    // One delivery went out, two are still queued
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_email = 'subscriber-0@example.com'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let report = run(
+        &app,
+        AdminCommand::RequeueIssue {
+            issue_id,
+            all: true,
+        },
+        "",
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(report.get("n_queued").unwrap(), 1);
+    let n_queued = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_queued, 3);
+}
+
+#[tokio::test]
+async fn requeueing_an_issue_must_be_confirmed_with_all() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 1).await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    let issue_id = sqlx::query_scalar!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!("DELETE FROM issue_delivery_queue")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let outcome = run(
+        &app,
+        AdminCommand::RequeueIssue {
+            issue_id,
+            all: false,
+        },
+        "",
+    )
+    .await;
+
+    // Assert
+    assert!(outcome.unwrap_err().to_string().contains("--all"));
+    let n_queued = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn commands_are_recorded_in_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let report = run(
+        &app,
+        AdminCommand::CreateUser {
+            username: "ursula".into(),
+        },
+        "a-long-enough-password\n",
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    let event = sqlx::query!("SELECT user_id, operator, action, target FROM audit_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.user_id, None);
+    assert_eq!(event.operator.as_deref(), Some("on-call"));
+    assert_eq!(event.action, "user.created");
+    assert_eq!(
+        event.target,
+        Some(format!(
+            "user:{}",
+            report.get("user_id").unwrap().as_str().unwrap()
+        ))
+    );
+}
+
+#[tokio::test]
+async fn commands_that_cannot_be_audited_are_rolled_back() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!("ALTER TABLE audit_log RENAME TO audit_log_elsewhere")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let outcome = run(
+        &app,
+        AdminCommand::CreateUser {
+            username: "ursula".into(),
+        },
+        "a-long-enough-password\n",
+    )
+    .await;
+
+    // Assert
+    assert!(outcome.is_err());
+    let n_users =
+        sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM users WHERE username = 'ursula'"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(n_users, 0);
+}
+
+#[tokio::test]
+async fn dry_runs_do_not_purge_idempotency_records() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"INSERT INTO idempotency (scope, user_id, idempotency_key, created_at)
+        VALUES ($1, $2, 'old-key', now() - interval '2 days')"#,
+        IdempotencyScope::User(app.test_user.user_id).to_string(),
+        app.test_user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let purge = |dry_run| AdminCommand::PurgeIdempotency {
+        older_than_hours: 24,
+        dry_run,
+    };
+
+    // Act - Part 1 - Dry run
+    let report = run(&app, purge(true), "").await.unwrap();
+
+    // Assert - Part 1
+    assert_eq!(report.get("n_deleted").unwrap(), 1);
+
+    // Act - Part 2 - Purge
+    run(&app, purge(false), "").await.unwrap();
+    let report = run(&app, purge(true), "").await.unwrap();
+
+    // Assert - Part 2
+    assert_eq!(report.get("n_deleted").unwrap(), 0);
+}
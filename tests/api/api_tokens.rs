@@ -0,0 +1,58 @@
+use crate::helpers::{
+    assert_is_redirect_to,
+    spawn_app,
+};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_api_tokens() {
+    let app = spawn_app().await;
+
+    let response = app.get_api_tokens().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_create_api_token(&serde_json::json!({ "name": "Automation" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn api_tokens_are_shown_once_and_stored_hashed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let token = app.create_api_token().await;
+
+    // The token is listed by name, but its value is not shown again
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("Automation"));
+    assert!(!html_page.contains(&token));
+
+    let saved = sqlx::query!("SELECT token_hash FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved API token.");
+    assert_ne!(saved.token_hash, token);
+}
+
+#[tokio::test]
+async fn a_revoked_api_token_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token().await;
+    let response = app.get_api("/subscribers", &token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let api_token_id = sqlx::query!("SELECT api_token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .api_token_id;
+    let response = app.post_revoke_api_token(&api_token_id.to_string()).await;
+    assert_is_redirect_to(&response, "/admin/api_tokens");
+
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("<p><i>The API token has been revoked.</i></p>"));
+    let response = app.get_api("/subscribers", &token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
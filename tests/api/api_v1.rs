@@ -0,0 +1,169 @@
+use crate::helpers::spawn_app;
+use uuid::Uuid;
+use wiremock::matchers::{
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+#[tokio::test]
+async fn requests_without_a_valid_api_token_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/subscribers", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        r#"Bearer realm="api""#,
+        response.headers()["WWW-Authenticate"]
+    );
+
+    let response = app.get_api("/subscribers", "pc_not-a-real-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_as_json() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let response = app.get_api("/subscribers", &token).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let subscribers = body["subscribers"].as_array().unwrap();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscribers[0]["status"], "pending_confirmation");
+
+    let id = subscribers[0]["id"].as_str().unwrap();
+    let response = app.get_api(&format!("/subscribers/{}", id), &token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["email"], "ursula_le_guin@gmail.com");
+
+    let response = app
+        .get_api(&format!("/subscribers/{}/consent_events", id), &token)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["consent_events"][0]["event_type"], "subscribed");
+
+    let response = app
+        .get_api(&format!("/subscribers/{}", Uuid::new_v4()), &token)
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "not_found");
+}
+
+#[tokio::test]
+async fn newsletter_issues_can_be_published_through_the_api_idempotently() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token().await;
+    let issue = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_api("/newsletter_issues", &token, &idempotency_key, &issue)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let first: serde_json::Value = response.json().await.unwrap();
+    let response = app
+        .post_api("/newsletter_issues", &token, &idempotency_key, &issue)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let second: serde_json::Value = response.json().await.unwrap();
+
+    assert_eq!(first, second);
+    let response = app.get_api("/newsletter_issues", &token).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["newsletter_issues"].as_array().unwrap().len(), 1);
+
+    let issue_id = first["newsletter_issue_id"].as_str().unwrap();
+    let response = app
+        .get_api(
+            &format!("/newsletter_issues/{}/delivery_status", issue_id),
+            &token,
+        )
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["pending_deliveries"], 0);
+}
+
+#[tokio::test]
+async fn publishing_through_the_api_requires_an_idempotency_key() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/api/v1/newsletter_issues", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "validation_error");
+}
+
+#[tokio::test]
+async fn translations_must_be_in_a_supported_locale() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token().await;
+    let issue = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "translations": [{
+            "locale": "tlh",
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }],
+    });
+
+    let response = app
+        .post_api(
+            "/newsletter_issues",
+            &token,
+            &Uuid::new_v4().to_string(),
+            &issue,
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "validation_error");
+}
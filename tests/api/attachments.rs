@@ -0,0 +1,168 @@
+use crate::helpers::{
+    assert_is_redirect_to,
+    create_confirmed_subscribers,
+    spawn_app,
+    spawn_app_with,
+    FileUpload,
+};
+use uuid::Uuid;
+use wiremock::matchers::{
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+fn newsletter_request_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": r#"<p>Newsletter body as HTML</p><img src="cid:logo.png">"#,
+        "idempotency_key": Uuid::new_v4().to_string()
+    })
+}
+
+fn report() -> FileUpload {
+    FileUpload {
+        field: "attachments",
+        file_name: "report.pdf",
+        content_type: "application/pdf",
+        content: b"%PDF-1.4".to_vec(),
+    }
+}
+
+fn logo() -> FileUpload {
+    FileUpload {
+        field: "inline_images",
+        file_name: "logo.png",
+        content_type: "image/png",
+        content: vec![0x89, b'P', b'N', b'G'],
+    }
+}
+
+#[tokio::test]
+async fn attachments_and_inline_images_are_sent_with_the_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 1).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter_with_files(&newsletter_request_body(), vec![report(), logo()])
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body["Attachments"],
+        serde_json::json!([
+            {
+                "Name": "logo.png",
+                "Content": "iVBORw==",
+                "ContentType": "image/png",
+                "ContentID": "cid:logo.png"
+            },
+            {
+                "Name": "report.pdf",
+                "Content": "JVBERi0xLjQ=",
+                "ContentType": "application/pdf"
+            }
+        ])
+    );
+}
+
+#[tokio::test]
+async fn the_issue_page_lists_its_attachments() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter_with_files(&newsletter_request_body(), vec![report(), logo()])
+        .await;
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    // Act
+    let html_page = app
+        .api_client
+        .get(format!("{}/admin/newsletters/{}", &app.address, issue_id))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("<li>logo.png (image/png, 4 B, embedded)</li>"));
+    assert!(html_page.contains("<li>report.pdf (application/pdf, 8 B)</li>"));
+}
+
+#[tokio::test]
+async fn files_of_a_type_that_is_not_allowed_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let program = FileUpload {
+        field: "attachments",
+        file_name: "run.exe",
+        content_type: "application/x-msdownload",
+        content: b"MZ".to_vec(),
+    };
+
+    // Act
+    let response = app
+        .post_publish_newsletter_with_files(&newsletter_request_body(), vec![program])
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>run.exe cannot be attached: application/x-msdownload files are not allowed.</i></p>"
+    ));
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM newsletter_issues",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn files_over_the_size_limit_are_rejected() {
+    // Arrange
+    let app = spawn_app_with(|c| c.attachments.max_file_size_bytes = 4).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter_with_files(&newsletter_request_body(), vec![logo(), report()])
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page
+        .contains("<p><i>report.pdf is larger than the size limit for a single file.</i></p>"));
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM newsletter_issues",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
@@ -0,0 +1,180 @@
+use crate::helpers::{
+    assert_is_redirect_to,
+    spawn_app,
+    TestApp,
+};
+use prod_craft::admin::{
+    execute,
+    AdminCommand,
+};
+use uuid::Uuid;
+
+struct AuditEventRecord {
+    user_id: Option<Uuid>,
+    action: String,
+    target: Option<String>,
+    ip_address: Option<String>,
+}
+
+async fn audit_events(app: &TestApp) -> Vec<AuditEventRecord> {
+    sqlx::query_as!(
+        AuditEventRecord,
+        "SELECT user_id, action, target, ip_address FROM audit_log ORDER BY occurred_at"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_audit_log() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_audit_log("").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn publishing_a_newsletter_issue_is_audited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let issue_id = sqlx::query_scalar!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let events = audit_events(&app).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].user_id, Some(app.test_user.user_id));
+    assert_eq!(events[0].action, "newsletter_issue.published");
+    assert_eq!(
+        events[0].target.as_deref(),
+        Some(format!("newsletter_issue:{issue_id}").as_str())
+    );
+    assert_eq!(events[0].ip_address.as_deref(), Some("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn rejected_actions_are_not_audited() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    app.post_change_password(&serde_json::json!({
+        "current_password": Uuid::new_v4().to_string(),
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+
+    // Assert
+    assert!(audit_events(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered_by_action() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+    let response = app
+        .post_suppression(&serde_json::json!({
+            "email": "ursula_le_guin@gmail.com",
+            "reason": "manual"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    // Act - Part 1 - Every event
+    let html_page = app.get_audit_log_html("").await;
+    assert!(html_page.contains("<td>password.changed</td>"));
+    assert!(html_page.contains("<td>suppression.added</td>"));
+    assert!(html_page.contains(&app.test_user.username));
+
+    // Act - Part 2 - Filtered
+    let html_page = app
+        .get_audit_log_html("action=suppression.added&user=")
+        .await;
+    assert!(!html_page.contains("<td>password.changed</td>"));
+    assert!(html_page.contains("<td>suppression.added</td>"));
+
+    // Act - Part 3 - Another user
+    let html_page = app.get_audit_log_html("user=somebody-else").await;
+    assert!(!html_page.contains("<td>suppression.added</td>"));
+}
+
+#[tokio::test]
+async fn command_line_actions_show_who_ran_them() {
+    // Arrange
+    let app = spawn_app().await;
+    execute(
+        AdminCommand::ResetPassword {
+            username: app.test_user.username.clone(),
+        },
+        "on-call",
+        &app.configuration,
+        &mut "a-brand-new-password\n".as_bytes(),
+    )
+    .await
+    .unwrap();
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "a-brand-new-password"
+    }))
+    .await;
+
+    // Act
+    let html_page = app.get_audit_log_html("user=on-call").await;
+
+    // Assert
+    assert!(html_page.contains("<td>on-call (command line)</td>"));
+    assert!(html_page.contains("<td>password.reset</td>"));
+}
+
+#[tokio::test]
+async fn the_audit_log_is_append_only() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+    assert_eq!(audit_events(&app).await[0].action, "session.logged_out");
+
+    // Act
+    let update = sqlx::query!("UPDATE audit_log SET action = 'nothing'")
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM audit_log")
+        .execute(&app.db_pool)
+        .await;
+
+    // Assert
+    assert!(update.is_err());
+    assert!(delete.is_err());
+}
@@ -0,0 +1,128 @@
+use crate::helpers::{
+    spawn_app,
+    TestApp,
+};
+use wiremock::matchers::{
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+async fn subscribe_from_widget(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("User-Agent", "Mozilla/5.0 (Test)")
+        .form(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "source": "footer-widget",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn subscribing_records_a_consent_event() {
+    let app = spawn_app().await;
+
+    subscribe_from_widget(&app).await;
+
+    let event = sqlx::query!(
+        "SELECT event_type, ip_address, user_agent, source, consent_wording FROM consent_events",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch consent event.");
+    assert_eq!(event.event_type, "subscribed");
+    assert_eq!(event.ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(event.user_agent.as_deref(), Some("Mozilla/5.0 (Test)"));
+    assert_eq!(event.source, "footer-widget");
+    assert!(!event.consent_wording.is_empty());
+}
+
+#[tokio::test]
+async fn confirming_records_a_consent_event_only_once() {
+    let app = spawn_app().await;
+    subscribe_from_widget(&app).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    for _ in 0..2 {
+        reqwest::get(confirmation_links.html.clone())
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    let events = sqlx::query!(
+        "SELECT event_type, consent_wording FROM consent_events ORDER BY occurred_at",
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch consent events.");
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].event_type, "subscribed");
+    assert_eq!(events[1].event_type, "confirmed");
+    assert_eq!(events[0].consent_wording, events[1].consent_wording);
+}
+
+#[tokio::test]
+async fn consent_events_cannot_be_altered() {
+    let app = spawn_app().await;
+    subscribe_from_widget(&app).await;
+
+    let update = sqlx::query!("UPDATE consent_events SET source = 'forged'",)
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM consent_events",)
+        .execute(&app.db_pool)
+        .await;
+
+    assert!(update.is_err());
+    assert!(delete.is_err());
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app
+        .get_subscriber_details(&uuid::Uuid::new_v4().to_string())
+        .await;
+
+    crate::helpers::assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_subscriber_page_shows_the_consent_history() {
+    let app = spawn_app().await;
+    subscribe_from_widget(&app).await;
+    app.test_user.login(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+
+    let html_page = app.get_subscribers_html().await;
+    assert!(html_page.contains(&format!(r#"href="/admin/subscribers/{}""#, subscriber_id)));
+
+    let response = app.get_subscriber_details(&subscriber_id.to_string()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<td>subscribed</td>"));
+    assert!(html_page.contains("<td>footer-widget</td>"));
+    assert!(html_page.contains("<td>127.0.0.1</td>"));
+}
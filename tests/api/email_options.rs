@@ -0,0 +1,206 @@
+use crate::helpers::{
+    assert_is_redirect_to,
+    create_confirmed_subscribers,
+    spawn_app,
+};
+use uuid::Uuid;
+use wiremock::matchers::{
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+#[tokio::test]
+async fn issues_are_sent_with_the_email_options_picked_by_their_author() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 1).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "sender": "editor",
+            "reply_to": "replies@example.com",
+            "headers": "X-Campaign: spring\nList-Id: <news.example.com>",
+            "tag": "spring-issue",
+            "metadata": "campaign: spring\nedition: 12",
+            "message_stream": "",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["From"], "The Editor <editor@gmail.com>");
+    assert_eq!(body["ReplyTo"], "replies@example.com");
+    assert_eq!(
+        body["Headers"],
+        serde_json::json!([
+            { "Name": "X-Campaign", "Value": "spring" },
+            { "Name": "List-Id", "Value": "<news.example.com>" }
+        ])
+    );
+    assert_eq!(body["Tag"], "spring-issue");
+    assert_eq!(
+        body["Metadata"],
+        serde_json::json!({ "campaign": "spring", "edition": "12" })
+    );
+    // Blank, the configured message stream is used.
+    assert_eq!(body["MessageStream"], "broadcast");
+}
+
+#[tokio::test]
+async fn the_issue_page_shows_its_email_options() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "sender": "editor",
+        "message_stream": "announcements",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    // Act
+    let html_page = app
+        .api_client
+        .get(format!("{}/admin/newsletters/{}", &app.address, issue_id))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("<p>From: The Editor &lt;editor@gmail.com&gt;</p>"));
+    assert!(html_page.contains("<p>Postmark message stream: announcements</p>"));
+}
+
+#[tokio::test]
+async fn headers_set_from_other_fields_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "headers": "From: ceo@example.com",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The From header cannot be set on an issue.</i></p>"));
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM newsletter_issues",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn issues_published_through_the_api_carry_their_email_options() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token().await;
+    let issue = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "sender": "editor",
+        "headers": [{ "name": "X-Campaign", "value": "spring" }],
+        "metadata": { "campaign": "spring" },
+    });
+
+    // Act
+    let response = app
+        .post_api(
+            "/newsletter_issues",
+            &token,
+            &Uuid::new_v4().to_string(),
+            &issue,
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap();
+    let response = app
+        .get_api(&format!("/newsletter_issues/{issue_id}"), &token)
+        .await;
+
+    // Assert
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["sender"],
+        serde_json::json!({ "name": "The Editor", "email": "editor@gmail.com" })
+    );
+    assert_eq!(
+        body["headers"],
+        serde_json::json!([{ "name": "X-Campaign", "value": "spring" }])
+    );
+    assert_eq!(
+        body["metadata"],
+        serde_json::json!({ "campaign": "spring" })
+    );
+    assert_eq!(body["message_stream"], "broadcast");
+}
+
+#[tokio::test]
+async fn the_sender_must_be_a_configured_identity() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token().await;
+    let issue = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "sender": "ceo",
+    });
+
+    // Act
+    let response = app
+        .post_api(
+            "/newsletter_issues",
+            &token,
+            &Uuid::new_v4().to_string(),
+            &issue,
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -7,15 +7,19 @@ use argon2::{
     Version,
 };
 use once_cell::sync::Lazy;
+use prod_craft::attachment::LocalBlobStore;
 use prod_craft::configuration::{
     get_configuration,
     DatabaseSettings,
+    Settings,
 };
+use prod_craft::deliverability::MxResolver;
 use prod_craft::email_client::EmailClient;
 use prod_craft::issue_delivery_worker::{
     try_execute_task,
     ExecutionOutcome,
 };
+use prod_craft::migrations::migrate_database;
 use prod_craft::startup::{
     get_connection_pool,
     Application,
@@ -24,12 +28,17 @@ use prod_craft::telemetry::{
     get_subscriber,
     init_subscriber,
 };
+use prod_craft::throttle::{
+    DomainLimits,
+    Throttle,
+};
 use sqlx::{
     Connection,
     Executor,
     PgConnection,
     PgPool,
 };
+use std::sync::Arc;
 use uuid::Uuid;
 use wiremock::MockServer;
 
@@ -46,6 +55,8 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     };
 });
 
+pub const MARKETING_SITE_ORIGIN: &str = "https://marketing.example.com";
+
 pub struct TestApp {
     pub address: String,
     pub port: u16,
@@ -54,6 +65,18 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub attachment_store: LocalBlobStore,
+    pub throttle: Throttle,
+    pub configuration: Settings,
+}
+
+/// A file sent with the publish form.
+pub struct FileUpload {
+    /// `attachments` or `inline_images`.
+    pub field: &'static str,
+    pub file_name: &'static str,
+    pub content_type: &'static str,
+    pub content: Vec<u8>,
 }
 
 /// Confirmation links embedded in the request to the email API.
@@ -62,22 +85,35 @@ pub struct ConfirmationLinks {
     pub plain_text: reqwest::Url,
 }
 
+/// The links emailed to a subscriber who asked for their data.
+pub struct DataRequestLinks {
+    pub export: reqwest::Url,
+    pub erasure: reqwest::Url,
+}
+
 impl TestApp {
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            match try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.attachment_store,
+                &self.throttle,
+                &self.address,
+            )
+            .await
+            .unwrap()
             {
-                break;
+                ExecutionOutcome::EmptyQueue => break,
+                ExecutionOutcome::Throttled(wait) => tokio::time::sleep(wait).await,
+                ExecutionOutcome::TaskCompleted => {}
             }
         }
     }
 
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -85,12 +121,30 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_data_request(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/data_requests", &self.address))
+            .form(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
@@ -99,7 +153,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -110,7 +164,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -122,7 +176,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -134,7 +188,7 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -145,7 +199,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
@@ -154,7 +208,7 @@ impl TestApp {
 
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -165,17 +219,215 @@ impl TestApp {
     }
 
     pub async fn post_publish_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_publish_newsletter_with_files(body, Vec::new())
+            .await
+    }
+
+    /// The form is sent as `multipart/form-data`, like browsers do.
+    pub async fn post_publish_newsletter_with_files<Body>(
+        &self,
+        body: &Body,
+        files: Vec<FileUpload>,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let mut form = reqwest::multipart::Form::new();
+        let fields = serde_json::to_value(body).unwrap();
+        for (name, value) in fields.as_object().unwrap() {
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                value => value.to_string(),
+            };
+            form = form.text(name.clone(), value);
+        }
+        for file in files {
+            let part = reqwest::multipart::Part::bytes(file.content)
+                .file_name(file.file_name)
+                .mime_str(file.content_type)
+                .unwrap();
+            form = form.part(file.field, part);
+        }
+        self.api_client
+            .post(format!("{}/admin/newsletters", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/subscribers", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_subscriber_details(&self, subscriber_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_erase_subscriber(&self, subscriber_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/erase",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_suppressions(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/suppressions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_suppressions_html(&self) -> String {
+        self.get_suppressions().await.text().await.unwrap()
+    }
+
+    pub async fn post_suppression<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/suppressions", &self.address))
             .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_import_suppressions<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/suppressions/import", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_remove_suppression(&self, email_hash: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/suppressions/{}/remove",
+                &self.address, email_hash
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_tokens(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/api_tokens", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_tokens_html(&self) -> String {
+        self.get_api_tokens().await.text().await.unwrap()
+    }
+
+    pub async fn post_create_api_token<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/api_tokens", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke_api_token(&self, api_token_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/api_tokens/{}/revoke",
+                &self.address, api_token_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Create an API token through the admin UI and return its plain-text
+    /// value. The test user must be logged in.
+    pub async fn create_api_token(&self) -> String {
+        let response = self
+            .post_create_api_token(&serde_json::json!({ "name": "Automation" }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/api_tokens");
+        let html_page = self.get_api_tokens_html().await;
+        let start = html_page.find("<code>").unwrap() + "<code>".len();
+        let end = html_page.find("</code>").unwrap();
+        html_page[start..end].to_owned()
+    }
+
+    pub async fn get_audit_log(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit_log?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_audit_log_html(&self, query: &str) -> String {
+        self.get_audit_log(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_api(&self, path: &str, api_token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/api/v1{}", &self.address, path))
+            .bearer_auth(api_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_api<Body>(
+        &self,
+        path: &str,
+        api_token: &str,
+        idempotency_key: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/api/v1{}", &self.address, path))
+            .bearer_auth(api_token)
+            .header("Idempotency-Key", idempotency_key)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     /// Extract the confirmation links embedded in the request to the email API.
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
@@ -199,9 +451,61 @@ impl TestApp {
         let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
+
+    /// Extract the export and erasure links from a data request email.
+    pub fn get_data_request_links(&self, email_request: &wiremock::Request) -> DataRequestLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let links: Vec<_> = linkify::LinkFinder::new()
+            .links(body["TextBody"].as_str().unwrap())
+            .filter(|l| *l.kind() == linkify::LinkKind::Url)
+            .map(|l| {
+                let mut link = reqwest::Url::parse(l.as_str()).unwrap();
+                assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+                link.set_port(Some(self.port)).unwrap();
+                link
+            })
+            .collect();
+        assert_eq!(links.len(), 2);
+        DataRequestLinks {
+            export: links[0].clone(),
+            erasure: links[1].clone(),
+        }
+    }
+}
+
+/// Skips the signup flow, for tests that need many subscribers.
+pub async fn create_confirmed_subscribers(app: &TestApp, n: usize) {
+    for i in 0..n {
+        sqlx::query!(
+            r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status, locale)
+            VALUES ($1, $2, $3, now(), 'confirmed', 'en')"#,
+            Uuid::new_v4(),
+            format!("subscriber-{i}@example.com"),
+            format!("Subscriber {i}"),
+        )
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to insert a subscriber.");
+    }
+}
+
+/// Stands in for DNS: only domains under the reserved `.invalid` TLD have no
+/// mail servers.
+pub struct StubMxResolver;
+
+#[async_trait::async_trait]
+impl MxResolver for StubMxResolver {
+    async fn has_mx_records(&self, domain: &str) -> Result<bool, anyhow::Error> {
+        Ok(!domain.ends_with(".invalid"))
+    }
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn the application after letting the test tweak its configuration.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
     // Launch a mock server to stand in for Postmark's API
@@ -216,18 +520,31 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         // Use the mock server as email API
         c.email_client.base_url = email_server.uri();
+        // Allow a fake marketing site to embed the signup form
+        c.application.cors_allowed_origins = vec![MARKETING_SITE_ORIGIN.into()];
+        // Keep uploaded files apart from other test cases
+        c.attachments.storage_path = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        // Send as fast as possible, unless the test is about throttling
+        c.delivery.max_sends_per_second = None;
+        c.delivery.per_domain = DomainLimits::default();
+        c.delivery.domain_overrides.clear();
+        configure(&mut c);
         c
     };
 
     // Create and migrate the database
     configure_database(&configuration.database).await;
 
-    // Launch the application as a background task
-    let application = Application::build(configuration.clone())
+    // Launch the application as a background task, without real DNS lookups
+    let mx_resolver = configuration
+        .signup
+        .check_mx_records
+        .then(|| Arc::new(StubMxResolver) as Arc<dyn MxResolver>);
+    let application = Application::build_with_mx_resolver(configuration.clone(), mx_resolver)
         .await
         .expect("Failed to build application.");
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    tokio::spawn(application.run_until_stopped());
 
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
@@ -242,7 +559,10 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         test_user: TestUser::generate(),
         api_client: client,
-        email_client: configuration.email_client.client(),
+        email_client: configuration.email_client.clone().client(),
+        attachment_store: configuration.attachments.store(),
+        throttle: configuration.delivery.throttle(),
+        configuration,
     };
 
     test_app.test_user.store(&test_app.db_pool).await;
@@ -264,8 +584,7 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
     let connection_pool = PgPool::connect_with(config.with_db())
         .await
         .expect("Failed to connect to Postgres.");
-    sqlx::migrate!("./migrations")
-        .run(&connection_pool)
+    migrate_database(&connection_pool)
         .await
         .expect("Failed to migrate the database");
 
@@ -273,7 +592,7 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
 }
 
 pub struct TestUser {
-    user_id: Uuid,
+    pub user_id: Uuid,
     pub username: String,
     pub password: String,
 }
@@ -0,0 +1,147 @@
+use crate::helpers::{
+    assert_is_redirect_to,
+    spawn_app,
+    TestApp,
+};
+use wiremock::matchers::{
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+async fn get_login_html_in(app: &TestApp, accept_language: &str) -> String {
+    app.api_client
+        .get(format!("{}/login", &app.address))
+        .header("Accept-Language", accept_language)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap()
+}
+
+async fn subscribe_in(app: &TestApp, accept_language: &str, body: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept-Language", accept_language)
+        .body(body.to_owned())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn pages_are_served_in_the_language_the_client_prefers() {
+    let app = spawn_app().await;
+
+    let html_page = get_login_html_in(&app, "fr-FR, sw-KE;q=0.8, en;q=0.5").await;
+
+    assert!(html_page.contains(r#"<html lang="sw">"#));
+    assert!(html_page.contains("<title>Ingia</title>"));
+}
+
+#[tokio::test]
+async fn pages_fall_back_to_english() {
+    let app = spawn_app().await;
+
+    let html_page = get_login_html_in(&app, "fr-FR, de;q=0.5").await;
+
+    assert!(html_page.contains(r#"<html lang="en">"#));
+    assert!(html_page.contains("<title>Login</title>"));
+}
+
+#[tokio::test]
+async fn flash_messages_are_translated() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .header("Accept-Language", "sw")
+        .form(&serde_json::json!({
+            "username": "random-username",
+            "password": "random-password"
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = get_login_html_in(&app, "sw").await;
+    assert!(html_page.contains("<p><i>Uthibitishaji umeshindikana</i></p>"));
+}
+
+#[tokio::test]
+async fn subscribers_get_emails_in_the_language_they_signed_up_in() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    subscribe_in(
+        &app,
+        "sw",
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let saved = sqlx::query!("SELECT locale FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.locale, "sw");
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Karibu!");
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Karibu kwenye jarida letu!"));
+}
+
+#[tokio::test]
+async fn an_explicit_locale_overrides_the_accept_language_header() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe_in(
+        &app,
+        "en",
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=sw",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let saved = sqlx::query!("SELECT locale FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.locale, "sw");
+}
+
+#[tokio::test]
+async fn validation_errors_are_translated() {
+    let app = spawn_app().await;
+
+    let response = subscribe_in(&app, "sw", "name=&email=ursula_le_guin%40gmail.com").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["reason"], "empty");
+    assert_eq!(body["error"]["message"], "Jina haliwezi kuwa tupu.");
+}
@@ -0,0 +1,275 @@
+use crate::helpers::{
+    assert_is_redirect_to,
+    create_confirmed_subscribers,
+    spawn_app,
+    spawn_app_with,
+    TestApp,
+};
+use prod_craft::idempotency::{
+    delete_expired_keys,
+    try_processing,
+    IdempotencyKey,
+    IdempotencyScope,
+    NextAction,
+    RequestFingerprint,
+};
+use sqlx::{
+    Postgres,
+    Transaction,
+};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Starts processing a request with `key` on behalf of the test user, as if
+/// another request with it were in flight.
+async fn hold_key(app: &TestApp, key: &str) -> Transaction<'static, Postgres> {
+    let key = IdempotencyKey::try_from(key.to_owned()).unwrap();
+    let fingerprint = RequestFingerprint::of(&"Another request").unwrap();
+    let policy = app.configuration.idempotency.policy();
+    match try_processing(
+        &app.db_pool,
+        &key,
+        &fingerprint,
+        &IdempotencyScope::User(app.test_user.user_id),
+        &policy,
+    )
+    .await
+    {
+        Ok(NextAction::StartProcessing(transaction)) => transaction,
+        _ => panic!("The key should have been new."),
+    }
+}
+
+async fn count_issues(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+fn newsletter_request_body(idempotency_key: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": idempotency_key
+    })
+}
+
+#[tokio::test]
+async fn expired_idempotency_keys_are_treated_as_new() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 1).await;
+    app.test_user.login(&app).await;
+    let newsletter_request_body = newsletter_request_body(&Uuid::new_v4().to_string());
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let expired_hours = app.configuration.idempotency.retention_hours as i32 + 1;
+    sqlx::query!(
+        "UPDATE idempotency SET created_at = now() - make_interval(hours => $1)",
+        expired_hours
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(count_issues(&app).await, 2);
+}
+
+#[tokio::test]
+async fn requests_wait_for_a_request_with_the_same_key_to_be_done() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let key = Uuid::new_v4().to_string();
+    let in_flight = hold_key(&app, &key).await;
+    let body = newsletter_request_body(&key);
+
+    // Act
+    let (response, _) = tokio::join!(app.post_publish_newsletter(&body), async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        // The request in flight fails
+        drop(in_flight);
+    });
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn requests_giving_up_on_a_key_in_flight_get_a_conflict() {
+    // Arrange
+    let app = spawn_app_with(|c| c.idempotency.in_flight_timeout_milliseconds = 100).await;
+    app.test_user.login(&app).await;
+    let key = Uuid::new_v4().to_string();
+    let in_flight = hold_key(&app, &key).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&newsletter_request_body(&key))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    drop(in_flight);
+}
+
+#[tokio::test]
+async fn keys_are_released_when_processing_fails() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let key = Uuid::new_v4().to_string();
+    // Processing fails before a response is saved
+    drop(hold_key(&app, &key).await);
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&newsletter_request_body(&key))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn expired_idempotency_keys_are_deleted_in_batches() {
+    // Arrange
+    let app = spawn_app().await;
+    for (key, age_hours) in [("old-1", 30), ("old-2", 26), ("old-3", 25), ("recent", 1)] {
+        sqlx::query!(
+            r#"INSERT INTO idempotency (scope, idempotency_key, created_at)
+            VALUES ($1, $2, now() - make_interval(hours => $3))"#,
+            IdempotencyScope::User(app.test_user.user_id).to_string(),
+            key,
+            age_hours
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    // Act
+    let n_deleted = delete_expired_keys(&app.db_pool, Duration::from_secs(24 * 60 * 60), 2)
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(n_deleted, 3);
+    let remaining = sqlx::query_scalar!("SELECT idempotency_key FROM idempotency")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, ["recent"]);
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_request_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let key = Uuid::new_v4().to_string();
+    let response = app
+        .post_publish_newsletter(&newsletter_request_body(&key))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let mut different_request_body = newsletter_request_body(&key);
+    different_request_body["title"] = "Another newsletter title".into();
+
+    // Act
+    let response = app.post_publish_newsletter(&different_request_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_api_request_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token().await;
+    let key = Uuid::new_v4().to_string();
+    let mut issue = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    });
+    let response = app
+        .post_api("/newsletter_issues", &token, &key, &issue)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    issue["text_content"] = "Another body".into();
+
+    // Act
+    let response = app
+        .post_api("/newsletter_issues", &token, &key, &issue)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "idempotency_key_reused");
+}
+
+#[tokio::test]
+async fn replayed_responses_are_flagged() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token().await;
+    let key = Uuid::new_v4().to_string();
+    let issue = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    });
+    let response = app
+        .post_api("/newsletter_issues", &token, &key, &issue)
+        .await;
+    assert!(response.headers().get("Idempotent-Replayed").is_none());
+    let first_body: serde_json::Value = response.json().await.unwrap();
+
+    // Act
+    let response = app
+        .post_api("/newsletter_issues", &token, &key, &issue)
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    assert_eq!(response.headers()["Idempotent-Replayed"], "true");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body, first_body);
+    assert_eq!(count_issues(&app).await, 1);
+}
+
+#[tokio::test]
+async fn oversized_signups_are_rejected_before_being_read_in_full() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&idempotency_key={}&padding={}",
+        Uuid::new_v4(),
+        "a".repeat(32 * 1024)
+    );
+
+    // Act
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 413);
+    let n_keys = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM idempotency"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_keys, 0);
+}
@@ -0,0 +1,155 @@
+use crate::helpers::{
+    assert_is_redirect_to,
+    create_confirmed_subscribers,
+    spawn_app,
+};
+use prod_craft::issue_delivery_worker::run_worker_until_stopped;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use wiremock::matchers::{
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+#[tokio::test]
+async fn deliveries_in_progress_are_completed_on_shutdown() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 1).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let shutdown = CancellationToken::new();
+    let workers = tokio::spawn(run_worker_until_stopped(
+        app.configuration.clone(),
+        shutdown.clone(),
+    ));
+    // Wait for a worker to pick up the delivery, i.e. to lock it
+    loop {
+        let n_unlocked = sqlx::query!(
+            r#"SELECT COUNT(*) AS "n!" FROM (
+                SELECT 1 FROM issue_delivery_queue FOR UPDATE SKIP LOCKED
+            ) q"#
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+        if n_unlocked == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Act
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(5), workers)
+        .await
+        .expect("The workers did not stop.")
+        .unwrap()
+        .unwrap();
+
+    // Assert
+    let n_pending = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_pending, 0);
+    let n_sent = sqlx::query!(r#"SELECT SUM(n_sent) AS "n!" FROM newsletter_issue_variants"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_sent, 1);
+}
+
+#[tokio::test]
+async fn idle_workers_stop_right_away_on_shutdown() {
+    // Arrange
+    let app = spawn_app().await;
+    let shutdown = CancellationToken::new();
+    let workers = tokio::spawn(run_worker_until_stopped(
+        app.configuration.clone(),
+        shutdown.clone(),
+    ));
+    // Let the workers find the queue empty
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Act
+    shutdown.cancel();
+
+    // Assert
+    tokio::time::timeout(Duration::from_secs(1), workers)
+        .await
+        .expect("The workers did not stop.")
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn idle_workers_are_woken_up_by_new_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 1).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let mut configuration = app.configuration.clone();
+    // Long enough that polling would not deliver within the test
+    configuration.delivery.poll_interval_seconds = 60;
+    let shutdown = CancellationToken::new();
+    let workers = tokio::spawn(run_worker_until_stopped(configuration, shutdown.clone()));
+    // Let the workers find the queue empty
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    tokio::time::timeout(Duration::from_secs(3), async {
+        while app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .is_empty()
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The issue was not delivered.");
+    shutdown.cancel();
+    workers.await.unwrap().unwrap();
+}
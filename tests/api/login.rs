@@ -39,6 +39,6 @@ async fn redirect_to_admin_dashboard_after_login_success() {
     assert_is_redirect_to(&response, "/admin/dashboard");
 
     // Act - Part 2 - Follow the redirect
-    let html_page = response.text().await.unwrap();
+    let html_page = app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
 }
@@ -1,7 +1,23 @@
+mod admin_cli;
+mod api_tokens;
+mod api_v1;
+mod attachments;
+mod audit_log;
 mod change_password;
+mod consent;
+mod email_options;
 mod health_check;
 mod helpers;
+mod i18n;
+mod idempotency;
+mod issue_delivery_worker;
 mod login;
+mod migrations;
 mod newsletter;
+mod signup_protection;
+mod subject_tests;
+mod subscriber_data;
 mod subscriptions;
 mod subscriptions_confirm;
+mod suppressions;
+mod throttling;
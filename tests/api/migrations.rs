@@ -0,0 +1,108 @@
+use crate::helpers::spawn_app;
+use prod_craft::configuration::Settings;
+use prod_craft::migrations::{
+    check_schema,
+    migrate_database,
+};
+use prod_craft::startup::{
+    get_connection_pool,
+    Application,
+};
+use sqlx::{
+    Connection,
+    Executor,
+    PgConnection,
+};
+use uuid::Uuid;
+
+/// `configuration`, pointed at a new database without any migrations
+/// applied.
+async fn with_empty_database(configuration: &Settings) -> Settings {
+    let mut configuration = configuration.clone();
+    configuration.database.database_name = Uuid::new_v4().to_string();
+    let mut connection = PgConnection::connect_with(&configuration.database.without_db())
+        .await
+        .expect("Failed to connect to Postgres");
+    connection
+        .execute(&*format!(
+            r#"CREATE DATABASE "{}";"#,
+            configuration.database.database_name
+        ))
+        .await
+        .expect("Failed to create database.");
+    configuration
+}
+
+#[tokio::test]
+async fn the_application_refuses_to_serve_an_unmigrated_database() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut configuration = with_empty_database(&app.configuration).await;
+    configuration.application.migrate_on_startup = false;
+
+    // Act
+    let outcome = Application::build_with_mx_resolver(configuration, None).await;
+
+    // Assert
+    let error = outcome.err().unwrap();
+    assert!(format!("{error:?}").contains("The database is behind"));
+}
+
+#[tokio::test]
+async fn the_application_refuses_to_serve_a_database_ahead_of_it() {
+    // Arrange
+    let app = spawn_app().await;
+    // Not checked at compile time, the table is not created by a migration
+    sqlx::query(
+        r#"INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+        VALUES (99990101000000, 'from a newer release', true, '\x00', 0)"#,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let mut configuration = app.configuration.clone();
+    configuration.application.migrate_on_startup = false;
+
+    // Act
+    let outcome = Application::build_with_mx_resolver(configuration, None).await;
+
+    // Assert
+    let error = outcome.err().unwrap();
+    assert!(format!("{error:?}").contains("[99990101000000] are unknown"));
+}
+
+#[tokio::test]
+async fn missing_migrations_are_applied_on_startup_when_enabled() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut configuration = with_empty_database(&app.configuration).await;
+    configuration.application.migrate_on_startup = true;
+
+    // Act
+    Application::build_with_mx_resolver(configuration.clone(), None)
+        .await
+        .expect("Failed to build application.");
+
+    // Assert
+    let pool = get_connection_pool(&configuration.database);
+    check_schema(&pool).await.unwrap();
+}
+
+#[tokio::test]
+async fn replicas_migrating_at_the_same_time_do_not_conflict() {
+    // Arrange
+    let app = spawn_app().await;
+    let configuration = with_empty_database(&app.configuration).await;
+    let pools: Vec<_> = (0..3)
+        .map(|_| get_connection_pool(&configuration.database))
+        .collect();
+
+    // Act
+    let outcomes = futures_util::future::join_all(pools.iter().map(migrate_database)).await;
+
+    // Assert
+    for outcome in outcomes {
+        outcome.unwrap();
+    }
+    check_schema(&pools[0]).await.unwrap();
+}
@@ -18,14 +18,15 @@ use wiremock::{
     ResponseTemplate,
 };
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+async fn create_unconfirmed_subscriber(app: &TestApp, locale: &str) -> ConfirmationLinks {
     // We are working with multiple subscribers now,
     // their details must be randomised to avoid conflicts!
     let name: String = Name().fake();
     let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
+    let body = serde_urlencoded::to_string(serde_json::json!({
         "name": name,
-        "email": email
+        "email": email,
+        "locale": locale
     }))
     .unwrap();
 
@@ -36,7 +37,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .expect(1)
         .mount_as_scoped(&app.email_server)
         .await;
-    app.post_subscriptions(body.into())
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .unwrap();
@@ -52,7 +53,11 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
 }
 
 async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await.html;
+    create_confirmed_subscriber_in(app, "en").await;
+}
+
+async fn create_confirmed_subscriber_in(app: &TestApp, locale: &str) {
+    let confirmation_link = create_unconfirmed_subscriber(app, locale).await.html;
     reqwest::get(confirmation_link)
         .await
         .unwrap()
@@ -64,7 +69,7 @@ async fn create_confirmed_subscriber(app: &TestApp) {
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     // Arrange
     let app = spawn_app().await;
-    create_unconfirmed_subscriber(&app).await;
+    create_unconfirmed_subscriber(&app, "en").await;
     app.test_user.login(&app).await;
 
     Mock::given(any())
@@ -236,3 +241,169 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     app.dispatch_all_pending_emails().await;
     // Mock verifies on Drop that we have sent the newsletter email **once**
 }
+
+/// The subject of the newsletter email each subscriber got, by locale.
+async fn subjects_by_locale(app: &TestApp) -> Vec<(String, String)> {
+    let mut subjects = vec![];
+    for request in app.email_server.received_requests().await.unwrap() {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let locale = sqlx::query!(
+            "SELECT locale FROM subscriptions WHERE email = $1",
+            body["To"].as_str().unwrap()
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .locale;
+        subjects.push((locale, body["Subject"].as_str().unwrap().to_owned()));
+    }
+    subjects.sort();
+    subjects
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_in_the_language_of_each_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_in(&app, "en").await;
+    create_confirmed_subscriber_in(&app, "sw").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "title_sw": "Kichwa cha jarida",
+        "text_content_sw": "Maudhui ya jarida",
+        "html_content_sw": "<p>Maudhui ya jarida</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    // Forget about the confirmation emails.
+    app.email_server.reset().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(
+        subjects_by_locale(&app).await,
+        vec![
+            ("en".to_owned(), "Newsletter title".to_owned()),
+            ("sw".to_owned(), "Kichwa cha jarida".to_owned()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribers_get_the_default_variant_when_the_issue_was_not_translated() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_in(&app, "sw").await;
+    app.test_user.login(&app).await;
+    // Forget about the confirmation email.
+    app.email_server.reset().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(
+        subjects_by_locale(&app).await,
+        vec![("sw".to_owned(), "Newsletter title".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn the_delivery_report_is_broken_down_by_variant() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber_in(&app, "en").await;
+    create_confirmed_subscriber_in(&app, "sw").await;
+    create_confirmed_subscriber_in(&app, "sw").await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token().await;
+    let issue = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "translations": [{
+            "locale": "sw",
+            "title": "Kichwa cha jarida",
+            "text_content": "Maudhui ya jarida",
+            "html_content": "<p>Maudhui ya jarida</p>",
+        }],
+    });
+    let response = app
+        .post_api(
+            "/newsletter_issues",
+            &token,
+            &uuid::Uuid::new_v4().to_string(),
+            &issue,
+        )
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let delivery_status_path = format!(
+        "/newsletter_issues/{}/delivery_status",
+        body["newsletter_issue_id"].as_str().unwrap()
+    );
+
+    // Act - Part 1 - Before delivery
+    let response = app.get_api(&delivery_status_path, &token).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["pending_deliveries"], 3);
+    assert_eq!(body["variants"][0]["locale"], "en");
+    assert_eq!(body["variants"][0]["pending_deliveries"], 1);
+    assert_eq!(body["variants"][1]["locale"], "sw");
+    assert_eq!(body["variants"][1]["pending_deliveries"], 2);
+
+    // Act - Part 2 - After delivery, with one failure
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let response = app.get_api(&delivery_status_path, &token).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+
+    // Assert
+    assert_eq!(body["pending_deliveries"], 0);
+    let variants = body["variants"].as_array().unwrap();
+    let sent: i64 = variants.iter().map(|v| v["sent"].as_i64().unwrap()).sum();
+    let failed: i64 = variants.iter().map(|v| v["failed"].as_i64().unwrap()).sum();
+    assert_eq!((sent, failed), (2, 1));
+    for variant in variants {
+        assert_eq!(variant["pending_deliveries"], 0);
+        assert_eq!(variant["skipped"], 0);
+    }
+    assert_eq!(
+        variants[0]["sent"].as_i64().unwrap() + variants[0]["failed"].as_i64().unwrap(),
+        1
+    );
+}
@@ -0,0 +1,188 @@
+use crate::helpers::{
+    spawn_app,
+    spawn_app_with,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use wiremock::matchers::{
+    any,
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+#[tokio::test]
+async fn signups_filling_in_the_honeypot_are_silently_dropped() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&website=http%3A%2F%2Fspam.com";
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn disposable_email_domains_are_rejected() {
+    let app = spawn_app().await;
+
+    for email in ["ursula%40mailinator.com", "ursula%40eu.mailinator.com"] {
+        let body = format!("name=le%20guin&email={}", email);
+        let response = app.post_subscriptions(body).await;
+
+        assert_eq!(400, response.status().as_u16());
+        let error: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(error["error"]["field"], "email");
+    }
+}
+
+#[tokio::test]
+async fn signups_are_rate_limited_per_ip() {
+    let app = spawn_app_with(|c| c.signup.max_requests_per_ip = 2).await;
+
+    for _ in 0..2 {
+        let response = app.post_subscriptions("name=&email=".into()).await;
+        assert_eq!(400, response.status().as_u16());
+    }
+    let response = app.post_subscriptions("name=&email=".into()).await;
+
+    assert_eq!(429, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn forwarded_addresses_do_not_get_around_the_ip_rate_limit() {
+    let app = spawn_app_with(|c| c.signup.max_requests_per_ip = 2).await;
+
+    for n in 0..3 {
+        let response = app
+            .api_client
+            .post(format!("{}/subscriptions", &app.address))
+            .header("X-Forwarded-For", format!("203.0.113.{n}"))
+            .form(&serde_json::json!({ "name": "", "email": "" }))
+            .send()
+            .await
+            .unwrap();
+        let expected = if n < 2 { 400 } else { 429 };
+        assert_eq!(expected, response.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn trusted_proxies_tell_the_ip_of_their_clients() {
+    let app = spawn_app_with(|c| {
+        c.signup.max_requests_per_ip = 1;
+        c.application.trusted_proxies = vec!["127.0.0.0/8".parse().unwrap()];
+    })
+    .await;
+
+    for n in 0..2 {
+        let response = app
+            .api_client
+            .post(format!("{}/subscriptions", &app.address))
+            // The first address comes from the client, it cannot be trusted
+            .header("X-Forwarded-For", format!("198.51.100.1, 203.0.113.{n}"))
+            .form(&serde_json::json!({ "name": "", "email": "" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(400, response.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn signups_are_rate_limited_per_email_domain() {
+    let app = spawn_app_with(|c| c.signup.max_requests_per_email_domain = 1).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=ursula&email=ursula%40example.com".into())
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let response = app
+        .post_subscriptions("name=ursula&email=ursula%40EXAMPLE.com".into())
+        .await;
+    assert_eq!(429, response.status().as_u16());
+    let response = app
+        .post_subscriptions("name=ursula&email=ursula%40example.org".into())
+        .await;
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_solved_challenge_is_required_when_proof_of_work_is_enabled() {
+    let app = spawn_app_with(|c| c.signup.proof_of_work_difficulty = Some(8)).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let mut body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com"
+    });
+
+    // Part 1 - No challenge
+    let response = app.post_subscriptions_json(&body).await;
+    assert_eq!(400, response.status().as_u16());
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["field"], "challenge");
+
+    // Part 2 - Solve a challenge
+    let challenge: serde_json::Value = app
+        .api_client
+        .get(format!("{}/subscriptions/challenge", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let token = challenge["challenge"].as_str().unwrap();
+    let difficulty = challenge["difficulty"].as_u64().unwrap() as u32;
+    let solution = (0u64..)
+        .map(|n| n.to_string())
+        .find(|n| {
+            let hash = Sha256::digest(format!("{}{}", token, n).as_bytes());
+            leading_zero_bits(&hash) >= difficulty
+        })
+        .unwrap();
+    body["challenge"] = token.into();
+    body["challenge_solution"] = solution.into();
+    let response = app.post_subscriptions_json(&body).await;
+    assert_eq!(200, response.status().as_u16());
+
+    // Part 3 - Replay the solved challenge
+    let response = app.post_subscriptions_json(&body).await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut n_zeros = 0;
+    for byte in hash {
+        n_zeros += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    n_zeros
+}
@@ -0,0 +1,256 @@
+use crate::helpers::{
+    assert_is_redirect_to,
+    create_confirmed_subscribers,
+    spawn_app,
+    TestApp,
+};
+use prod_craft::subject_test::decide_due_subject_tests;
+use uuid::Uuid;
+use wiremock::matchers::{
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+/// Publishes an issue whose subject line is tested, returns its id.
+async fn publish_tested_issue(app: &TestApp, wait_minutes: i64) -> String {
+    app.test_user.login(app).await;
+    let token = app.create_api_token().await;
+    let issue = serde_json::json!({
+        "title": "Subject A",
+        "text_content": "Newsletter body as plain text",
+        "html_content": r#"<p>Read <a href="https://example.com/post?id=1&amp;ref=a">the post</a></p>"#,
+        "subject_test": {
+            "subject_lines": ["Subject B"],
+            "metric": "opens",
+            "sample_percent": 40,
+            "wait_minutes": wait_minutes,
+        },
+    });
+    let response = app
+        .post_api(
+            "/newsletter_issues",
+            &token,
+            &Uuid::new_v4().to_string(),
+            &issue,
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+/// Makes every pending test due, as if its wait was over.
+async fn end_the_wait(app: &TestApp) {
+    sqlx::query!("UPDATE subject_tests SET decide_at = now() WHERE winner IS NULL")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+struct SentEmail {
+    subject: String,
+    html_body: String,
+}
+
+async fn sent_emails(app: &TestApp) -> Vec<SentEmail> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            SentEmail {
+                subject: body["Subject"].as_str().unwrap().to_owned(),
+                html_body: body["HtmlBody"].as_str().unwrap().to_owned(),
+            }
+        })
+        .collect()
+}
+
+/// The first tracking link of the given kind in an email.
+fn tracking_link(html_body: &str, kind: &str) -> String {
+    let start = html_body
+        .find(&format!("/newsletters/{kind}/"))
+        .expect("No tracking link.");
+    let start = html_body[..start].rfind('"').unwrap() + 1;
+    let end = start + html_body[start..].find('"').unwrap();
+    html_body[start..end].to_owned()
+}
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn the_sample_gets_every_subject_line_and_the_rest_waits_for_the_winner() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 10).await;
+    mount_email_server(&app).await;
+    publish_tested_issue(&app, 1).await;
+
+    // Act - Part 1 - Send to the sample
+    app.dispatch_all_pending_emails().await;
+    let emails = sent_emails(&app).await;
+    let mut subjects: Vec<_> = emails.iter().map(|e| e.subject.as_str()).collect();
+    subjects.sort();
+    assert_eq!(
+        subjects,
+        ["Subject A", "Subject A", "Subject B", "Subject B"]
+    );
+    let held = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM issue_delivery_queue WHERE held",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(held, 6);
+
+    // Act - Part 2 - Subject B gets opened
+    let opened = emails.iter().find(|e| e.subject == "Subject B").unwrap();
+    let response = reqwest::get(tracking_link(&opened.html_body, "opened"))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+
+    // Act - Part 3 - Send the winner to everybody else
+    app.email_server.reset().await;
+    mount_email_server(&app).await;
+    end_the_wait(&app).await;
+    decide_due_subject_tests(&app.db_pool).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let emails = sent_emails(&app).await;
+    assert_eq!(emails.len(), 6);
+    assert!(emails.iter().all(|e| e.subject == "Subject B"));
+    // Only the sample is tracked.
+    assert!(emails
+        .iter()
+        .all(|e| !e.html_body.contains("/newsletters/opened/")));
+}
+
+#[tokio::test]
+async fn the_winner_is_not_picked_before_the_wait_is_over() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 5).await;
+    mount_email_server(&app).await;
+    publish_tested_issue(&app, 60).await;
+    app.dispatch_all_pending_emails().await;
+    app.email_server.reset().await;
+    mount_email_server(&app).await;
+
+    // Act
+    decide_due_subject_tests(&app.db_pool).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert!(sent_emails(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn clicks_are_tracked_and_redirected_to_the_links_of_the_issue_only() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 5).await;
+    mount_email_server(&app).await;
+    let issue_id = publish_tested_issue(&app, 60).await;
+    app.dispatch_all_pending_emails().await;
+    let email = &sent_emails(&app).await[0];
+    let click_link = reqwest::Url::parse(&tracking_link(&email.html_body, "clicked")).unwrap();
+
+    // Act - Part 1 - Follow a link of the issue
+    let response = app.api_client.get(click_link.clone()).send().await.unwrap();
+    assert_is_redirect_to(&response, "https://example.com/post?id=1&ref=a");
+
+    // Act - Part 2 - Try to be redirected elsewhere
+    let mut forged_link = click_link.clone();
+    forged_link
+        .query_pairs_mut()
+        .clear()
+        .append_pair("url", "https://evil.example.com");
+    let response = app.api_client.get(forged_link).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+
+    // Assert
+    let clicked = sqlx::query!(
+        r#"SELECT COUNT(clicked_at) AS "n!" FROM subject_test_deliveries WHERE newsletter_issue_id = $1"#,
+        Uuid::parse_str(&issue_id).unwrap()
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .n;
+    assert_eq!(clicked, 1);
+}
+
+#[tokio::test]
+async fn the_issue_page_shows_the_winner() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscribers(&app, 5).await;
+    mount_email_server(&app).await;
+    let issue_id = publish_tested_issue(&app, 1).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    end_the_wait(&app).await;
+    decide_due_subject_tests(&app.db_pool).await.unwrap();
+    let html_page = app
+        .api_client
+        .get(format!("{}/admin/newsletters/{}", &app.address, issue_id))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    // Nothing was opened: the title wins.
+    assert!(html_page.contains("<td>Subject A <b>(winner)</b></td>"));
+    assert!(html_page.contains("The winner was picked by open rate"));
+}
+
+#[tokio::test]
+async fn translated_issues_cannot_be_tested() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Subject A",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "title_sw": "Kichwa",
+            "text_content_sw": "Maudhui",
+            "html_content_sw": "<p>Maudhui</p>",
+            "subject_lines": "Subject B",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page
+        .contains("<p><i>Subject lines cannot be A/B tested on translated issues yet.</i></p>"));
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS \"n!\" FROM newsletter_issues",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
@@ -0,0 +1,202 @@
+use crate::helpers::{
+    assert_is_redirect_to,
+    spawn_app,
+    DataRequestLinks,
+    TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{
+    any,
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn create_subscriber(app: &TestApp) -> Uuid {
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("SELECT id FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn request_data(app: &TestApp) -> DataRequestLinks {
+    app.post_data_request(EMAIL)
+        .await
+        .error_for_status()
+        .unwrap();
+    let requests = app.email_server.received_requests().await.unwrap();
+    app.get_data_request_links(requests.last().unwrap())
+}
+
+async fn assert_subscriber_is_erased(app: &TestApp) {
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM subscriptions"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_subscribers, 0);
+    let n_consent_events = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM consent_events"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_consent_events, 0);
+    let suppression = sqlx::query!("SELECT email_hash, reason FROM suppressions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The erased address was not suppressed.");
+    assert_eq!(
+        suppression.email_hash,
+        prod_craft::suppression::email_hash(EMAIL)
+    );
+    assert_eq!(suppression.reason, "erased");
+}
+
+#[tokio::test]
+async fn requesting_data_for_an_unknown_address_sends_no_email() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_data_request(EMAIL).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn data_requests_send_at_most_one_email_per_cooldown() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    create_subscriber(&app).await;
+    let n_emails = || async { app.email_server.received_requests().await.unwrap().len() };
+    let n_before = n_emails().await;
+
+    for _ in 0..3 {
+        let response = app.post_data_request(EMAIL).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    assert_eq!(n_emails().await, n_before + 1);
+}
+
+#[tokio::test]
+async fn subscribers_can_download_their_data() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let subscriber_id = create_subscriber(&app).await;
+    let links = request_data(&app).await;
+
+    let response = reqwest::get(links.export).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["id"], subscriber_id.to_string());
+    assert_eq!(export["subscriber"]["email"], EMAIL);
+    assert_eq!(export["consent_events"][0]["event_type"], "subscribed");
+}
+
+#[tokio::test]
+async fn data_requests_with_an_invalid_token_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/data?data_request_token=not-a-token",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_can_erase_their_data() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    create_subscriber(&app).await;
+    let links = request_data(&app).await;
+    let token = links
+        .erasure
+        .query_pairs()
+        .find(|(k, _)| k == "data_request_token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    let html_page = reqwest::get(links.erasure)
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(&token));
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions/erase", app.address))
+        .form(&serde_json::json!({ "data_request_token": token }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_subscriber_is_erased(&app).await;
+    // The link dies with the data.
+    let response = reqwest::get(links.export).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn admins_can_export_and_erase_a_subscriber() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let subscriber_id = create_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/subscribers/{}/export",
+            app.address, subscriber_id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], EMAIL);
+
+    let response = app.post_erase_subscriber(&subscriber_id.to_string()).await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers_html().await;
+    assert!(html_page.contains("<p><i>The subscriber's data has been erased.</i></p>"));
+    assert_subscriber_is_erased(&app).await;
+}
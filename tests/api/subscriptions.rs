@@ -1,4 +1,8 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{
+    spawn_app,
+    spawn_app_with,
+    MARKETING_SITE_ORIGIN,
+};
 use wiremock::matchers::{
     method,
     path,
@@ -104,7 +108,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
     app.post_subscriptions(body.into()).await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
@@ -135,8 +139,8 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
         links[0].as_str().to_owned()
     };
 
-    let html_link = get_link(&body["HtmlBody"].as_str().unwrap());
-    let text_link = get_link(&body["TextBody"].as_str().unwrap());
+    let html_link = get_link(body["HtmlBody"].as_str().unwrap());
+    let text_link = get_link(body["TextBody"].as_str().unwrap());
 
     assert_eq!(html_link, text_link);
 }
@@ -155,3 +159,370 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
 
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribe_accepts_json() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email, name FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+}
+
+#[tokio::test]
+async fn subscribe_returns_structured_validation_errors() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"name": "", "email": "ursula_le_guin@gmail.com"}),
+            "name",
+        ),
+        (
+            serde_json::json!({"name": "Ursula", "email": "definitely-not-an-email"}),
+            "email",
+        ),
+    ];
+
+    for (body, field) in test_cases {
+        let response = app.post_subscriptions_json(&body).await;
+
+        assert_eq!(400, response.status().as_u16());
+        let error: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(error["error"]["code"], "validation_error");
+        assert_eq!(
+            error["error"]["field"], field,
+            "The API did not flag the {} field as invalid.",
+            field
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_allows_cross_origin_requests_from_configured_origins() {
+    let app = spawn_app().await;
+
+    let preflight = |origin: &'static str| {
+        app.api_client
+            .request(
+                reqwest::Method::OPTIONS,
+                format!("{}/subscriptions", &app.address),
+            )
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "POST")
+            .header(
+                "Access-Control-Request-Headers",
+                "content-type, idempotency-key",
+            )
+            .send()
+    };
+
+    let response = preflight(MARKETING_SITE_ORIGIN).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        MARKETING_SITE_ORIGIN,
+        response.headers()["Access-Control-Allow-Origin"]
+    );
+
+    let response = preflight("https://evil.example.com").await.unwrap();
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+}
+
+#[tokio::test]
+async fn subscribe_normalizes_email_addresses() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=%20Ursula_Le_Guin%40GMail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribe_suggests_a_fix_for_common_domain_typos() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmial.com"
+        }))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["field"], "email");
+    assert_eq!(error["error"]["suggestion"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribers_can_keep_an_email_that_looks_like_a_typo() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmial.com",
+            "keep_email_as_typed": true
+        }))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmial.com");
+}
+
+#[tokio::test]
+async fn subscribe_keeps_the_case_of_mailbox_names_unless_configured_otherwise() {
+    let app = spawn_app_with(|c| c.signup.lowercase_email_local_parts = false).await;
+    let body = "name=le%20guin&email=Ursula_Le_Guin%40GMail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "Ursula_Le_Guin@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribe_rejects_domains_without_mail_servers_when_mx_checks_are_enabled() {
+    let app = spawn_app_with(|c| c.signup.check_mx_records = true).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@nowhere.invalid"
+        }))
+        .await;
+    assert_eq!(400, response.status().as_u16());
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["field"], "email");
+
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_strips_invisible_characters_from_names() {
+    let app = spawn_app().await;
+    // A right-to-left override and a zero width space around the name.
+    let body = "name=%E2%80%AEle%20guin%E2%80%8B&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT name FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.name, "le guin");
+}
+
+#[tokio::test]
+async fn subscribe_enforces_the_configured_maximum_name_length() {
+    let app = spawn_app_with(|c| c.signup.max_name_length = 5).await;
+
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["field"], "name");
+    assert_eq!(error["error"]["reason"], "too_long");
+}
+
+#[tokio::test]
+async fn signing_up_twice_resends_the_confirmation_email() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(200, response.status().as_u16());
+    let body = "name=le%20guin&email=Ursula_Le_Guin%40gmail.com";
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let n_subscribers = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, 1);
+    // Both confirmation links work
+    for email_request in &app.email_server.received_requests().await.unwrap() {
+        let confirmation_links = app.get_confirmation_links(email_request);
+        let response = reqwest::get(confirmation_links.html).await.unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn signing_up_again_once_confirmed_sends_no_email() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    // Mock verifies on Drop that we have sent a single confirmation email
+}
+
+#[tokio::test]
+async fn double_submissions_sign_up_once() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&idempotency_key=signup-1";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(200)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let (response1, response2) = tokio::join!(
+        app.post_subscriptions(body.into()),
+        app.post_subscriptions(body.into())
+    );
+
+    assert_eq!(200, response1.status().as_u16());
+    assert_eq!(200, response2.status().as_u16());
+    let n_subscribers = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "n!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, 1);
+    // Mock verifies on Drop that we have sent a single confirmation email
+}
+
+#[tokio::test]
+async fn retried_json_signups_are_replayed() {
+    let app = spawn_app().await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com"
+    });
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let post = || {
+        app.api_client
+            .post(format!("{}/subscriptions", &app.address))
+            .header("Idempotency-Key", "signup-1")
+            .json(&body)
+            .send()
+    };
+
+    let response = post().await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let response = post().await.unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.headers()["Idempotent-Replayed"], "true");
+}
@@ -0,0 +1,183 @@
+use crate::helpers::{
+    assert_is_redirect_to,
+    spawn_app,
+    TestApp,
+};
+use prod_craft::suppression::email_hash;
+use wiremock::matchers::{
+    any,
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn suppress(app: &TestApp, email: &str) {
+    let response = app
+        .post_suppression(&serde_json::json!({ "email": email, "reason": "bounced" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+}
+
+async fn publish_newsletter(app: &TestApp) {
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_suppressions() {
+    let app = spawn_app().await;
+
+    let response = app.get_suppressions().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_suppression(&serde_json::json!({ "email": EMAIL, "reason": "manual" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn suppressed_addresses_cannot_sign_up_again() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    suppress(&app, EMAIL).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40gmail.com".into())
+        .await;
+
+    // Same answer as a successful signup, but nothing happened.
+    assert_eq!(response.status().as_u16(), 200);
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM subscriptions"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_addresses() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    suppress(&app, "URSULA_LE_GUIN@gmail.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM issue_delivery_queue"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_queued, 0);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn the_worker_skips_addresses_suppressed_after_publishing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    suppress(&app, EMAIL).await;
+    app.dispatch_all_pending_emails().await;
+
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) as "n!" FROM issue_delivery_queue"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn suppressions_can_be_imported_in_bulk_and_removed() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_import_suppressions(&serde_json::json!({
+            "emails": "ursula_le_guin@gmail.com\nnot-an-email\n\nle.guin@example.com\n",
+            "reason": "complained"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page
+        .contains("<p><i>2 new email address(es) suppressed, 1 invalid line(s) skipped.</i></p>"));
+    assert!(html_page.contains(&email_hash(EMAIL)));
+    assert!(html_page.contains(&email_hash("le.guin@example.com")));
+
+    let response = app.post_remove_suppression(&email_hash(EMAIL)).await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("<p><i>The suppression has been removed.</i></p>"));
+    assert!(!html_page.contains(&email_hash(EMAIL)));
+    assert!(html_page.contains(&email_hash("le.guin@example.com")));
+}
+
+#[tokio::test]
+async fn unknown_suppression_reasons_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_suppression(&serde_json::json!({ "email": EMAIL, "reason": "because" }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("<p><i>Unknown suppression reason.</i></p>"));
+    assert!(!html_page.contains(&email_hash(EMAIL)));
+}
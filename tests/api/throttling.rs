@@ -0,0 +1,128 @@
+use crate::helpers::{
+    assert_is_redirect_to,
+    create_confirmed_subscribers,
+    spawn_app_with,
+    TestApp,
+};
+use prod_craft::issue_delivery_worker::{
+    try_execute_task,
+    ExecutionOutcome,
+};
+use prod_craft::throttle::DomainLimits;
+use std::time::{
+    Duration,
+    Instant,
+};
+use uuid::Uuid;
+use wiremock::matchers::{
+    method,
+    path,
+};
+use wiremock::{
+    Mock,
+    ResponseTemplate,
+};
+
+async fn spawn_app_with_one_send_per_second_per_domain() -> TestApp {
+    spawn_app_with(|c| {
+        c.delivery.per_domain = DomainLimits {
+            max_sends_per_minute: Some(60.0),
+            max_concurrent_sends: Some(1),
+        };
+    })
+    .await
+}
+
+async fn publish_newsletter(app: &TestApp) {
+    app.test_user.login(app).await;
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+async fn try_execute_task_once(app: &TestApp) -> ExecutionOutcome {
+    try_execute_task(
+        &app.db_pool,
+        &app.email_client,
+        &app.attachment_store,
+        &app.throttle,
+        &app.address,
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn sends_to_the_same_domain_are_spaced_out() {
+    // Arrange
+    let app = spawn_app_with_one_send_per_second_per_domain().await;
+    create_confirmed_subscribers(&app, 2).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+
+    // Act
+    let start = Instant::now();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert!(start.elapsed() >= Duration::from_millis(900));
+}
+
+#[tokio::test]
+async fn a_throttled_domain_does_not_hold_up_the_others() {
+    // Arrange
+    let app = spawn_app_with_one_send_per_second_per_domain().await;
+    create_confirmed_subscribers(&app, 2).await;
+    sqlx::query!(
+        r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status, locale)
+        VALUES ($1, 'someone@example.org', 'Someone', now(), 'confirmed', 'en')"#,
+        Uuid::new_v4(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+
+    // Act - Part 1 - One delivery per domain goes out right away
+    for _ in 0..2 {
+        assert!(matches!(
+            try_execute_task_once(&app).await,
+            ExecutionOutcome::TaskCompleted
+        ));
+    }
+    let outcome = try_execute_task_once(&app).await;
+
+    // Assert - Part 1
+    let ExecutionOutcome::Throttled(wait) = outcome else {
+        panic!("The last delivery should have been throttled.");
+    };
+    assert!(wait <= Duration::from_secs(1));
+
+    // Act - Part 2 - The last one goes out once the domain allows it
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2
+    let n_pending = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_pending, 0);
+}